@@ -0,0 +1,356 @@
+//! Script demos & debug servers without a tablet
+//!
+use std::{
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use anyhow::Result;
+use clap::{Parser, Subcommand};
+use log::info;
+use marauder::{
+    bots::{self, Bot, Room},
+    client::{add_admin_token, add_xuser, channel_with_ca, AdminToken, Session},
+    fonts,
+    shapes::wipe,
+};
+use pb::proto::hypercards::{
+    admin_client::AdminClient, drawing::Color, event, screen_sharing_client::ScreenSharingClient,
+    whiteboard_client::WhiteboardClient, BanReq, ClearRoomReq, DeleteRoomReq, Drawing, KickReq,
+    ListRoomMembersReq, ListRoomsReq, LockRoomReq, RecvScreenReq, RenameRoomReq,
+};
+use tokio_stream::StreamExt;
+use tonic::{transport::Channel, Request};
+use uuid::Uuid;
+
+#[derive(Parser, Debug)]
+#[clap(name = "hc", about = "HyperCard rooms from the command line")]
+struct Args {
+    /// Host to connect to
+    #[arg(long, env = "WHITEBOARD_HOST", default_value = "http://fknwkdacd.com:10000")]
+    host: String,
+
+    /// PEM to authenticate https:// hosts with: a CA bundle or the host's self-signed certificate
+    #[arg(long, env = "WHITEBOARD_CA")]
+    ca: Option<PathBuf>,
+
+    /// ID to identify as (random by default)
+    #[arg(long, env = "HC_USER")]
+    user: Option<String>,
+
+    #[command(subcommand)]
+    cmd: Cmd,
+}
+
+#[derive(Subcommand, Debug)]
+enum Cmd {
+    /// List rooms along with their amount of users
+    Rooms,
+
+    /// Count a room's users
+    Members {
+        /// Room to look into
+        #[arg(long, env = "WHITEBOARD_ROOM", default_value = "living-room")]
+        room: String,
+    },
+
+    /// Print a room's events as JSON lines, as they happen
+    Tail {
+        /// Room to listen on
+        #[arg(long, env = "WHITEBOARD_ROOM", default_value = "living-room")]
+        room: String,
+    },
+
+    /// Send the drawings of .svg, .jsonl or .ndjson files (as read by `scrolls`)
+    Post {
+        /// Room to draw in
+        #[arg(long, env = "WHITEBOARD_ROOM", default_value = "living-room")]
+        room: String,
+
+        /// Files to read, their extension selects the format
+        #[arg(required = true)]
+        files: Vec<String>,
+    },
+
+    /// Handwrite some text
+    Say {
+        /// Room to write in
+        #[arg(long, env = "WHITEBOARD_ROOM", default_value = "living-room")]
+        room: String,
+
+        /// Left of the text, in pixels
+        #[arg(long, default_value_t = 100.)]
+        x: f32,
+
+        /// Top of the text, in pixels
+        #[arg(long, default_value_t = 200.)]
+        y: f32,
+
+        /// Height of the text, in pixels
+        #[arg(long, default_value_t = 80.)]
+        size: f32,
+
+        /// What to write
+        #[arg(required = true)]
+        text: Vec<String>,
+    },
+
+    /// Erase everything in a room's canvas
+    Clear {
+        /// Room to wipe
+        #[arg(long, env = "WHITEBOARD_ROOM", default_value = "living-room")]
+        room: String,
+    },
+
+    /// Run a bot that welcomes users joining a room
+    Greet {
+        /// Room to watch
+        #[arg(long, env = "WHITEBOARD_ROOM", default_value = "living-room")]
+        room: String,
+    },
+
+    /// Download the latest screen shared in a room, as PNG
+    Screen {
+        /// Room to look into
+        #[arg(long, env = "WHITEBOARD_ROOM", default_value = "living-room")]
+        room: String,
+
+        /// File to write
+        output: PathBuf,
+    },
+
+    /// Moderate rooms & users, with the server's admin token
+    Admin {
+        /// Token the server was started with (its --admin-token)
+        #[arg(long, env = "HC_ADMIN_TOKEN", hide_env_values = true)]
+        token: AdminToken,
+
+        #[command(subcommand)]
+        cmd: AdminCmd,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum AdminCmd {
+    /// Close a user's streams, showing them a reason
+    Kick {
+        /// Room to kick from (every room by default)
+        #[arg(long)]
+        room: Option<String>,
+
+        /// User to kick
+        #[arg(long)]
+        user: String,
+
+        /// Shown to the user
+        #[arg(long, default_value = "")]
+        reason: String,
+    },
+
+    /// Kick a user out of every room and turn their calls down
+    Ban {
+        /// User to ban
+        #[arg(long)]
+        user: String,
+
+        /// Shown to the user
+        #[arg(long, default_value = "")]
+        reason: String,
+
+        /// Unban instead
+        #[arg(long)]
+        lift: bool,
+    },
+
+    /// Forget a room's drawings & screenshot, erasing its members' canvases
+    Clear {
+        /// Room to clear
+        #[arg(long)]
+        room: String,
+    },
+
+    /// Make a room read-only
+    Lock {
+        /// Room to lock
+        #[arg(long)]
+        room: String,
+
+        /// Unlock instead
+        #[arg(long)]
+        unlock: bool,
+    },
+
+    /// Move a room's history & screenshot to a new name, kicking its members
+    Rename {
+        /// Room to rename
+        #[arg(long)]
+        room: String,
+
+        /// Its new name
+        #[arg(long)]
+        to: String,
+    },
+
+    /// Kick a room's members then forget everything about it
+    Delete {
+        /// Room to delete
+        #[arg(long)]
+        room: String,
+
+        /// Shown to the room's members
+        #[arg(long, default_value = "")]
+        reason: String,
+    },
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    env_logger::init();
+
+    let args = Args::parse();
+    info!("args = {args:?}");
+    let Args { host, ca, user, cmd } = args;
+    let user_id = user.unwrap_or_else(|| Uuid::new_v4().hyphenated().to_string());
+    let ch = channel_with_ca(&host, ca.as_deref())?;
+    let session = |room: &str| Session::with_channel(ch.clone(), room, &user_id);
+
+    match cmd {
+        Cmd::Rooms => rooms(&session("")).await,
+        Cmd::Members { room } => members(&session(&room)).await,
+        Cmd::Tail { room } => tail(&session(&room)).await,
+        Cmd::Post { room, files } => {
+            let mut ds = vec![];
+            for fpath in files {
+                ds.extend(scrolls::read(&fpath)?);
+            }
+            send_all(&session(&room), ds).await
+        }
+        Cmd::Say { room, x, y, size, text } => {
+            let font = fonts::emsdelight_swash_caps()?;
+            let ds = fonts::write(&font, &text.join(" "), (x, y), size, Color::Black);
+            send_all(&session(&room), ds).await
+        }
+        Cmd::Clear { room } => send_all(&session(&room), wipe::whole_display()).await,
+        Cmd::Greet { room } => {
+            bots::run(&mut Greeter, session(&room)).await;
+            Ok(())
+        }
+        Cmd::Screen { room, output } => screen(&session(&room), output).await,
+        Cmd::Admin { token, cmd } => admin(AdminClient::new(ch), &token, cmd).await,
+    }
+}
+
+async fn admin(mut client: AdminClient<Channel>, token: &AdminToken, cmd: AdminCmd) -> Result<()> {
+    fn req<T>(token: &AdminToken, msg: T) -> Result<Request<T>> {
+        let mut req = Request::new(msg);
+        add_admin_token(&mut req, token)?;
+        Ok(req)
+    }
+
+    match cmd {
+        AdminCmd::Kick { room, user, reason } => {
+            let room_id = room.unwrap_or_default();
+            client.kick(req(token, KickReq { room_id, user_id: user, reason })?).await?;
+        }
+        AdminCmd::Ban { user, reason, lift } => {
+            client.ban(req(token, BanReq { user_id: user, reason, lift })?).await?;
+        }
+        AdminCmd::Clear { room } => {
+            client.clear_room(req(token, ClearRoomReq { room_id: room })?).await?;
+        }
+        AdminCmd::Lock { room, unlock } => {
+            client.lock_room(req(token, LockRoomReq { room_id: room, unlock })?).await?;
+        }
+        AdminCmd::Rename { room, to } => {
+            let rename = RenameRoomReq { room_id: room, new_room_id: to };
+            client.rename_room(req(token, rename)?).await?;
+        }
+        AdminCmd::Delete { room, reason } => {
+            client.delete_room(req(token, DeleteRoomReq { room_id: room, reason })?).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn rooms(session: &Session) -> Result<()> {
+    let mut req = Request::new(ListRoomsReq {});
+    add_xuser(&mut req, session.user_id())?;
+    let rep = WhiteboardClient::new(session.channel()).list_rooms(req).await?.into_inner();
+    for e in rep.events {
+        if let Some(event::Event::UsersInTheRoom(count)) = e.event {
+            println!("{}\t{count}", e.in_room_id);
+        }
+    }
+    Ok(())
+}
+
+async fn members(session: &Session) -> Result<()> {
+    let mut req = Request::new(ListRoomMembersReq { room_id: session.room_id().to_owned() });
+    add_xuser(&mut req, session.user_id())?;
+    let rep = WhiteboardClient::new(session.channel()).list_room_members(req).await?.into_inner();
+    println!("{}", rep.members.len());
+    Ok(())
+}
+
+async fn tail(session: &Session) -> Result<()> {
+    let mut events = Box::pin(session.events());
+    let mut stdout = io::stdout().lock();
+    while let Some(e) = events.next().await {
+        writeln!(stdout, "{}", serde_json::to_string(&e)?)?;
+        stdout.flush()?;
+    }
+    match session.rejection() {
+        Some(rejection) => Err(rejection.into()),
+        None => Ok(()),
+    }
+}
+
+async fn send_all(session: &Session, ds: Vec<Drawing>) -> Result<()> {
+    let n = ds.len();
+    for d in ds {
+        session.send_drawing(d).await?;
+    }
+    info!("[send_all] sent {n} drawings to {:?}", session.room_id());
+    Ok(())
+}
+
+struct Greeter;
+
+#[tonic::async_trait]
+impl Bot for Greeter {
+    async fn on_join(&mut self, room: &Room, _user_id: &str) -> Result<()> {
+        let people = room.session().people();
+        room.write(&format!("Welcome! {people} here"), (100., 200.), 80.).await
+    }
+}
+
+async fn screen(session: &Session, output: PathBuf) -> Result<()> {
+    let mut req = Request::new(RecvScreenReq { room_id: session.room_id().to_owned() });
+    add_xuser(&mut req, session.user_id())?;
+    let rep = ScreenSharingClient::new(session.channel()).recv_screen(req).await?.into_inner();
+    info!("[screen] got {} bytes", rep.canvas_png.len());
+    fs::write(&output, rep.canvas_png)?;
+    Ok(())
+}
+
+#[test]
+fn prints_events_as_proto_named_json() {
+    use pb::proto::hypercards::Event;
+
+    let e = Event {
+        created_at: 42,
+        by_user_id: "joe".to_owned(),
+        in_room_id: "r".to_owned(),
+        event: Some(event::Event::UsersInTheRoom(3)),
+        ..Default::default()
+    };
+    assert_eq!(
+        serde_json::to_string(&e).unwrap(),
+        r#"{"created_at":42,"by_user_id":"joe","in_room_id":"r","origin_server_id":"","via_server_ids":[],"users_in_the_room":3}"#
+    );
+
+    let d = Drawing { xs: vec![1.], ys: vec![2.], pressures: vec![3], widths: vec![4], color: 1 };
+    let e = Event { event: Some(event::Event::Drawing(d)), ..e };
+    assert_eq!(serde_json::to_value(&e).unwrap()["drawing"]["color"], "BLACK");
+}
@@ -0,0 +1,57 @@
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use libremarkable::appctx::ApplicationContext;
+use log::info;
+use marauder::hypercard::{self, Host};
+
+#[derive(Parser, Debug)]
+#[clap(name = "launcher", about = "Lists HyperCards, runs them & switches between them")]
+struct Args {
+    /// Card to start with, rather than the list of cards
+    #[arg(long, env = "HYPERCARDS_CARD")]
+    card: Option<String>,
+
+    /// Print the installed cards then exit
+    #[arg(long)]
+    list: bool,
+
+    /// Record pen, touch & button events to this file (JSON Lines)
+    #[arg(long, env = "HYPERCARDS_RECORD")]
+    record: Option<PathBuf>,
+
+    /// Replay events recorded with --record, alongside live ones
+    #[arg(long, env = "HYPERCARDS_REPLAY")]
+    replay: Option<PathBuf>,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    env_logger::init();
+    let args = Args::parse();
+    info!("args = {args:?}");
+
+    let mut host = Host::new(hypercard::installed());
+    if args.list {
+        for name in host.names() {
+            println!("{name}");
+        }
+        return Ok(());
+    }
+    let start = match args.card {
+        None => None,
+        Some(ref name) => {
+            Some(host.position(name).ok_or_else(|| anyhow!("no such card {name:?}"))?)
+        }
+    };
+
+    let mut app: ApplicationContext<'_> = ApplicationContext::default();
+    app.clear(true);
+    match start {
+        None => host.menu(app.get_framebuffer_ref()),
+        Some(i) => host.start(app.get_framebuffer_ref(), i),
+    }
+
+    hypercard::launch(&mut app, host, args.record.as_deref(), args.replay.as_deref())
+}
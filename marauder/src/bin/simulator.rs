@@ -0,0 +1,214 @@
+use std::{
+    path::{Path, PathBuf},
+    thread,
+    time::Instant,
+};
+
+use anyhow::{anyhow, bail, Result};
+use clap::Parser;
+use log::info;
+use marauder::{
+    hypercard::{self, Flow, Host},
+    recording,
+    simulator::Display,
+};
+
+#[derive(Parser, Debug)]
+#[clap(name = "simulator", about = "A reMarkable on the desktop, running HyperCards")]
+struct Args {
+    /// Play a session recorded with --record (by this or the apps on a tablet)
+    #[arg(long)]
+    replay: Option<PathBuf>,
+
+    /// Write what the display shows as a PNG sequence into this directory
+    #[arg(long)]
+    frames: Option<PathBuf>,
+
+    /// Time between frames, in milliseconds (recorded time when replaying)
+    #[arg(long, default_value_t = 40)]
+    frame_every: u64,
+
+    /// Record the session's mouse & keyboard input, as tablet events
+    #[arg(long)]
+    record: Option<PathBuf>,
+
+    /// Size of the window, relative to the tablet's display
+    #[cfg(feature = "window")]
+    #[arg(long, default_value_t = 0.5)]
+    scale: f32,
+
+    /// Card to start with, rather than the list of cards
+    #[arg(long)]
+    card: Option<String>,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    env_logger::init();
+    let args = Args::parse();
+    info!("args = {args:?}");
+
+    let mut display = Display::default();
+    let mut host = Host::new(hypercard::installed());
+    match args.card {
+        None => host.menu(&mut display),
+        Some(ref name) => {
+            let i = host.position(name).ok_or_else(|| anyhow!("no such card {name:?}"))?;
+            host.start(&mut display, i);
+        }
+    }
+
+    if let Some(ref path) = args.replay {
+        return replay(&args, path, &mut display, &mut host);
+    }
+
+    #[cfg(feature = "window")]
+    return window::run(&args, &mut display, &mut host);
+
+    #[cfg(not(feature = "window"))]
+    bail!("nothing to replay and no window to draw in: build with --features=window")
+}
+
+/// Plays a session as fast as cards refresh, writing frames as they would have shown.
+fn replay(args: &Args, path: &Path, display: &mut Display, host: &mut Host) -> Result<()> {
+    let entries = recording::read(path)?;
+    info!("[replay] {} events from {path:?}", entries.len());
+    let Some(ref dir) = args.frames else { bail!("replaying without --frames shows nothing") };
+
+    let mut ticked = Instant::now();
+    let mut next_frame = 0;
+    for entry in &entries {
+        if entry.at >= next_frame {
+            tick(display, host, &mut ticked);
+            if display.take_changed() {
+                display.save_frame(dir)?;
+                next_frame = entry.at + args.frame_every;
+            }
+        }
+        if host.on_input(display, entry.event.into()) == Flow::Exit {
+            break;
+        }
+    }
+    tick(display, host, &mut ticked);
+    if display.take_changed() {
+        let last = display.save_frame(dir)?;
+        info!("[replay] last frame: {last:?}");
+    }
+    Ok(())
+}
+
+/// Ticks the card running, once it's been long enough: cards refresh at their own pace.
+fn tick(display: &mut Display, host: &mut Host, ticked: &mut Instant) {
+    thread::sleep(hypercard::TICK_EVERY.saturating_sub(ticked.elapsed()));
+    host.tick(display);
+    *ticked = Instant::now();
+}
+
+#[cfg(feature = "window")]
+mod window {
+    use std::time::{Duration, Instant};
+
+    use libremarkable::{cgmath::Point2, image::imageops, input::InputEvent};
+    use marauder::{
+        recording::Recorder,
+        simulator::{self, Pointer, Tool},
+    };
+    use minifb::{Key, KeyRepeat, MouseButton, MouseMode, Window, WindowOptions};
+
+    use super::*;
+
+    /// Records events as they come, if asked to.
+    struct Session {
+        recorder: Option<Recorder>,
+        frames: Option<PathBuf>,
+        frame_every: Duration,
+        last_frame: Option<Instant>,
+    }
+
+    impl Session {
+        fn new(args: &Args) -> Result<Self> {
+            Ok(Self {
+                recorder: args.record.as_deref().map(Recorder::create).transpose()?,
+                frames: args.frames.clone(),
+                frame_every: Duration::from_millis(args.frame_every),
+                last_frame: None,
+            })
+        }
+
+        fn handle(
+            &mut self,
+            display: &mut Display,
+            host: &mut Host,
+            evt: InputEvent,
+        ) -> Result<Flow> {
+            if let Some(ref mut recorder) = self.recorder {
+                recorder.record(&evt)?;
+            }
+            Ok(host.on_input(display, evt))
+        }
+
+        /// Saves a frame, unless the last one is too recent.
+        fn frame(&mut self, display: &mut Display) -> Result<()> {
+            let Some(ref dir) = self.frames else { return Ok(()) };
+            if self.last_frame.is_some_and(|last| last.elapsed() < self.frame_every) {
+                return Ok(());
+            }
+            display.save_frame(dir)?;
+            self.last_frame = Some(Instant::now());
+            Ok(())
+        }
+    }
+
+    /// Left click draws, right click erases and middle click touches.
+    /// Keys L, M, R & P press the tablet's buttons; Escape, or P for POWER, quits.
+    pub(super) fn run(args: &Args, display: &mut Display, host: &mut Host) -> Result<()> {
+        let mut session = Session::new(args)?;
+        let (w, h) = display.shown().dimensions();
+        let (ww, wh) = ((w as f32 * args.scale) as u32, (h as f32 * args.scale) as u32);
+        let mut window =
+            Window::new("reMarkable", ww as usize, wh as usize, WindowOptions::default())?;
+        window.set_target_fps(60);
+
+        let mut pointer = Pointer::default();
+        let mut buffer = vec![];
+        while window.is_open() && !window.is_key_down(Key::Escape) {
+            let mut events = vec![];
+            if let Some((x, y)) = window.get_mouse_pos(MouseMode::Discard) {
+                let at = Point2 { x: x / args.scale, y: y / args.scale };
+                let down = [
+                    (MouseButton::Left, Tool::Pen),
+                    (MouseButton::Right, Tool::Rubber),
+                    (MouseButton::Middle, Tool::Finger),
+                ]
+                .into_iter()
+                .find_map(|(button, tool)| window.get_mouse_down(button).then_some(tool));
+                events.extend(pointer.update(down, at));
+            }
+            for key in window.get_keys_pressed(KeyRepeat::No) {
+                let c = match key {
+                    Key::L => 'l',
+                    Key::M => 'm',
+                    Key::R => 'r',
+                    Key::P => 'p',
+                    _ => continue,
+                };
+                events.extend(simulator::key(c).into_iter().flatten());
+            }
+            for evt in events {
+                if session.handle(display, host, evt)? == Flow::Exit {
+                    return Ok(());
+                }
+            }
+            host.tick(display);
+
+            if display.take_changed() {
+                session.frame(display)?;
+                let shown =
+                    imageops::resize(display.shown(), ww, wh, imageops::FilterType::Triangle);
+                buffer = shown.pixels().map(|p| u32::from(p.0[0]) * 0x01_01_01).collect();
+            }
+            window.update_with_buffer(&buffer, ww as usize, wh as usize)?;
+        }
+        Ok(())
+    }
+}
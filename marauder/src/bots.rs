@@ -0,0 +1,256 @@
+//! Automated room participants
+//!
+//! A [`Bot`] reacts to what happens in a room, through a [`Room`] that paints
+//! drawings, `drawings::*` assets or text. Rooms carry no text of their own:
+//! bots that [`Bot::read`] handwriting get their `on_text` called whenever a
+//! user pauses after a few strokes.
+//!
+use std::{
+    collections::HashMap,
+    sync::LazyLock,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use log::{debug, info, warn};
+use pb::proto::hypercards::{drawing::Color, event, Drawing, Event};
+use tokio::time::sleep_until;
+use tokio_stream::{Stream, StreamExt};
+
+use crate::{
+    client::Session,
+    fonts::{self, Font},
+};
+
+/// How long after a user's last stroke their handwriting gets read.
+pub const TEXT_PAUSE: Duration = Duration::from_millis(1500);
+
+static FONT: LazyLock<Font> = LazyLock::new(|| fonts::emsdelight_swash_caps().unwrap());
+
+/// Hooks default to doing nothing. Errors are logged and do not stop the bot.
+#[tonic::async_trait]
+pub trait Bot: Send {
+    async fn on_join(&mut self, _room: &Room, _user_id: &str) -> Result<()> {
+        Ok(())
+    }
+
+    async fn on_leave(&mut self, _room: &Room, _user_id: &str) -> Result<()> {
+        Ok(())
+    }
+
+    async fn on_drawing(&mut self, _room: &Room, _user_id: &str, _drawing: &Drawing) -> Result<()> {
+        Ok(())
+    }
+
+    async fn on_text(&mut self, _room: &Room, _user_id: &str, _text: &str) -> Result<()> {
+        Ok(())
+    }
+
+    /// Recognizes the handwriting of one user's consecutive strokes.
+    fn read(&mut self, _strokes: &[Drawing]) -> Option<String> {
+        None
+    }
+}
+
+/// What bots act on: their room.
+pub struct Room {
+    session: Session,
+}
+
+impl Room {
+    #[must_use]
+    pub fn new(session: Session) -> Self {
+        Self { session }
+    }
+
+    #[must_use]
+    pub fn session(&self) -> &Session {
+        &self.session
+    }
+
+    pub async fn draw(&self, drawing: Drawing) -> Result<()> {
+        self.session.send_drawing(drawing).await
+    }
+
+    pub async fn draw_all(&self, ds: Vec<Drawing>) -> Result<()> {
+        for d in ds {
+            self.draw(d).await?;
+        }
+        Ok(())
+    }
+
+    /// Paints one of the `drawings` crate's assets, e.g. `drawings::top_left_x3::f`, moved by `(dx, dy)`.
+    pub async fn draw_asset(
+        &self,
+        asset: fn(Color) -> Vec<Drawing>,
+        (dx, dy): (f32, f32),
+        color: Color,
+    ) -> Result<()> {
+        let ds = asset(color)
+            .into_iter()
+            .map(|d| Drawing {
+                xs: d.xs.into_iter().map(|x| x + dx).collect(),
+                ys: d.ys.into_iter().map(|y| y + dy).collect(),
+                ..d
+            })
+            .collect();
+        self.draw_all(ds).await
+    }
+
+    /// Handwrites `text` on one line, see [`fonts::write`].
+    pub async fn write(&self, text: &str, origin: (f32, f32), size: f32) -> Result<()> {
+        self.draw_all(fonts::write(&FONT, text, origin, size, Color::Black)).await
+    }
+}
+
+/// Joins the session's room then calls the bot's hooks, forever.
+pub async fn run<B: Bot>(bot: &mut B, session: Session) {
+    let events = session.events();
+    let room = Room::new(session);
+    info!("[run] bot joining {:?} as {:?}", room.session.room_id(), room.session.user_id());
+    dispatch(bot, &room, events).await;
+}
+
+/// Strokes awaiting to be read, by user.
+type Pending = HashMap<String, (Vec<Drawing>, Instant)>;
+
+async fn dispatch<B: Bot>(bot: &mut B, room: &Room, events: impl Stream<Item = Event>) {
+    let mut events = Box::pin(events);
+    let mut pending = Pending::new();
+    loop {
+        let deadline = pending.values().map(|(_, last)| *last + TEXT_PAUSE).min();
+        let event = match deadline {
+            None => events.next().await,
+            Some(deadline) => tokio::select! {
+                event = events.next() => event,
+                () = sleep_until(deadline.into()) => {
+                    let now = Instant::now();
+                    let due: Vec<_> = pending
+                        .iter()
+                        .filter(|(_, (_, last))| *last + TEXT_PAUSE <= now)
+                        .map(|(user_id, _)| user_id.clone())
+                        .collect();
+                    for user_id in due {
+                        read(bot, room, &mut pending, &user_id).await;
+                    }
+                    continue;
+                }
+            },
+        };
+        let Some(event) = event else { break };
+
+        let Event { by_user_id: user_id, event, .. } = event;
+        let res = match event {
+            Some(event::Event::UserJoinedTheRoom(_)) => bot.on_join(room, &user_id).await,
+            Some(event::Event::UserLeftTheRoom(_)) => {
+                read(bot, room, &mut pending, &user_id).await;
+                bot.on_leave(room, &user_id).await
+            }
+            Some(event::Event::Drawing(drawing)) => {
+                let res = bot.on_drawing(room, &user_id, &drawing).await;
+                let now = Instant::now();
+                let (strokes, last) = pending.entry(user_id.clone()).or_insert((vec![], now));
+                strokes.push(drawing);
+                *last = now;
+                res
+            }
+            Some(event::Event::UsersInTheRoom(c)) => {
+                debug!("[dispatch] {c} users in the room");
+                Ok(())
+            }
+            None => Ok(()),
+        };
+        if let Err(e) = res {
+            warn!("[dispatch] bot failed handling {user_id:?}'s event: {e}");
+        }
+    }
+
+    let users: Vec<_> = pending.keys().cloned().collect();
+    for user_id in users {
+        read(bot, room, &mut pending, &user_id).await;
+    }
+}
+
+async fn read<B: Bot>(bot: &mut B, room: &Room, pending: &mut Pending, user_id: &str) {
+    let Some((strokes, _)) = pending.remove(user_id) else { return };
+    let Some(text) = bot.read(&strokes) else { return };
+    debug!("[read] {user_id:?} wrote {text:?}");
+    if let Err(e) = bot.on_text(room, user_id, &text).await {
+        warn!("[read] bot failed handling {user_id:?}'s text: {e}");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Reads every stroke as an "x", writes down everything it witnesses.
+    #[derive(Default)]
+    struct Scribe {
+        seen: Vec<String>,
+    }
+
+    #[tonic::async_trait]
+    impl Bot for Scribe {
+        async fn on_join(&mut self, _: &Room, user_id: &str) -> Result<()> {
+            self.seen.push(format!("+{user_id}"));
+            Ok(())
+        }
+
+        async fn on_leave(&mut self, _: &Room, user_id: &str) -> Result<()> {
+            self.seen.push(format!("-{user_id}"));
+            Ok(())
+        }
+
+        async fn on_drawing(&mut self, _: &Room, user_id: &str, _: &Drawing) -> Result<()> {
+            self.seen.push(format!("{user_id}~"));
+            Ok(())
+        }
+
+        async fn on_text(&mut self, _: &Room, user_id: &str, text: &str) -> Result<()> {
+            self.seen.push(format!("{user_id}:{text}"));
+            Ok(())
+        }
+
+        fn read(&mut self, strokes: &[Drawing]) -> Option<String> {
+            Some("x".repeat(strokes.len()))
+        }
+    }
+
+    fn ev(user_id: &str, e: event::Event) -> Event {
+        Event { by_user_id: user_id.to_owned(), event: Some(e), ..Default::default() }
+    }
+
+    #[tokio::test]
+    async fn calls_hooks_in_order() {
+        let session = Session::connect("http://127.0.0.1:1", "r", "bot").unwrap();
+        let room = Room::new(session);
+        let drawing = || event::Event::Drawing(Drawing::default());
+        let events = tokio_stream::iter([
+            ev("bot", event::Event::UsersInTheRoom(1)),
+            ev("bob", event::Event::UserJoinedTheRoom(true)),
+            ev("bob", drawing()),
+            ev("joe", drawing()),
+            ev("bob", drawing()),
+            ev("bob", event::Event::UserLeftTheRoom(true)),
+            ev("joe", drawing()),
+        ]);
+
+        let mut bot = Scribe::default();
+        dispatch(&mut bot, &room, events).await;
+        assert_eq!(bot.seen, ["+bob", "bob~", "joe~", "bob~", "bob:xx", "-bob", "joe~", "joe:xx"]);
+    }
+
+    #[tokio::test]
+    async fn reads_after_a_pause() {
+        let session = Session::connect("http://127.0.0.1:1", "r", "bot").unwrap();
+        let room = Room::new(session);
+        let events = tokio_stream::iter([ev("bob", event::Event::Drawing(Drawing::default()))])
+            .chain(tokio_stream::pending());
+
+        let mut bot = Scribe::default();
+        let timeout = TEXT_PAUSE + Duration::from_millis(500);
+        let _ = tokio::time::timeout(timeout, dispatch(&mut bot, &room, events)).await;
+        assert_eq!(bot.seen, ["bob~", "bob:x"]);
+    }
+}
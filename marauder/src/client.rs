@@ -0,0 +1,663 @@
+//! Talk to HyperCard rooms, with or without a framebuffer
+//!
+//! A [`Session`] is one user in one room: it sends drawings & screens and
+//! streams the room's events, reconnecting whenever the stream drops.
+//! Calls the server turns down fail with a [`Rejection`].
+//!
+use std::{
+    convert::Infallible,
+    fmt, fs,
+    path::Path,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use log::{debug, error, info, warn};
+use pb::{
+    capabilities,
+    proto::hypercards::{
+        event, screen_sharing_client::ScreenSharingClient, whiteboard_client::WhiteboardClient,
+        Capabilities, Drawing, Event, RecvEventsReq, SendEventReq, SendScreenReq,
+    },
+};
+use prost::Message;
+use tokio::time::sleep;
+use tokio_stream::Stream;
+use tonic::{
+    metadata::AsciiMetadataValue,
+    transport::{Certificate, Channel, ClientTlsConfig, Endpoint},
+    Code, Request, Status,
+};
+
+const REPO: &str = env!("CARGO_PKG_REPOSITORY");
+const VSN: &str = env!("CARGO_PKG_VERSION");
+
+/// Shortest then longest pause between two connection attempts.
+const MIN_BACKOFF: Duration = Duration::from_millis(250);
+const MAX_BACKOFF: Duration = Duration::from_secs(10);
+
+/// Pause between attempts to join a full room.
+const FULL_BACKOFF: Duration = Duration::from_secs(5);
+
+/// Header rate-limited calls come back with.
+const RETRY_AFTER_HEADER: &str = "retry-after-ms";
+
+/// Header servers tell their ID with, on joining.
+const SERVER_ID_HEADER: &str = "x-server-id";
+
+/// Why the server turned a call down, as opposed to failing to reach it.
+/// Find it with `err.downcast_ref::<Rejection>()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Rejection {
+    /// Malformed, e.g. a drawing whose coordinates don't line up: retrying won't help.
+    Invalid(String),
+    /// Sent too fast: retrying after a while will.
+    TooFast { retry_after: Duration, why: String },
+    /// The room is full, of members or of history.
+    Full(String),
+    /// The room is read-only, for now.
+    Locked(String),
+    /// Kicked or banned by a moderator: the reason is meant for the user.
+    Kicked(String),
+    /// More than the server told it supports, e.g. too large: not even sent.
+    Unsupported(String),
+}
+
+impl Rejection {
+    #[must_use]
+    pub fn from_status(status: &Status) -> Option<Self> {
+        let why = status.message().to_owned();
+        match status.code() {
+            Code::InvalidArgument => Some(Self::Invalid(why)),
+            Code::FailedPrecondition => Some(Self::Locked(why)),
+            Code::PermissionDenied => Some(Self::Kicked(why)),
+            Code::ResourceExhausted => {
+                let retry_after = status
+                    .metadata()
+                    .get(RETRY_AFTER_HEADER)
+                    .and_then(|ms| ms.to_str().ok()?.parse().ok())
+                    .map(Duration::from_millis);
+                match retry_after {
+                    Some(retry_after) => Some(Self::TooFast { retry_after, why }),
+                    None => Some(Self::Full(why)),
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Invalid(why) => write!(f, "rejected as invalid: {why}"),
+            Self::TooFast { retry_after, why } => write!(f, "{why} (retry after {retry_after:?})"),
+            Self::Full(why) | Self::Locked(why) | Self::Unsupported(why) => {
+                write!(f, "rejected: {why}")
+            }
+            Self::Kicked(why) => write!(f, "{why}"),
+        }
+    }
+}
+
+impl std::error::Error for Rejection {}
+
+/// Secret a server's admin API is called with. Kept out of logs.
+#[derive(Clone, PartialEq, Eq)]
+pub struct AdminToken(String);
+
+impl AdminToken {
+    #[must_use]
+    pub fn secret(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for AdminToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "AdminToken(..)")
+    }
+}
+
+impl FromStr for AdminToken {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self(s.to_owned()))
+    }
+}
+
+/// Sets the header the admin API authenticates calls with.
+pub fn add_admin_token<T>(req: &mut Request<T>, token: &AdminToken) -> Result<()> {
+    let token: AsciiMetadataValue = format!("Bearer {}", token.secret()).parse()?;
+    Request::metadata_mut(req).insert("authorization", token);
+    Ok(())
+}
+
+/// Sets the header servers use to tell users apart.
+pub fn add_xuser<T>(req: &mut Request<T>, user_id: &str) -> Result<()> {
+    let md = Request::metadata_mut(req);
+    let key = "x-user";
+    assert!(md.get(key).is_none());
+    let user_id: AsciiMetadataValue = user_id.parse()?;
+    md.insert(key, user_id);
+    Ok(())
+}
+
+/// A connection to `host` that gets established on first use.
+/// `https://` hosts get authenticated against well-known CAs.
+pub fn channel(host: &str) -> Result<Channel> {
+    channel_with_ca(host, None)
+}
+
+/// Same as [`channel`] but `https://` hosts get authenticated against `ca` (PEM):
+/// either a CA bundle or, to trust it, the host's self-signed certificate.
+/// Either way the certificate is a trust anchor, not a pin: host names still get checked.
+pub fn channel_with_ca(host: &str, ca: Option<&Path>) -> Result<Channel> {
+    info!("[channel] using gRPC host: {host:?}");
+    let mut ep = Endpoint::from_shared(host.to_owned())?
+        .connect_timeout(Duration::from_secs(10))
+        .timeout(Duration::from_secs(4))
+        .user_agent(format!("{REPO}/releases/tag/v{VSN}"))?;
+    match (ep.uri().scheme_str(), ca) {
+        (Some("https"), None) => ep = ep.tls_config(ClientTlsConfig::new().with_webpki_roots())?,
+        (Some("https"), Some(ca)) => {
+            let pem = fs::read(ca).with_context(|| format!("reading CA {}", ca.display()))?;
+            info!("[channel] trusting {}", ca.display());
+            let tls = ClientTlsConfig::new().ca_certificate(Certificate::from_pem(pem));
+            ep = ep.tls_config(tls)?;
+        }
+        (_, Some(ca)) => bail!("{} can't authenticate plaintext host {host:?}", ca.display()),
+        (_, None) => {}
+    }
+    Ok(ep.connect_lazy())
+}
+
+/// One user in one room. Cheap to clone: clones share the connection and the room's bookkeeping.
+#[derive(Clone)]
+pub struct Session {
+    ch: Channel,
+    room_id: String,
+    user_id: String,
+    people: Arc<AtomicU32>,
+    ended: Arc<Mutex<Option<Rejection>>>,
+    server_id: Arc<Mutex<Option<String>>>,
+    server_capabilities: Arc<Mutex<Capabilities>>,
+}
+
+impl Session {
+    /// Prepares a session without actually connecting: that happens on first call.
+    pub fn connect(host: &str, room_id: &str, user_id: &str) -> Result<Self> {
+        Ok(Self::with_channel(channel(host)?, room_id, user_id))
+    }
+
+    #[must_use]
+    pub fn with_channel(ch: Channel, room_id: &str, user_id: &str) -> Self {
+        Self {
+            ch,
+            room_id: room_id.to_owned(),
+            user_id: user_id.to_owned(),
+            people: Default::default(),
+            ended: Default::default(),
+            server_id: Default::default(),
+            server_capabilities: Arc::new(Mutex::new(Capabilities::of_peer(None))),
+        }
+    }
+
+    #[must_use]
+    pub fn room_id(&self) -> &str {
+        &self.room_id
+    }
+
+    #[must_use]
+    pub fn user_id(&self) -> &str {
+        &self.user_id
+    }
+
+    /// The underlying connection, e.g. to call other HyperCard services.
+    #[must_use]
+    pub fn channel(&self) -> Channel {
+        self.ch.clone()
+    }
+
+    /// Amount of users in the room (this one included), as of the last event received.
+    #[must_use]
+    pub fn people(&self) -> u32 {
+        self.people.load(Ordering::Relaxed)
+    }
+
+    /// Why [`Session::events`] ended, if it did: the room is invalid, or the user got kicked.
+    #[must_use]
+    pub fn rejection(&self) -> Option<Rejection> {
+        self.ended.lock().unwrap().clone()
+    }
+
+    fn end(&self, rejection: Rejection) {
+        error!("[events] giving up: {rejection}");
+        *self.ended.lock().unwrap() = Some(rejection);
+    }
+
+    /// ID the server told when last joined, if it did.
+    #[must_use]
+    pub fn server_id(&self) -> Option<String> {
+        self.server_id.lock().unwrap().clone()
+    }
+
+    /// What the server told it supports when last joined: protocol version 1 until then,
+    /// or if it told nothing.
+    #[must_use]
+    pub fn server_capabilities(&self) -> Capabilities {
+        self.server_capabilities.lock().unwrap().clone()
+    }
+
+    /// Waits out rate limits. Fails with a [`Rejection`] if the drawing is invalid or the room full.
+    pub async fn send_drawing(&self, drawing: Drawing) -> Result<()> {
+        let event = Event { event: Some(event::Event::Drawing(drawing)), ..Default::default() };
+        self.send_event(event).await
+    }
+
+    /// Same as [`Session::send_drawing`], for events that aren't just a drawing,
+    /// e.g. ones republished with their `origin_server_id`.
+    pub async fn send_event(&self, event: Event) -> Result<()> {
+        let server = self.server_capabilities();
+        if !server.accepts(&event) {
+            let kind = event.event.as_ref().map_or("empty", capabilities::event_kind);
+            let why =
+                format!("{kind} event of {} bytes unsupported by server", event.encoded_len());
+            return Err(Rejection::Unsupported(why).into());
+        }
+        let req = SendEventReq { event: Some(event), room_ids: vec![self.room_id.clone()] };
+        loop {
+            let mut req = Request::new(req.clone());
+            add_xuser(&mut req, &self.user_id)?;
+            debug!("[send_event] REQ = {req:?}");
+            let Err(e) = WhiteboardClient::new(self.channel()).send_event(req).await else {
+                return Ok(());
+            };
+            match Rejection::from_status(&e) {
+                Some(Rejection::TooFast { retry_after, why }) => {
+                    warn!("[send_event] {why}, next attempt in {retry_after:?}");
+                    sleep(retry_after).await;
+                }
+                Some(rejection) => return Err(rejection.into()),
+                None => bail!("[send_event] failure: {e}"),
+            }
+        }
+    }
+
+    /// Fails with a [`Rejection`] if the server turns the screen down.
+    pub async fn send_screen(&self, screen_png: Vec<u8>) -> Result<()> {
+        let bytes = screen_png.len();
+        let mut req = Request::new(SendScreenReq { room_id: self.room_id.clone(), screen_png });
+        add_xuser(&mut req, &self.user_id)?;
+        if let Err(e) = ScreenSharingClient::new(self.channel()).send_screen(req).await {
+            if let Some(rejection) = Rejection::from_status(&e) {
+                return Err(rejection.into());
+            }
+            bail!("[send_screen] failure: {e}")
+        }
+        debug!("[send_screen] sent {bytes} bytes");
+        Ok(())
+    }
+
+    /// Joins the room then yields its events, forever: dropped connections are
+    /// re-established with some backoff, full rooms are waited on. Events are
+    /// passed on as received, after users joining or leaving were accounted for
+    /// in [`Session::people`]. Ends only if the server rejects the room as invalid
+    /// or kicks the user out, see [`Session::rejection`].
+    pub fn events(&self) -> impl Stream<Item = Event> + Send + 'static {
+        let session = self.clone();
+        async_stream::stream! {
+            let mut backoff = Backoff::default();
+            'rejoin: loop {
+                let Some(mut stream) = session.join(&mut backoff).await else { break };
+                info!("[events] receiving...");
+                loop {
+                    match stream.message().await {
+                        Err(e) => match Rejection::from_status(&e) {
+                            Some(kicked @ Rejection::Kicked(_)) => {
+                                session.end(kicked);
+                                break 'rejoin;
+                            }
+                            _ => {
+                                error!("[events] sender status: {e}");
+                                break;
+                            }
+                        },
+                        Ok(None) => {
+                            warn!("[events] connection dropped!");
+                            break;
+                        }
+                        Ok(Some(event)) => {
+                            backoff.reset();
+                            session.account_for(&event);
+                            yield event;
+                        }
+                    }
+                }
+                let pause = backoff.pause();
+                warn!("[events] rejoining in {pause:?}");
+                sleep(pause).await;
+            }
+        }
+    }
+
+    /// Waits on `backoff` between failed attempts.
+    async fn join(&self, backoff: &mut Backoff) -> Option<tonic::Streaming<Event>> {
+        let capabilities = Some(Capabilities::ours());
+        let req = RecvEventsReq { room_id: self.room_id.clone(), capabilities };
+        let mut client = WhiteboardClient::new(self.channel());
+
+        info!("[join] creating stream");
+        loop {
+            let mut req = Request::new(req.clone());
+            let res = match add_xuser(&mut req, &self.user_id) {
+                Ok(()) => client.recv_events(req).await,
+                Err(e) => {
+                    error!("[join] {e}");
+                    return None;
+                }
+            };
+            match res {
+                Ok(r) => {
+                    info!("[join] connection established!");
+                    let server_id =
+                        r.metadata().get(SERVER_ID_HEADER).and_then(|v| v.to_str().ok());
+                    *self.server_id.lock().unwrap() = server_id.map(str::to_owned);
+                    let told = Capabilities::from_header(r.metadata());
+                    *self.server_capabilities.lock().unwrap() = Capabilities::of_peer(told);
+                    return Some(r.into_inner());
+                }
+                Err(e) => match Rejection::from_status(&e) {
+                    Some(rejection @ (Rejection::Invalid(_) | Rejection::Kicked(_))) => {
+                        self.end(rejection);
+                        return None;
+                    }
+                    Some(Rejection::Full(why)) => {
+                        warn!("[join] {why}, next attempt in {FULL_BACKOFF:?}");
+                        sleep(FULL_BACKOFF).await;
+                    }
+                    _ => {
+                        let pause = backoff.pause();
+                        warn!("[join] couldn't connect, next attempt in {pause:?}: {e}");
+                        sleep(pause).await;
+                    }
+                },
+            }
+        }
+    }
+
+    fn account_for(&self, event: &Event) {
+        match event.event {
+            Some(event::Event::UsersInTheRoom(c)) => {
+                self.people.store(c, Ordering::Relaxed);
+                info!("[events] room {:?} has {c:?} users", event.in_room_id);
+            }
+            Some(event::Event::UserJoinedTheRoom(_)) => {
+                info!("[events] user {:?} joined room", event.by_user_id);
+                self.people.fetch_add(1, Ordering::Relaxed);
+            }
+            Some(event::Event::UserLeftTheRoom(_)) => {
+                info!("[events] user {:?} left room", event.by_user_id);
+                let _ = self
+                    .people
+                    .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |c| c.checked_sub(1));
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Pauses between connection attempts, doubling up to [`MAX_BACKOFF`].
+#[derive(Debug)]
+struct Backoff {
+    next: Duration,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self { next: MIN_BACKOFF }
+    }
+}
+
+impl Backoff {
+    fn pause(&mut self) -> Duration {
+        let pause = self.next;
+        self.next = (pause * 2).min(MAX_BACKOFF);
+        pause
+    }
+
+    /// Back to short pauses, once connected for good.
+    fn reset(&mut self) {
+        self.next = MIN_BACKOFF;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        pin::Pin,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+    };
+
+    use pb::proto::hypercards::{
+        whiteboard_server::{Whiteboard, WhiteboardServer},
+        ListRoomMembersRep, ListRoomMembersReq, ListRoomsRep, ListRoomsReq, SendEventRep,
+    };
+    use tokio::net::TcpListener;
+    use tokio_stream::{wrappers::TcpListenerStream, StreamExt};
+    use tonic::{Response, Status};
+
+    use super::*;
+
+    /// Refuses the first connection, drops the second one after a few events.
+    /// Rejects events sent to rooms "bad" and "full", throttles the first one sent to "busy".
+    /// Kicks from room "kick" after one event, and out of room "banned" right away.
+    /// Only takes small drawings in room "tiny", from clients telling their capabilities.
+    /// Ends streams of room "drop" right away.
+    #[derive(Default)]
+    struct Flaky {
+        attempts: AtomicUsize,
+        drops: Arc<AtomicUsize>,
+        throttled: AtomicUsize,
+        sent: Arc<Mutex<Vec<(String, SendEventReq)>>>,
+    }
+
+    fn ev(by: &str, e: event::Event) -> Event {
+        Event {
+            by_user_id: by.to_owned(),
+            in_room_id: "r".to_owned(),
+            event: Some(e),
+            ..Default::default()
+        }
+    }
+
+    fn kicked() -> Status {
+        Status::permission_denied("kicked: spam")
+    }
+
+    #[tonic::async_trait]
+    impl Whiteboard for Flaky {
+        type RecvEventsStream = Pin<Box<dyn Stream<Item = Result<Event, Status>> + Send>>;
+
+        async fn recv_events(
+            &self,
+            req: Request<RecvEventsReq>,
+        ) -> Result<Response<Self::RecvEventsStream>, Status> {
+            if req.get_ref().room_id == "kick" {
+                let events = [Ok(ev("joe", event::Event::UsersInTheRoom(1))), Err(kicked())];
+                return Ok(Response::new(Box::pin(tokio_stream::iter(events))));
+            }
+            if req.get_ref().room_id == "drop" {
+                self.drops.fetch_add(1, Ordering::Relaxed);
+                return Ok(Response::new(Box::pin(tokio_stream::empty())));
+            }
+            if req.get_ref().room_id == "banned" {
+                return Err(kicked());
+            }
+            if req.get_ref().room_id == "tiny" {
+                if req.get_ref().capabilities != Some(Capabilities::ours()) {
+                    return Err(Status::invalid_argument("tell capabilities"));
+                }
+                let events = [Ok(ev("joe", event::Event::UsersInTheRoom(1)))];
+                let stream: Self::RecvEventsStream =
+                    Box::pin(tokio_stream::iter(events).chain(tokio_stream::pending()));
+                let mut rep = Response::new(stream);
+                let caps = Capabilities {
+                    event_kinds: vec!["drawing".to_owned()],
+                    max_event_bytes: 64,
+                    ..Default::default()
+                };
+                caps.to_header(rep.metadata_mut());
+                return Ok(rep);
+            }
+            let attempt = self.attempts.fetch_add(1, Ordering::Relaxed);
+            let events = match attempt {
+                0 => return Err(Status::unavailable("not yet")),
+                1 => vec![
+                    ev("joe", event::Event::UsersInTheRoom(2)),
+                    ev("bob", event::Event::UserJoinedTheRoom(true)),
+                    ev("bob", event::Event::Drawing(Drawing::default())),
+                    ev("bob", event::Event::UserLeftTheRoom(true)),
+                ],
+                _ => vec![ev("joe", event::Event::UsersInTheRoom(1))],
+            };
+            let events = tokio_stream::iter(events.into_iter().map(Ok));
+            let stream: Self::RecvEventsStream = if attempt == 1 {
+                Box::pin(events)
+            } else {
+                Box::pin(events.chain(tokio_stream::pending()))
+            };
+            Ok(Response::new(stream))
+        }
+
+        async fn send_event(
+            &self,
+            req: Request<SendEventReq>,
+        ) -> Result<Response<SendEventRep>, Status> {
+            let user_id = req.metadata().get("x-user").unwrap().to_str().unwrap().to_owned();
+            match req.get_ref().room_ids[0].as_str() {
+                "bad" => return Err(Status::invalid_argument("bad drawing")),
+                "full" => return Err(Status::resource_exhausted("room is full")),
+                "busy" if self.throttled.fetch_add(1, Ordering::Relaxed) == 0 => {
+                    let mut status = Status::resource_exhausted("slow down");
+                    status.metadata_mut().insert(RETRY_AFTER_HEADER, "10".parse().unwrap());
+                    return Err(status);
+                }
+                _ => {}
+            }
+            self.sent.lock().unwrap().push((user_id, req.into_inner()));
+            Ok(Response::new(SendEventRep {}))
+        }
+
+        async fn list_rooms(
+            &self,
+            _: Request<ListRoomsReq>,
+        ) -> Result<Response<ListRoomsRep>, Status> {
+            Err(Status::unimplemented("flaky servers do not list rooms"))
+        }
+
+        async fn list_room_members(
+            &self,
+            _: Request<ListRoomMembersReq>,
+        ) -> Result<Response<ListRoomMembersRep>, Status> {
+            Err(Status::unimplemented("flaky servers do not list room members"))
+        }
+    }
+
+    #[tokio::test]
+    async fn reconnects_and_keeps_count() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let host = format!("http://{}", listener.local_addr().unwrap());
+        let flaky = Flaky::default();
+        let sent = flaky.sent.clone();
+        tokio::spawn(
+            tonic::transport::Server::builder()
+                .add_service(WhiteboardServer::new(flaky))
+                .serve_with_incoming(TcpListenerStream::new(listener)),
+        );
+
+        let session = Session::connect(&host, "r", "joe").unwrap();
+        let mut events = Box::pin(session.events());
+        let mut counts = vec![];
+        for _ in 0..5 {
+            let e = events.next().await.unwrap();
+            counts.push((e.by_user_id, session.people()));
+        }
+        let by = |u: &str, c| (u.to_owned(), c);
+        assert_eq!(counts, [by("joe", 2), by("bob", 3), by("bob", 3), by("bob", 2), by("joe", 1)]);
+
+        session.send_drawing(Drawing::default()).await.unwrap();
+        {
+            let sent = sent.lock().unwrap();
+            assert_eq!(sent.len(), 1);
+            assert_eq!(sent[0].0, "joe");
+            assert_eq!(sent[0].1.room_ids, ["r"]);
+        }
+
+        let in_room = |room_id| Session::with_channel(session.channel(), room_id, "joe");
+        let rejection = |res: Result<()>| res.unwrap_err().downcast::<Rejection>().unwrap();
+        let bad = in_room("bad").send_drawing(Drawing::default()).await;
+        assert_eq!(rejection(bad), Rejection::Invalid("bad drawing".to_owned()));
+        let full = in_room("full").send_drawing(Drawing::default()).await;
+        assert_eq!(rejection(full), Rejection::Full("room is full".to_owned()));
+        in_room("busy").send_drawing(Drawing::default()).await.unwrap();
+        assert_eq!(sent.lock().unwrap().len(), 2);
+        assert_eq!(session.rejection(), None);
+
+        let kicked = Rejection::Kicked("kicked: spam".to_owned());
+        for room_id in ["kick", "banned"] {
+            let session = in_room(room_id);
+            let events: Vec<_> = session.events().collect().await;
+            assert_eq!(events.len(), usize::from(room_id == "kick"));
+            assert_eq!(session.rejection(), Some(kicked.clone()));
+        }
+
+        let tiny = in_room("tiny");
+        Box::pin(tiny.events()).next().await.unwrap();
+        assert_eq!(tiny.server_capabilities().max_event_bytes, 64);
+        let big = Drawing { xs: vec![1.; 100], ys: vec![1.; 100], ..Default::default() };
+        let big = tiny.send_drawing(big).await;
+        assert!(matches!(rejection(big), Rejection::Unsupported(_)));
+        tiny.send_drawing(Drawing::default()).await.unwrap();
+        assert_eq!(tiny.server_capabilities().protocol_version, 1);
+    }
+
+    #[tokio::test]
+    async fn backs_off_from_streams_ending_right_away() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let host = format!("http://{}", listener.local_addr().unwrap());
+        let flaky = Flaky::default();
+        let drops = flaky.drops.clone();
+        tokio::spawn(
+            tonic::transport::Server::builder()
+                .add_service(WhiteboardServer::new(flaky))
+                .serve_with_incoming(TcpListenerStream::new(listener)),
+        );
+
+        let session = Session::connect(&host, "drop", "joe").unwrap();
+        let mut events = Box::pin(session.events());
+        let waited = tokio::time::timeout(Duration::from_secs(1), events.next()).await;
+        assert!(waited.is_err());
+        // Joined at 0, 250ms then 750ms
+        let drops = drops.load(Ordering::Relaxed);
+        assert!((2..=3).contains(&drops), "{drops}");
+    }
+
+    #[test]
+    fn doubles_pauses_until_reset() {
+        let mut backoff = Backoff::default();
+        let pauses: Vec<_> = (0..8).map(|_| backoff.pause().as_millis()).collect();
+        assert_eq!(pauses, [250, 500, 1000, 2000, 4000, 8000, 10000, 10000]);
+        backoff.reset();
+        assert_eq!(backoff.pause(), MIN_BACKOFF);
+    }
+}
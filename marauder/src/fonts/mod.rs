@@ -7,6 +7,7 @@ use nom::{
     sequence::tuple,
     IResult,
 };
+use pb::proto::hypercards::{drawing::Color, Drawing};
 use quick_xml::de::{from_str, DeError};
 use serde::Deserialize;
 
@@ -76,8 +77,81 @@ pub fn emsdelight_swash_caps() -> Result<Font, DeError> {
     Ok(glyphs)
 }
 
+/// Font units per em, as well as height of glyphs above their baseline.
+const UNITS_PER_EM: f32 = 1000.;
+const ASCENT: f32 = 800.;
+/// Width of spaces and of glyphs the font is missing.
+const ADVANCE: f32 = 378.;
+const SPACING: f32 = 80.;
+
+const PRESSURE: i32 = 3992;
+const WIDTH: u32 = 3;
+
+/// Lays `text` out on a single line, `size` pixels per em, with `origin` as its top left corner.
+/// Each of the glyphs' strokes makes one drawing.
+pub fn write(font: &Font, text: &str, origin: (f32, f32), size: f32, color: Color) -> Vec<Drawing> {
+    let k = size / UNITS_PER_EM;
+    let mut pen = 0.;
+    let mut ds = vec![];
+    for c in text.chars() {
+        let Some(glyph) = font.get(c.to_string().as_str()) else {
+            pen += ADVANCE;
+            continue;
+        };
+        for stroke in glyph {
+            let at = |(x, y): (f32, f32)| (origin.0 + k * (pen + x), origin.1 + k * (ASCENT - y));
+            ds.push(polyline(stroke.iter().copied().map(at), color));
+        }
+        let right = glyph.iter().flatten().map(|(x, _)| *x).fold(0., f32::max);
+        pen += right + SPACING;
+    }
+    ds
+}
+
+/// Straight lines through `points`: inserts segments' middles, which bezier painting
+/// goes through, and repeats both ends so the whole length gets painted.
+fn polyline(points: impl Iterator<Item = (f32, f32)>, color: Color) -> Drawing {
+    let points: Vec<_> = points.collect();
+    let mut path = Vec::with_capacity(2 * points.len() + 1);
+    for (i, &(x, y)) in points.iter().enumerate() {
+        if let Some(&(px, py)) = i.checked_sub(1).and_then(|i| points.get(i)) {
+            path.push(((px + x) / 2., (py + y) / 2.));
+        } else {
+            path.push((x, y));
+        }
+        path.push((x, y));
+    }
+    path.extend(path.last().copied());
+    let n = path.len();
+    Drawing {
+        xs: path.iter().map(|(x, _)| *x).collect(),
+        ys: path.iter().map(|(_, y)| *y).collect(),
+        pressures: vec![PRESSURE; n],
+        widths: vec![WIDTH; n],
+        color: color.into(),
+    }
+}
+
 #[cfg(test)]
 mod test {
+    use pb::proto::hypercards::drawing::Color;
+
+    #[test]
+    fn writes_text_left_to_right() {
+        let glyphs = super::emsdelight_swash_caps().unwrap();
+        let ds = super::write(&glyphs, "i i", (10., 20.), 100., Color::Black);
+        assert_eq!(ds.len(), 4);
+        assert!(ds.iter().all(|d| d.xs.len() >= 3 && d.color() == Color::Black));
+
+        // The stem of the first "i", from (117,438) down to (117,9.45)
+        assert_eq!(ds[0].xs, [21.7, 21.7, 21.7, 21.7, 21.7]);
+        assert_eq!(ds[0].ys[0], 20. + 0.1 * (800. - 438.));
+        assert_eq!(ds[0].ys[4], 20. + 0.1 * (800. - 9.45));
+
+        // The second "i" comes after a space
+        let second = 10. + 0.1 * (126. + 80. + 378. + 117.);
+        assert!((ds[2].xs[0] - second).abs() < 1e-3, "{}", ds[2].xs[0]);
+    }
 
     #[test]
     fn emsdelight_swash_caps_result() {
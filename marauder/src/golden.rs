@@ -0,0 +1,171 @@
+//! Golden images: what painting should look like, committed as PNGs
+//!
+//! [`assert_matches`] compares a rendering against its reference, allowing for
+//! a few stray pixels. On failure it saves the rendering and a diff image
+//! (red: only in the reference, blue: only in the rendering) under
+//! `$GOLDEN_DIFFS` (by default `golden` in the temporary directory).
+//!
+//! Run tests with `UPDATE_GOLDEN=1` to (re)write references instead.
+//!
+use std::{env, path::Path};
+
+use libremarkable::image::{GrayImage, Rgb, RgbImage};
+
+/// Lumas further apart than this differ.
+const THRESHOLD: u8 = 64;
+
+/// Share of pixels allowed to differ.
+const TOLERANCE: f64 = 0.001;
+
+/// Panics unless `img` looks like `dir/{name}.png`.
+pub fn assert_matches(dir: &Path, name: &str, img: &GrayImage) {
+    let path = dir.join(format!("{name}.png"));
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::create_dir_all(dir).unwrap();
+        img.save(&path).unwrap();
+        return;
+    }
+    let golden = match libremarkable::image::open(&path) {
+        Ok(golden) => golden.into_luma8(),
+        Err(e) => panic!("no golden image {path:?} ({e}): run with UPDATE_GOLDEN=1 to create it"),
+    };
+
+    let out =
+        env::var_os("GOLDEN_DIFFS").map_or_else(|| env::temp_dir().join("golden"), Into::into);
+    let actual = out.join(format!("{name}.actual.png"));
+    if golden.dimensions() != img.dimensions() {
+        save(&actual, img);
+        panic!(
+            "{name}: rendered {:?} instead of {:?}, see {actual:?}",
+            img.dimensions(),
+            golden.dimensions(),
+        );
+    }
+
+    let (diff, differing) = diff(&golden, img);
+    let allowed = (f64::from(img.width() * img.height()) * TOLERANCE) as usize;
+    if differing > allowed {
+        save(&actual, img);
+        let diff_path = out.join(format!("{name}.diff.png"));
+        std::fs::create_dir_all(&out).unwrap();
+        diff.save(&diff_path).unwrap();
+        panic!("{name}: {differing} pixels differ (> {allowed}), see {actual:?} & {diff_path:?}");
+    }
+}
+
+fn save(path: &Path, img: &GrayImage) {
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    img.save(path).unwrap();
+}
+
+/// A diff image and how many pixels differ.
+fn diff(golden: &GrayImage, img: &GrayImage) -> (RgbImage, usize) {
+    let mut differing = 0;
+    let diff = RgbImage::from_fn(img.width(), img.height(), |x, y| {
+        let (g, i) = (golden.get_pixel(x, y).0[0], img.get_pixel(x, y).0[0]);
+        if g.abs_diff(i) <= THRESHOLD {
+            // Faded, for context
+            let v = 192 + i / 4;
+            return Rgb([v, v, v]);
+        }
+        differing += 1;
+        if g < i {
+            Rgb([255, 0, 0])
+        } else {
+            Rgb([0, 0, 255])
+        }
+    });
+    (diff, differing)
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use libremarkable::image::Luma;
+    use pb::proto::hypercards::{drawing::Color, Drawing};
+
+    use super::*;
+    use crate::{fonts, raster::Canvas};
+
+    fn golden_dir() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("golden")
+    }
+
+    /// Assets all live within the toolbar.
+    const TOOLBAR: (u32, u32) = (1404, 80);
+
+    type Asset = fn(Color) -> Vec<Drawing>;
+
+    fn render(ds: &[Drawing]) -> GrayImage {
+        let mut canvas = Canvas::new(TOOLBAR.0, TOOLBAR.1);
+        for d in ds {
+            canvas.paint(d);
+        }
+        canvas.image().clone()
+    }
+
+    #[test]
+    fn diffs_images() {
+        let white = GrayImage::from_pixel(100, 100, Luma([u8::MAX]));
+        let mut dotted = white.clone();
+        dotted.put_pixel(1, 1, Luma([0]));
+        dotted.put_pixel(2, 2, Luma([200]));
+        let (diff, differing) = diff(&white, &dotted);
+        assert_eq!(differing, 1);
+        assert_eq!(diff.get_pixel(1, 1).0, [0, 0, 255]);
+        assert_eq!(diff.get_pixel(0, 0).0, [255, 255, 255]);
+    }
+
+    #[test]
+    fn drawings_assets() {
+        let assets: [(&str, Asset); 14] = [
+            ("title_whiteboard", drawings::title_whiteboard::f),
+            ("top_left_help", drawings::top_left_help::f),
+            ("top_left_white_empty_square", drawings::top_left_white_empty_square::f),
+            ("top_left_x3", drawings::top_left_x3::f),
+            ("top_right_0", drawings::top_right_0::f),
+            ("top_right_1", drawings::top_right_1::f),
+            ("top_right_2", drawings::top_right_2::f),
+            ("top_right_3", drawings::top_right_3::f),
+            ("top_right_4", drawings::top_right_4::f),
+            ("top_right_5", drawings::top_right_5::f),
+            ("top_right_6", drawings::top_right_6::f),
+            ("top_right_7", drawings::top_right_7::f),
+            ("top_right_8", drawings::top_right_8::f),
+            ("top_right_9", drawings::top_right_9::f),
+        ];
+        for (name, f) in assets {
+            assert_matches(&golden_dir().join("drawings"), name, &render(&f(Color::Black)));
+        }
+    }
+
+    /// `whiteboard` paints its title banner at half width.
+    #[test]
+    fn title_whiteboard_banner() {
+        let mut parts = drawings::title_whiteboard::f(Color::Black);
+        for w in parts.iter_mut().flat_map(|part| part.widths.iter_mut()) {
+            *w /= 2;
+        }
+        assert_matches(&golden_dir(), "title_whiteboard_banner", &render(&parts));
+    }
+
+    /// All glyphs, in codepoint order, 16 per row.
+    #[test]
+    fn emsdelight_swash_caps() {
+        const CELL: f32 = 80.;
+        let font = fonts::emsdelight_swash_caps().unwrap();
+        let mut glyphs: Vec<char> = font.keys().filter_map(|k| k.chars().next()).collect();
+        glyphs.sort_unstable();
+
+        let rows = glyphs.len().div_ceil(16) as u32;
+        let mut canvas = Canvas::new(16 * CELL as u32, rows * CELL as u32);
+        for (i, c) in glyphs.into_iter().enumerate() {
+            let at = ((i % 16) as f32 * CELL + 10., (i / 16) as f32 * CELL + 5.);
+            for d in fonts::write(&font, &c.to_string(), at, 60., Color::Black) {
+                canvas.paint(&d);
+            }
+        }
+        assert_matches(&golden_dir(), "emsdelight_swash_caps", canvas.image());
+    }
+}
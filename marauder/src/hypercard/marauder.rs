@@ -0,0 +1,563 @@
+//! The drawing demo, as a card: ink with the pen, stamp shapes with fingers
+//!
+//! Controls above the canvas undo the last stroke, zoom out of the canvas, blur
+//! or invert it, pick what fingers stamp, switch between black & white and
+//! resize the tip. They get tapped with a finger, or with the pen once it hovered.
+//! The right button turns fingers on & off, the left and middle ones clear the
+//! display: clearing can be undone.
+//!
+use std::{
+    sync::LazyLock,
+    time::{Duration, Instant},
+};
+
+use chrono::Local;
+use libremarkable::{
+    battery,
+    cgmath::{vec2, Point2},
+    device::Model,
+    framebuffer::common::{color, mxcfb_rect},
+    image::{
+        imageops::{self, FilterType},
+        Rgb, RgbImage,
+    },
+    input::{GPIOEvent, MultitouchEvent, PhysicalButton, WacomEvent, WacomPen},
+};
+use log::{info, warn};
+use pb::proto::hypercards::drawing::Color;
+
+use super::HyperCard;
+use crate::{
+    fonts::{self, Font},
+    ink::{self, Ink, Sample, Trail},
+    modes::{draw::DrawMode, touch::TouchMode},
+    raster,
+    surface::{self, Refresh, Surface, Waveform},
+};
+
+static FONT: LazyLock<Font> = LazyLock::new(|| fonts::emsdelight_swash_caps().unwrap());
+
+const fn rect(left: u32, top: u32, width: u32, height: u32) -> mxcfb_rect {
+    mxcfb_rect { top, left, width, height }
+}
+
+// This region will have the following size at rest:
+//   raw: 5896 kB
+//   zstd: 10 kB
+const CANVAS_REGION: mxcfb_rect = rect(0, 720, 1404, 1080 + 50);
+
+/// Where the battery & time show, and how often they get updated.
+const STATUS: mxcfb_rect = rect(30, 30, 1350, 60);
+const STATUS_EVERY: Duration = Duration::from_secs(37);
+
+/// Where the tip size shows.
+const SIZE: mxcfb_rect = rect(830, 630, 270, 80);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Control {
+    Undo,
+    ZoomOut,
+    Blur,
+    Invert,
+    TouchMode,
+    Eraser,
+    /// Grows or shrinks the tip by this much
+    Size(i32),
+}
+
+/// Clear of the display's left & right edges, where fingers gesture to the host.
+const CONTROLS: [(Control, mxcfb_rect); 10] = [
+    (Control::Undo, rect(70, 130, 300, 150)),
+    (Control::ZoomOut, rect(640, 350, 290, 80)),
+    (Control::Blur, rect(945, 350, 160, 80)),
+    (Control::Invert, rect(1115, 350, 215, 80)),
+    (Control::TouchMode, rect(640, 450, 690, 80)),
+    (Control::Eraser, rect(640, 540, 690, 80)),
+    (Control::Size(-10), rect(640, 630, 100, 80)),
+    (Control::Size(-1), rect(750, 630, 70, 80)),
+    (Control::Size(1), rect(1110, 630, 70, 80)),
+    (Control::Size(10), rect(1190, 630, 140, 80)),
+];
+
+fn control_at(p: Point2<u32>) -> Option<Control> {
+    CONTROLS.iter().find(|(_, area)| area.contains_point(&p)).map(|&(control, _)| control)
+}
+
+pub struct Marauder {
+    draw_mode: DrawMode,
+    touch_mode: TouchMode,
+    touch_enabled: bool,
+    trail: Trail,
+    drawing: bool,
+    wacom_in_range: bool,
+    unpress_observed: bool,
+    /// Tracking ID of the last finger that could have tapped a control: fingers tap once
+    last_tap: i32,
+    /// The canvas as of the last two strokes, to undo the last one
+    saved: Option<RgbImage>,
+    saved_prev: Option<RgbImage>,
+    status_at: Option<Instant>,
+}
+
+impl Default for Marauder {
+    fn default() -> Self {
+        Self {
+            draw_mode: DrawMode::default(),
+            touch_mode: TouchMode::OnlyUI,
+            touch_enabled: true,
+            trail: Trail::default(),
+            drawing: false,
+            wacom_in_range: false,
+            unpress_observed: false,
+            last_tap: -1,
+            saved: None,
+            saved_prev: None,
+            status_at: None,
+        }
+    }
+}
+
+impl Marauder {
+    fn label(&self, control: Control) -> String {
+        match control {
+            Control::Undo => "UNDO".to_owned(),
+            Control::ZoomOut => "ZOOM OUT".to_owned(),
+            Control::Blur => "BLUR".to_owned(),
+            Control::Invert => "INVERT".to_owned(),
+            Control::TouchMode => format!("TOUCH: {}", self.touch_mode).to_uppercase(),
+            Control::Eraser => format!("INK: {}", self.draw_mode).to_uppercase(),
+            Control::Size(-10) => "--".to_owned(),
+            Control::Size(-1) => "-".to_owned(),
+            Control::Size(1) => "+".to_owned(),
+            Control::Size(_) => "++".to_owned(),
+        }
+    }
+
+    fn paint_control(&self, s: &mut dyn Surface, control: Control) {
+        let (_, area) = CONTROLS.iter().find(|(c, _)| *c == control).unwrap();
+        write(s, area, &self.label(control));
+        outline(s, area, 5);
+    }
+
+    fn paint_size(&self, s: &mut dyn Surface) {
+        write(s, &SIZE, &format!("SIZE: {}", self.draw_mode.get_size()));
+    }
+
+    fn paint_status(&mut self, s: &mut dyn Surface, now: Instant) {
+        let time = Local::now().format("%F %R");
+        // Off the tablet (e.g. in the simulator) there's no battery to read
+        let battery = Model::current_model().ok().and_then(|_| {
+            Some((battery::percentage().ok()?, battery::human_readable_charging_status().ok()?))
+        });
+        let status = match battery {
+            Some((percents, status)) => format!("{percents}% ~ {} {time}", status.to_uppercase()),
+            None => time.to_string(),
+        };
+        write(s, &STATUS, &status);
+        self.status_at = Some(now);
+    }
+
+    /// Keeps the canvas, to undo what comes next.
+    fn save_canvas(&mut self, s: &mut dyn Surface) {
+        let Some(canvas) = s.snapshot(&CANVAS_REGION) else {
+            warn!("[save_canvas] can't read the canvas back");
+            return;
+        };
+        self.saved_prev = self.saved.replace(canvas);
+    }
+
+    fn undo(&mut self, s: &mut dyn Surface) {
+        let Some(prev) = self.saved_prev.take() else { return };
+        restore(s, &prev);
+        s.refresh(&CANVAS_REGION, Waveform::Quality, Refresh::Async);
+        self.saved = None;
+        self.save_canvas(s);
+    }
+
+    /// Paints the canvas over, made into something else.
+    fn transform(&mut self, s: &mut dyn Surface, f: impl FnOnce(RgbImage) -> RgbImage) {
+        let Some(canvas) = s.snapshot(&CANVAS_REGION) else {
+            warn!("[transform] can't read the canvas back");
+            return;
+        };
+        restore(s, &f(canvas));
+        s.refresh(&CANVAS_REGION, Waveform::Quality, Refresh::Async);
+    }
+
+    fn tap(&mut self, s: &mut dyn Surface, control: Control) {
+        info!("[tap] {control:?}");
+        match control {
+            Control::Undo => self.undo(s),
+            Control::ZoomOut => self.transform(s, |canvas| {
+                let (w, h) = canvas.dimensions();
+                let (rw, rh) = ((w as f32 / 1.25) as u32, (h as f32 / 1.25) as u32);
+                let resized = imageops::resize(&canvas, rw, rh, FilterType::Nearest);
+                // Get a clean image the size of the canvas
+                let mut zoomed = RgbImage::from_pixel(w, h, Rgb([u8::MAX; 3]));
+                imageops::overlay(&mut zoomed, &resized, (w / 8).into(), (h / 8).into());
+                zoomed
+            }),
+            Control::Blur => self.transform(s, |canvas| imageops::blur(&canvas, 0.6)),
+            Control::Invert => {
+                self.transform(s, |mut canvas| {
+                    imageops::invert(&mut canvas);
+                    canvas
+                });
+                // Invert the draw color as well for more natural UX
+                self.tap(s, Control::Eraser);
+            }
+            Control::TouchMode => {
+                self.touch_mode = self.touch_mode.toggle();
+                self.show(s, control);
+            }
+            Control::Eraser => {
+                self.draw_mode = match self.draw_mode {
+                    DrawMode::Erase(size) => DrawMode::Draw(size),
+                    DrawMode::Draw(size) => DrawMode::Erase(size),
+                };
+                self.show(s, control);
+            }
+            Control::Size(delta) => {
+                let size = self.draw_mode.get_size() as i32;
+                let resized = (size + delta).clamp(1, 99);
+                if resized != size {
+                    self.draw_mode = self.draw_mode.set_size(resized as u32);
+                    self.paint_size(s);
+                    s.refresh(&SIZE, Waveform::Fast, Refresh::Async);
+                }
+            }
+        }
+    }
+
+    /// Paints a control again then refreshes it.
+    fn show(&self, s: &mut dyn Surface, control: Control) {
+        let (_, area) = CONTROLS.iter().find(|(c, _)| *c == control).unwrap();
+        self.paint_control(s, control);
+        s.refresh(area, Waveform::Fast, Refresh::Async);
+    }
+
+    /// Stamps what fingers stamp at `at`, returning the area painted.
+    fn stamp(&self, s: &mut dyn Surface, at: Point2<i32>) -> Option<mxcfb_rect> {
+        let mut ink = |p| s.write_pixel(p, color::BLACK);
+        match self.touch_mode {
+            TouchMode::OnlyUI => None,
+            TouchMode::Bezier => {
+                let at = at.cast().unwrap();
+                let points = [
+                    (vec2(-40.0, 0.0), 2.5),
+                    (vec2(40.0, -60.0), 5.5),
+                    (vec2(0.0, 0.0), 3.5),
+                    (vec2(-40.0, 60.0), 6.5),
+                    (vec2(-10.0, 50.0), 5.0),
+                    (vec2(10.0, 45.0), 4.5),
+                    (vec2(30.0, 55.0), 3.5),
+                    (vec2(50.0, 65.0), 3.0),
+                    (vec2(70.0, 40.0), 0.0),
+                ];
+                let mut rect = mxcfb_rect::invalid();
+                for window in points.windows(3).step_by(2) {
+                    rect = rect.merge_rect(&raster::draw_dynamic_bezier(
+                        &mut ink,
+                        (at + window[0].0, window[0].1),
+                        (at + window[1].0, window[1].1),
+                        (at + window[2].0, window[2].1),
+                        100,
+                    ));
+                }
+                Some(rect)
+            }
+            TouchMode::Circles => Some(circle(&mut ink, at, 20)),
+            TouchMode::Diamonds => Some(diamond(&mut ink, at, false)),
+            TouchMode::FillDiamonds => Some(diamond(&mut ink, at, true)),
+        }
+    }
+}
+
+impl HyperCard for Marauder {
+    fn name(&self) -> &str {
+        "MARAUDER"
+    }
+
+    fn on_pen(&mut self, s: &mut dyn Surface, event: WacomEvent) {
+        match event {
+            WacomEvent::Draw { position, pressure, tilt } => {
+                // Outside of drawable region
+                if !CANVAS_REGION.contains_point(&position.cast().unwrap()) {
+                    // This is so that we can click the controls outside the canvas region
+                    // normally meant to be touched with a finger using our stylus
+                    self.trail.clear();
+                    if std::mem::take(&mut self.unpress_observed) {
+                        if let Some(control) = control_at(position.cast().unwrap()) {
+                            self.tap(s, control);
+                        }
+                    }
+                    return;
+                }
+                self.drawing = true;
+
+                let (col, mult) = match self.draw_mode {
+                    DrawMode::Draw(size) => (color::BLACK, size),
+                    DrawMode::Erase(size) => (color::WHITE, size * 3),
+                };
+                let sample = Sample::from_wacom(position, pressure, tilt, mult);
+                if let Some(samples) = self.trail.push(sample) {
+                    Ink::new(col).paint(s, samples, Refresh::Async);
+                }
+            }
+            WacomEvent::InstrumentChange { pen: WacomPen::ToolPen, state } => {
+                // Whether the pen is in range
+                self.wacom_in_range = state;
+            }
+            WacomEvent::InstrumentChange { pen: WacomPen::Touch, state: false } => {
+                if std::mem::take(&mut self.drawing) {
+                    self.save_canvas(s);
+                }
+                self.trail.clear();
+            }
+            // If the pen is hovering, don't record its coordinates as the origin of the next line
+            WacomEvent::Hover { distance, .. } if distance > 1 => {
+                self.trail.clear();
+                self.unpress_observed = true;
+            }
+            _ => {}
+        }
+    }
+
+    fn on_touch(&mut self, s: &mut dyn Surface, event: MultitouchEvent) {
+        if !self.touch_enabled {
+            return;
+        }
+        match event {
+            MultitouchEvent::Press { finger } | MultitouchEvent::Move { finger } => {
+                let at: Point2<u32> = finger.pos.cast().unwrap();
+                if finger.tracking_id != self.last_tap {
+                    self.last_tap = finger.tracking_id;
+                    if let Some(control) = control_at(at) {
+                        return self.tap(s, control);
+                    }
+                }
+                if !CANVAS_REGION.contains_point(&at) {
+                    return;
+                }
+                if let Some(rect) = self.stamp(s, at.cast().unwrap()) {
+                    s.refresh(&rect, Waveform::Fast, Refresh::Async);
+                }
+            }
+            MultitouchEvent::Release { finger } => {
+                let at: Point2<u32> = finger.pos.cast().unwrap();
+                if self.touch_mode != TouchMode::OnlyUI && CANVAS_REGION.contains_point(&at) {
+                    self.save_canvas(s);
+                }
+            }
+            MultitouchEvent::Unknown => {}
+        }
+    }
+
+    fn on_button(&mut self, s: &mut dyn Surface, event: GPIOEvent) {
+        // Ignoring the unpressed event
+        let GPIOEvent::Press { button } = event else { return };
+        // Simple but effective accidental button press filtering
+        if self.wacom_in_range {
+            return;
+        }
+        match button {
+            PhysicalButton::RIGHT => {
+                self.touch_enabled = !self.touch_enabled;
+                info!("[on_button] touch enabled: {}", self.touch_enabled);
+            }
+            PhysicalButton::MIDDLE | PhysicalButton::LEFT => {
+                self.saved_prev = self.saved.take();
+                s.clear();
+                self.render(s);
+            }
+            PhysicalButton::WAKEUP => info!("WAKEUP button(?) pressed(?)"),
+            PhysicalButton::POWER => {}
+        }
+    }
+
+    fn render(&mut self, s: &mut dyn Surface) {
+        for (control, _) in CONTROLS {
+            self.paint_control(s, control);
+        }
+        self.paint_size(s);
+        self.paint_status(s, Instant::now());
+        let border = rect(0, CANVAS_REGION.top - 2, CANVAS_REGION.width, CANVAS_REGION.height + 3);
+        outline(s, &border, 2);
+        if let Some(ref saved) = self.saved {
+            restore(s, saved);
+        }
+        let (w, h) = s.dimensions();
+        s.refresh(&surface::everything(w, h), Waveform::Quality, Refresh::Async);
+    }
+
+    fn tick(&mut self, s: &mut dyn Surface) {
+        let now = Instant::now();
+        if self.status_at.is_some_and(|at| now.duration_since(at) < STATUS_EVERY) {
+            return;
+        }
+        self.paint_status(s, now);
+        s.refresh(&STATUS, Waveform::Fast, Refresh::Async);
+    }
+
+    fn suspend(&mut self) {
+        self.drawing = false;
+        self.trail.clear();
+    }
+}
+
+/// Blanks `area` then handwrites `text` in it.
+fn write(s: &mut dyn Surface, area: &mxcfb_rect, text: &str) {
+    fill(s, area, color::WHITE);
+    let size = area.height as f32 * 0.6;
+    let at = (area.left as f32 + 15., area.top as f32 + area.height as f32 * 0.2);
+    for d in fonts::write(&FONT, text, at, size, Color::Black) {
+        if d.xs.len() >= 3 {
+            ink::draw(s, &d);
+        }
+    }
+}
+
+fn fill(s: &mut dyn Surface, area: &mxcfb_rect, c: color) {
+    for y in area.top..area.top + area.height {
+        for x in area.left..area.left + area.width {
+            s.write_pixel(Point2 { x: x as i32, y: y as i32 }, c);
+        }
+    }
+}
+
+/// Borders `area` with `px` pixels of black, inside it.
+fn outline(s: &mut dyn Surface, area: &mxcfb_rect, px: u32) {
+    let (w, h) = (area.width, area.height);
+    for side in [
+        rect(area.left, area.top, w, px),
+        rect(area.left, area.top + h - px, w, px),
+        rect(area.left, area.top, px, h),
+        rect(area.left + w - px, area.top, px, h),
+    ] {
+        fill(s, &side, color::BLACK);
+    }
+}
+
+/// Paints a snapshot of the canvas back, without refreshing it.
+fn restore(s: &mut dyn Surface, canvas: &RgbImage) {
+    for (x, y, px) in canvas.enumerate_pixels() {
+        let [r, g, b] = px.0;
+        let at = Point2 { x: (CANVAS_REGION.left + x) as i32, y: (CANVAS_REGION.top + y) as i32 };
+        s.write_pixel(at, color::RGB(r, g, b));
+    }
+}
+
+/// The `2w+1` by `2h+1` area centered on `c`.
+fn around(c: Point2<i32>, w: i32, h: i32) -> mxcfb_rect {
+    let (left, top) = ((c.x - w).max(0), (c.y - h).max(0));
+    rect(left as u32, top as u32, (c.x + w + 1 - left) as u32, (c.y + h + 1 - top) as u32)
+}
+
+fn circle(ink: &mut impl FnMut(Point2<i32>), c: Point2<i32>, r: i32) -> mxcfb_rect {
+    let (mut x, mut y, mut err) = (r, 0, 1 - r);
+    while x >= y {
+        for (dx, dy) in [(x, y), (y, x), (-y, x), (-x, y), (-x, -y), (-y, -x), (y, -x), (x, -y)] {
+            ink(Point2 { x: c.x + dx, y: c.y + dy });
+        }
+        y += 1;
+        if err < 0 {
+            err += 2 * y + 1;
+        } else {
+            x -= 1;
+            err += 2 * (y - x) + 1;
+        }
+    }
+    around(c, r, r)
+}
+
+/// A diamond twice as tall as it is wide, centered on `c`.
+fn diamond(ink: &mut impl FnMut(Point2<i32>), c: Point2<i32>, filled: bool) -> mxcfb_rect {
+    let (w, h): (i32, i32) = (10, 20);
+    for dy in -h..=h {
+        let half = w * (h - dy.abs()) / h;
+        if filled {
+            for dx in -half..=half {
+                ink(Point2 { x: c.x + dx, y: c.y + dy });
+            }
+        } else {
+            ink(Point2 { x: c.x - half, y: c.y + dy });
+            ink(Point2 { x: c.x + half, y: c.y + dy });
+        }
+    }
+    around(c, w, h)
+}
+
+#[cfg(test)]
+mod test {
+    use libremarkable::input::Finger;
+
+    use super::*;
+    use crate::raster::Canvas;
+
+    fn stroke(card: &mut Marauder, canvas: &mut Canvas, y: f32) {
+        for x in 0..10 {
+            let position = Point2 { x: 100. + 20. * x as f32, y };
+            let tilt = (0, 0).into();
+            card.on_pen(canvas, WacomEvent::Draw { position, pressure: 2000, tilt });
+        }
+        card.on_pen(canvas, WacomEvent::InstrumentChange { pen: WacomPen::Touch, state: false });
+    }
+
+    fn finger(
+        event: fn(Finger) -> MultitouchEvent,
+        id: i32,
+        (x, y): (u16, u16),
+    ) -> MultitouchEvent {
+        let mut f = Finger::default();
+        f.tracking_id = id;
+        f.pos = Point2 { x, y };
+        event(f)
+    }
+
+    #[test]
+    fn undoes_strokes_and_paints_them_again_once_resumed() {
+        let mut card = Marauder::default();
+        let mut canvas = Canvas::default();
+        card.resume(&mut canvas);
+        stroke(&mut card, &mut canvas, 1000.);
+        stroke(&mut card, &mut canvas, 1200.);
+        assert_eq!(canvas.image().get_pixel(150, 999).0, [0]);
+        assert_eq!(canvas.image().get_pixel(150, 1199).0, [0]);
+
+        card.suspend();
+        canvas.clear();
+        card.resume(&mut canvas);
+        assert_eq!(canvas.image().get_pixel(150, 1199).0, [0]);
+
+        let (_, undo) = CONTROLS[0];
+        let at = (undo.left as u16 + 10, undo.top as u16 + 10);
+        card.on_touch(&mut canvas, finger(|finger| MultitouchEvent::Press { finger }, 1, at));
+        assert_eq!(canvas.image().get_pixel(150, 999).0, [0]);
+        assert_eq!(canvas.image().get_pixel(150, 1199).0, [u8::MAX]);
+    }
+
+    #[test]
+    fn stamps_with_fingers_once_picked() {
+        let mut card = Marauder::default();
+        let mut canvas = Canvas::default();
+        card.resume(&mut canvas);
+        let press = |finger| MultitouchEvent::Press { finger };
+        card.on_touch(&mut canvas, finger(press, 1, (700, 1300)));
+        assert_eq!(canvas.image().get_pixel(720, 1300).0, [u8::MAX]);
+
+        // Bezier, then circles
+        let (_, touch_mode) = CONTROLS[4];
+        let at = (touch_mode.left as u16 + 10, touch_mode.top as u16 + 10);
+        card.on_touch(&mut canvas, finger(press, 2, at));
+        card.on_touch(&mut canvas, finger(press, 3, at));
+        assert_eq!(card.touch_mode, TouchMode::Circles);
+
+        card.on_touch(&mut canvas, finger(press, 4, (700, 1300)));
+        assert_eq!(canvas.image().get_pixel(720, 1300).0, [0]);
+        assert_eq!(canvas.image().get_pixel(700, 1300).0, [u8::MAX]);
+
+        card.on_button(&mut canvas, GPIOEvent::Press { button: PhysicalButton::RIGHT });
+        card.on_touch(&mut canvas, finger(press, 5, (300, 1300)));
+        assert_eq!(canvas.image().get_pixel(320, 1300).0, [u8::MAX]);
+    }
+}
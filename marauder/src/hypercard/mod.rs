@@ -0,0 +1,496 @@
+//! HyperCards: apps sharing the tablet, one at a time
+//!
+//! A card implements [`HyperCard`], painting onto whatever [`Surface`] it is
+//! handed. A [`Host`] owns the installed cards: it lists them, starts the one
+//! tapped, switches between them when a finger swipes across the display and
+//! lets go of the display when POWER is pressed.
+//!
+//! Gestures start at the left or right edge of the display: swipe in from the
+//! right edge for the next card, from the left edge for the previous one and
+//! down along either edge for the list of cards. The host keeps fingers that
+//! start there to itself, cards never see them.
+//!
+//! On the tablet, [`launch`] runs a host: it hands it input, ticks it and lets
+//! xochitl have the display back once the host lets go of it.
+//!
+use std::{
+    collections::HashMap,
+    path::Path,
+    process::{self, Command},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use anyhow::Result;
+use clap::Parser;
+use libremarkable::{
+    appctx::ApplicationContext,
+    cgmath::Point2,
+    input::{GPIOEvent, InputEvent, MultitouchEvent, PhysicalButton, WacomEvent, WacomPen},
+};
+use log::{error, info, warn};
+use pb::proto::hypercards::drawing::Color;
+
+use crate::{
+    fonts::{self, Font},
+    ink::{self, Brush},
+    recording::{self, Recorder, Timing},
+    surface::{Refresh, Surface, Waveform},
+    whiteboard::Settings,
+};
+
+pub mod marauder;
+pub mod sketch;
+pub mod whiteboard;
+
+/// Fingers must travel this far (in pixels) to swipe.
+const SWIPE: i32 = 400;
+
+/// Fingers pressed within this distance of the left or right side of the display gesture.
+const EDGE: i32 = 60;
+
+/// Where the list of cards starts, and the height of its rows.
+const MENU_TOP: f32 = 400.;
+const MENU_ROW: f32 = 160.;
+
+/// How often the running card gets ticked.
+pub const TICK_EVERY: Duration = Duration::from_millis(50);
+
+/// Cards get handed input on one thread and ticked on another.
+pub trait HyperCard: Send {
+    /// How it is listed: in capitals, as that is all the font has.
+    fn name(&self) -> &str;
+
+    fn on_pen(&mut self, _s: &mut dyn Surface, _event: WacomEvent) {}
+
+    fn on_touch(&mut self, _s: &mut dyn Surface, _event: MultitouchEvent) {}
+
+    /// Called for any button but POWER, which belongs to the [`Host`].
+    fn on_button(&mut self, _s: &mut dyn Surface, _event: GPIOEvent) {}
+
+    /// Paints the card from scratch, onto a blank surface.
+    fn render(&mut self, s: &mut dyn Surface);
+
+    /// Called every so often while running, e.g. to paint what came over the network.
+    fn tick(&mut self, _s: &mut dyn Surface) {}
+
+    /// Another card is taking the display.
+    fn suspend(&mut self) {}
+
+    /// The card is back on a blank display.
+    fn resume(&mut self, s: &mut dyn Surface) {
+        self.render(s);
+    }
+}
+
+/// The cards this build comes with. The whiteboard is set up from `WHITEBOARD_*`
+/// environment variables, see [`Settings`].
+#[must_use]
+pub fn installed() -> Vec<Box<dyn HyperCard>> {
+    let mut cards: Vec<Box<dyn HyperCard>> = vec![
+        Box::new(sketch::Sketch::new("SKETCH", Brush::Ballpoint)),
+        Box::new(sketch::Sketch::new("CALLIGRAPHY", Brush::Calligraphy)),
+    ];
+    match Settings::try_parse_from(["whiteboard"]) {
+        Ok(settings) => cards.push(Box::new(whiteboard::Whiteboard::new(settings))),
+        Err(e) => error!("[installed] skipping the whiteboard: {e}"),
+    }
+    cards.push(Box::new(marauder::Marauder::default()));
+    cards
+}
+
+/// What the caller should do after an event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    Continue,
+    /// Hand the display back, e.g. to xochitl.
+    Exit,
+}
+
+/// Runs one card at a time, or lists them.
+pub struct Host {
+    cards: Vec<Box<dyn HyperCard>>,
+    current: Option<usize>,
+    font: Font,
+    /// Where each finger touching the display first did
+    touches: HashMap<i32, Point2<i32>>,
+    /// Where the pen last touched the list of cards
+    pen_at: Option<Point2<f32>>,
+}
+
+impl Host {
+    #[must_use]
+    pub fn new(cards: Vec<Box<dyn HyperCard>>) -> Self {
+        let font = fonts::emsdelight_swash_caps().unwrap();
+        Self { cards, current: None, font, touches: HashMap::new(), pen_at: None }
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.cards.iter().map(|card| card.name())
+    }
+
+    /// Index of the card named so.
+    #[must_use]
+    pub fn position(&self, name: &str) -> Option<usize> {
+        self.names().position(|n| n.eq_ignore_ascii_case(name))
+    }
+
+    /// Name of the card running, if not listing them.
+    #[must_use]
+    pub fn current(&self) -> Option<&str> {
+        self.current.map(|i| self.cards[i].name())
+    }
+
+    /// Brings the `i`-th card to the front.
+    pub fn start(&mut self, s: &mut dyn Surface, i: usize) {
+        self.suspend();
+        info!("[start] {}", self.cards[i].name());
+        s.clear();
+        self.cards[i].resume(s);
+        self.current = Some(i);
+    }
+
+    /// Shows the list of cards.
+    pub fn menu(&mut self, s: &mut dyn Surface) {
+        self.suspend();
+        s.clear();
+        let title = fonts::write(&self.font, "HYPERCARDS", (60., 200.), 120., Color::Black);
+        let names = self.names().enumerate().flat_map(|(i, name)| {
+            let at = (100., MENU_TOP + (i as f32 + 0.7) * MENU_ROW);
+            fonts::write(&self.font, name, at, 90., Color::Black)
+        });
+        let ds: Vec<_> = title.into_iter().chain(names).filter(|d| d.xs.len() >= 3).collect();
+        let (w, h) = s.dimensions();
+        for d in &ds {
+            ink::draw(s, d);
+        }
+        s.refresh(&crate::surface::everything(w, h), Waveform::Quality, Refresh::Async);
+    }
+
+    fn suspend(&mut self) {
+        if let Some(i) = self.current.take() {
+            self.cards[i].suspend();
+        }
+    }
+
+    /// Ticks the card running, if any.
+    pub fn tick(&mut self, s: &mut dyn Surface) {
+        if let Some(i) = self.current {
+            self.cards[i].tick(s);
+        }
+    }
+
+    /// Row of the list of cards at `y`, if any.
+    fn listed_at(&self, y: f32) -> Option<usize> {
+        let row = ((y - MENU_TOP) / MENU_ROW).floor();
+        (row >= 0. && (row as usize) < self.cards.len()).then_some(row as usize)
+    }
+
+    /// Switches to the card `by` places away, going through the list of cards.
+    fn switch(&mut self, s: &mut dyn Surface, by: isize) {
+        let count = self.cards.len() as isize + 1;
+        let from = self.current.map_or(0, |i| i as isize + 1);
+        match (from + by).rem_euclid(count) {
+            0 => self.menu(s),
+            to => self.start(s, to as usize - 1),
+        }
+    }
+
+    pub fn on_input(&mut self, s: &mut dyn Surface, evt: InputEvent) -> Flow {
+        match evt {
+            InputEvent::GPIO { event: GPIOEvent::Press { button: PhysicalButton::POWER } } => {
+                warn!("[on_input] letting go of the display");
+                self.suspend();
+                return Flow::Exit;
+            }
+            InputEvent::GPIO { event } => {
+                if let Some(i) = self.current {
+                    self.cards[i].on_button(s, event);
+                }
+            }
+            InputEvent::MultitouchEvent { event } => match self.current {
+                Some(i) if !self.claims(s, event) => self.cards[i].on_touch(s, event),
+                _ => self.on_finger(s, event),
+            },
+            InputEvent::WacomEvent { event } => match self.current {
+                Some(i) => self.cards[i].on_pen(s, event),
+                None => self.on_pen_in_menu(s, event),
+            },
+            InputEvent::Unknown {} => {}
+        }
+        Flow::Continue
+    }
+
+    /// Whether the finger is one gesturing: pressed at an edge.
+    fn claims(&self, s: &dyn Surface, event: MultitouchEvent) -> bool {
+        match event {
+            MultitouchEvent::Press { finger } => {
+                let (w, _) = s.dimensions();
+                let x = i32::from(finger.pos.x);
+                x < EDGE || x >= w as i32 - EDGE
+            }
+            MultitouchEvent::Move { finger } | MultitouchEvent::Release { finger } => {
+                self.touches.contains_key(&finger.tracking_id)
+            }
+            MultitouchEvent::Unknown => false,
+        }
+    }
+
+    fn on_finger(&mut self, s: &mut dyn Surface, event: MultitouchEvent) {
+        match event {
+            MultitouchEvent::Press { finger } => {
+                self.touches.insert(finger.tracking_id, finger.pos.cast().unwrap());
+            }
+            MultitouchEvent::Release { finger } => {
+                let Some(from) = self.touches.remove(&finger.tracking_id) else { return };
+                let to: Point2<i32> = finger.pos.cast().unwrap();
+                let (dx, dy) = (to.x - from.x, to.y - from.y);
+                if dx.abs() >= SWIPE && dy.abs() < dx.abs() / 2 {
+                    self.switch(s, if dx < 0 { 1 } else { -1 });
+                } else if dy >= SWIPE && dx.abs() < dy / 2 {
+                    self.menu(s);
+                } else if self.current.is_none() {
+                    if let Some(i) = self.listed_at(to.y as f32) {
+                        self.start(s, i);
+                    }
+                }
+            }
+            MultitouchEvent::Move { .. } | MultitouchEvent::Unknown => {}
+        }
+    }
+
+    /// Starts the card tapped with the pen, once it's lifted.
+    fn on_pen_in_menu(&mut self, s: &mut dyn Surface, event: WacomEvent) {
+        match event {
+            WacomEvent::Draw { position, .. } => self.pen_at = Some(position),
+            WacomEvent::InstrumentChange { pen: WacomPen::Touch, state: false } => {
+                let tapped = self.pen_at.take().and_then(|at| self.listed_at(at.y));
+                if let Some(i) = tapped {
+                    self.start(s, i);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Runs `host` on the tablet's display, as started or listed already: hands it input,
+/// ticks it and once it lets go of the display, hands that back to xochitl.
+/// Input gets recorded to `record`, and events recorded in `replay` get played alongside.
+pub fn launch(
+    app: &mut ApplicationContext<'_>,
+    host: Host,
+    record: Option<&Path>,
+    replay: Option<&Path>,
+) -> Result<()> {
+    let host = Arc::new(Mutex::new(host));
+
+    let (host0, appref0) = (host.clone(), app.upgrade_ref());
+    thread::spawn(move || loop {
+        thread::sleep(TICK_EVERY);
+        host0.lock().unwrap().tick(appref0.get_framebuffer_ref());
+    });
+
+    if let Some(path) = replay {
+        let entries = recording::read(path)?;
+        info!("[launch] replaying {} events from {path:?}", entries.len());
+        let (host1, appref1) = (host.clone(), app.upgrade_ref());
+        let runtime = tokio::runtime::Handle::try_current().ok();
+        thread::spawn(move || {
+            let _entered = runtime.as_ref().map(tokio::runtime::Handle::enter);
+            recording::replay(&entries, Timing::Recorded, |evt| {
+                if host1.lock().unwrap().on_input(appref1.get_framebuffer_ref(), evt) == Flow::Exit
+                {
+                    hand_back();
+                }
+            });
+            info!("[launch] done replaying");
+        });
+    }
+
+    let mut recorder = record.map(Recorder::create).transpose()?;
+
+    info!("[launch] beginning event dispatch...");
+    app.start_event_loop(true, true, true, |ctx, evt| {
+        if let Some(ref mut recorder) = recorder {
+            if let Err(e) = recorder.record(&evt) {
+                error!("[launch] failed recording {evt:?}: {e}");
+            }
+        }
+        if host.lock().unwrap().on_input(ctx.get_framebuffer_ref(), evt) == Flow::Exit {
+            hand_back();
+        }
+    });
+    Ok(())
+}
+
+/// Lets xochitl, the tablet's own UI, have the display back.
+fn hand_back() -> ! {
+    warn!("[hand_back] about to shut down & switch back to xochitl");
+    Command::new("systemctl").arg("start").arg("xochitl").spawn().unwrap();
+    process::exit(0);
+}
+
+#[cfg(test)]
+mod test {
+    use libremarkable::input::Finger;
+
+    use super::*;
+    use crate::raster::Canvas;
+
+    type Log = Arc<Mutex<Vec<String>>>;
+
+    /// Notes down what it's asked to do.
+    struct Spy {
+        name: &'static str,
+        log: Log,
+    }
+
+    impl HyperCard for Spy {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn on_touch(&mut self, _: &mut dyn Surface, _: MultitouchEvent) {
+            self.log.lock().unwrap().push(format!("{} touched", self.name));
+        }
+
+        fn on_button(&mut self, _: &mut dyn Surface, _: GPIOEvent) {
+            self.log.lock().unwrap().push(format!("{} pressed", self.name));
+        }
+
+        fn render(&mut self, _: &mut dyn Surface) {
+            self.log.lock().unwrap().push(format!("{} rendered", self.name));
+        }
+
+        fn suspend(&mut self) {
+            self.log.lock().unwrap().push(format!("{} suspended", self.name));
+        }
+    }
+
+    fn host() -> (Host, Log) {
+        let log = Log::default();
+        let cards: Vec<Box<dyn HyperCard>> = vec![
+            Box::new(Spy { name: "A", log: log.clone() }),
+            Box::new(Spy { name: "B", log: log.clone() }),
+        ];
+        (Host::new(cards), log)
+    }
+
+    fn swipe(from: (u16, u16), to: (u16, u16)) -> [InputEvent; 2] {
+        let finger = |(x, y)| {
+            let mut f = Finger::default();
+            f.tracking_id = 7;
+            f.pos = Point2 { x, y };
+            f
+        };
+        [
+            InputEvent::MultitouchEvent { event: MultitouchEvent::Press { finger: finger(from) } },
+            InputEvent::MultitouchEvent { event: MultitouchEvent::Release { finger: finger(to) } },
+        ]
+    }
+
+    fn button(button: PhysicalButton) -> InputEvent {
+        InputEvent::GPIO { event: GPIOEvent::Press { button } }
+    }
+
+    #[test]
+    fn lists_starts_and_switches_cards() {
+        let (mut host, log) = host();
+        let mut canvas = Canvas::default();
+        host.menu(&mut canvas);
+        assert_eq!(host.names().collect::<Vec<_>>(), ["A", "B"]);
+        assert_eq!(host.current(), None);
+        assert_eq!(host.position("b"), Some(1));
+
+        // Tapping the second row
+        for evt in swipe((200, 600), (205, 610)) {
+            assert_eq!(host.on_input(&mut canvas, evt), Flow::Continue);
+        }
+        assert_eq!(host.current(), Some("B"));
+
+        // Swiping in from the right edge wraps around to the list of cards, then to the first card
+        for evt in swipe((1380, 900), (300, 950)) {
+            host.on_input(&mut canvas, evt);
+        }
+        assert_eq!(host.current(), None);
+        for evt in swipe((1380, 900), (300, 950)) {
+            host.on_input(&mut canvas, evt);
+        }
+        assert_eq!(host.current(), Some("A"));
+
+        // Swiping down along the left edge lists cards
+        for evt in swipe((20, 100), (40, 900)) {
+            host.on_input(&mut canvas, evt);
+        }
+        assert_eq!(host.current(), None);
+
+        assert_eq!(
+            *log.lock().unwrap(),
+            ["B rendered", "B suspended", "A rendered", "A suspended"]
+        );
+    }
+
+    #[test]
+    fn keeps_gesturing_fingers_from_cards() {
+        let (mut host, log) = host();
+        let mut canvas = Canvas::default();
+        host.start(&mut canvas, 1);
+
+        // Swiping across the display, not from an edge, is the card's to handle
+        for evt in swipe((1200, 900), (300, 950)) {
+            host.on_input(&mut canvas, evt);
+        }
+        assert_eq!(host.current(), Some("B"));
+        // Swiping in from the left edge, moving along the way, is the host's
+        let mut moved = Finger::default();
+        moved.tracking_id = 7;
+        moved.pos = Point2 { x: 500, y: 910 };
+        let [press, release] = swipe((10, 900), (900, 950));
+        host.on_input(&mut canvas, press);
+        host.on_input(
+            &mut canvas,
+            InputEvent::MultitouchEvent { event: MultitouchEvent::Move { finger: moved } },
+        );
+        host.on_input(&mut canvas, release);
+        assert_eq!(host.current(), Some("A"));
+
+        assert_eq!(
+            *log.lock().unwrap(),
+            ["B rendered", "B touched", "B touched", "B suspended", "A rendered"]
+        );
+    }
+
+    #[test]
+    fn routes_buttons_but_power() {
+        let (mut host, log) = host();
+        let mut canvas = Canvas::default();
+        host.start(&mut canvas, 0);
+        assert_eq!(host.on_input(&mut canvas, button(PhysicalButton::MIDDLE)), Flow::Continue);
+        assert_eq!(host.on_input(&mut canvas, button(PhysicalButton::POWER)), Flow::Exit);
+        assert_eq!(*log.lock().unwrap(), ["A rendered", "A pressed", "A suspended"]);
+    }
+
+    #[test]
+    fn starts_cards_tapped_with_the_pen() {
+        let (mut host, _) = host();
+        let mut canvas = Canvas::default();
+        host.menu(&mut canvas);
+        let pen = |event| InputEvent::WacomEvent { event };
+        host.on_input(
+            &mut canvas,
+            pen(WacomEvent::Draw {
+                position: Point2 { x: 300., y: 450. },
+                pressure: 1000,
+                tilt: (0, 0).into(),
+            }),
+        );
+        assert_eq!(host.current(), None);
+        host.on_input(
+            &mut canvas,
+            pen(WacomEvent::InstrumentChange { pen: WacomPen::Touch, state: false }),
+        );
+        assert_eq!(host.current(), Some("A"));
+    }
+}
@@ -0,0 +1,146 @@
+//! A sketchpad: ink with the pen, erase with its rubber, clear with the left or middle button
+//!
+use libremarkable::{
+    framebuffer::common::{color, mxcfb_rect},
+    input::{GPIOEvent, PhysicalButton, WacomEvent, WacomPen},
+};
+
+use super::HyperCard;
+use crate::{
+    ink::{Brush, Ink, Sample, Trail},
+    surface::{self, Refresh, Surface, Waveform},
+};
+
+/// Width of ink, and of erasing.
+const INK_WIDTH: u32 = 2;
+const RUBBER_WIDTH: u32 = 50;
+
+pub struct Sketch {
+    name: &'static str,
+    brush: Brush,
+    erasing: bool,
+    drawing: bool,
+    trail: Trail,
+    /// Every stroke so far, to paint it all over again when resumed
+    strokes: Vec<(Ink, Vec<Sample>)>,
+}
+
+impl Sketch {
+    #[must_use]
+    pub fn new(name: &'static str, brush: Brush) -> Self {
+        Self {
+            name,
+            brush,
+            erasing: false,
+            drawing: false,
+            trail: Trail::default(),
+            strokes: vec![],
+        }
+    }
+
+    fn ink(&self) -> Ink {
+        if self.erasing {
+            Ink::new(color::WHITE)
+        } else {
+            Ink::new(color::BLACK).with_brush(self.brush)
+        }
+    }
+
+    /// Lets go of the line being drawn.
+    fn lift(&mut self) {
+        self.drawing = false;
+        self.trail.clear();
+    }
+}
+
+impl HyperCard for Sketch {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn on_pen(&mut self, s: &mut dyn Surface, event: WacomEvent) {
+        match event {
+            WacomEvent::Draw { position, pressure, tilt } => {
+                let width = if self.erasing { RUBBER_WIDTH } else { INK_WIDTH };
+                let sample = Sample::from_wacom(position, pressure, tilt, width);
+                let ink = self.ink();
+                match self.strokes.last_mut() {
+                    Some((_, stroke)) if self.drawing => stroke.push(sample),
+                    _ => self.strokes.push((ink, vec![sample])),
+                }
+                self.drawing = true;
+                if let Some(samples) = self.trail.push(sample) {
+                    ink.paint(s, samples, Refresh::Async);
+                }
+            }
+            WacomEvent::InstrumentChange {
+                pen: pen @ (WacomPen::ToolPen | WacomPen::ToolRubber),
+                ..
+            } => {
+                self.erasing = pen == WacomPen::ToolRubber;
+            }
+            WacomEvent::InstrumentChange { pen: WacomPen::Touch, state: false } => self.lift(),
+            WacomEvent::Hover { distance, .. } if distance > 1 => self.lift(),
+            _ => {}
+        }
+    }
+
+    fn on_button(&mut self, s: &mut dyn Surface, event: GPIOEvent) {
+        if let GPIOEvent::Press { button: PhysicalButton::LEFT | PhysicalButton::MIDDLE } = event {
+            self.strokes.clear();
+            s.clear();
+        }
+    }
+
+    fn render(&mut self, s: &mut dyn Surface) {
+        let mut rect = mxcfb_rect::invalid();
+        for (ink, stroke) in &self.strokes {
+            let mut trail = Trail::default();
+            for samples in stroke.iter().filter_map(|&sample| trail.push(sample)) {
+                let painted = ink.rasterize(samples, &mut |p| s.write_pixel(p, ink.color));
+                rect = rect.merge_rect(&painted);
+            }
+        }
+        if surface::is_empty(&rect) {
+            return;
+        }
+        s.refresh(&rect, Waveform::for_area(&rect), Refresh::Async);
+    }
+
+    fn suspend(&mut self) {
+        self.lift();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use libremarkable::cgmath::{Point2, Vector2};
+
+    use super::*;
+    use crate::raster::Canvas;
+
+    #[test]
+    fn paints_strokes_again_once_resumed() {
+        let mut sketch = Sketch::new("SKETCH", Brush::Ballpoint);
+        let mut canvas = Canvas::default();
+        for x in 0..10 {
+            let position = Point2 { x: 100. + 20. * x as f32, y: 300. };
+            let tilt = Vector2 { x: 0, y: 0 };
+            sketch.on_pen(&mut canvas, WacomEvent::Draw { position, pressure: 2000, tilt });
+        }
+        let lifted = WacomEvent::InstrumentChange { pen: WacomPen::Touch, state: false };
+        sketch.on_pen(&mut canvas, lifted);
+        let drawn = canvas.image().clone();
+        assert!(drawn.pixels().any(|p| p.0 == [0]));
+
+        sketch.suspend();
+        canvas.clear();
+        sketch.resume(&mut canvas);
+        assert_eq!(*canvas.image(), drawn);
+
+        sketch.on_button(&mut canvas, GPIOEvent::Press { button: PhysicalButton::MIDDLE });
+        canvas.clear();
+        sketch.resume(&mut canvas);
+        assert!(canvas.image().pixels().all(|p| p.0 == [u8::MAX]));
+    }
+}
@@ -0,0 +1,102 @@
+//! The whiteboard, as a card: see [`crate::whiteboard`]
+//!
+//! The room gets joined once the card first runs, and stays joined while other
+//! cards do: others' drawings get painted once back, onto the canvas as it was.
+//!
+use std::{sync::Arc, time::Instant};
+
+use libremarkable::input::{GPIOEvent, MultitouchEvent, WacomEvent};
+use log::error;
+
+use super::HyperCard;
+use crate::{
+    surface::Surface,
+    whiteboard::{Settings, WhiteboardSession},
+};
+
+pub struct Whiteboard {
+    session: Arc<WhiteboardSession>,
+}
+
+impl Whiteboard {
+    #[must_use]
+    pub fn new(settings: Settings) -> Self {
+        Self { session: Arc::new(WhiteboardSession::new(settings)) }
+    }
+
+    #[must_use]
+    pub fn session(&self) -> &Arc<WhiteboardSession> {
+        &self.session
+    }
+}
+
+impl HyperCard for Whiteboard {
+    fn name(&self) -> &str {
+        "WHITEBOARD"
+    }
+
+    fn on_pen(&mut self, s: &mut dyn Surface, event: WacomEvent) {
+        self.session.on_pen(s, event);
+    }
+
+    fn on_touch(&mut self, _: &mut dyn Surface, event: MultitouchEvent) {
+        self.session.on_tch(event);
+    }
+
+    fn on_button(&mut self, _: &mut dyn Surface, event: GPIOEvent) {
+        self.session.on_btn(event);
+    }
+
+    fn render(&mut self, s: &mut dyn Surface) {
+        self.session.repaint(s);
+    }
+
+    fn tick(&mut self, s: &mut dyn Surface) {
+        self.session.tick(s, Instant::now());
+    }
+
+    fn resume(&mut self, s: &mut dyn Surface) {
+        self.render(s);
+        if let Err(e) = self.session.connect() {
+            error!("[resume] not joining {:?}: {e}", self.session.settings().room);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use clap::Parser;
+    use libremarkable::{cgmath::Point2, input::WacomPen};
+
+    use super::*;
+    use crate::raster::Canvas;
+
+    /// The canvas survives other cards taking the display.
+    #[tokio::test]
+    async fn joins_then_paints_the_canvas_again_once_resumed() {
+        let settings = Settings::parse_from(["whiteboard", "--host", "http://127.0.0.1:9"]);
+        let mut card = Whiteboard::new(settings);
+        let mut canvas = Canvas::default();
+        card.resume(&mut canvas);
+        assert!(card.session().is_connected());
+
+        for x in 0..10 {
+            let position = Point2 { x: 300. + 20. * x as f32, y: 600. };
+            let tilt = (0, 0).into();
+            card.on_pen(&mut canvas, WacomEvent::Draw { position, pressure: 2000, tilt });
+        }
+        card.on_pen(
+            &mut canvas,
+            WacomEvent::InstrumentChange { pen: WacomPen::Touch, state: false },
+        );
+        card.tick(&mut canvas);
+        let drawn = canvas.image().clone();
+        assert_eq!(drawn.get_pixel(350, 599).0, [0]);
+
+        card.suspend();
+        canvas.clear();
+        card.resume(&mut canvas);
+        assert_eq!(canvas.image().get_pixel(350, 599).0, [0]);
+        card.session().disconnect();
+    }
+}
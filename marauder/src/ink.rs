@@ -0,0 +1,430 @@
+//! How pen samples turn into ink, on the tablet or off it
+//!
+//! Every 3 consecutive samples make a quadratic bezier curve: from between the
+//! last two to between the first two, bending around the middle one, as wide
+//! as the [`Brush`] makes it under the [`PressureCurve`]. `whiteboard`,
+//! `marauder`, `scrolls` & [`raster`](crate::raster) all paint through [`Ink`],
+//! so the same samples painted with the same brush look the same wherever they
+//! land. A [`Drawing`] carries neither brush nor pressure curve though: others'
+//! drawings show as [`Brush::Ballpoint`], whatever their author picked.
+//!
+use std::{collections::VecDeque, str::FromStr, time::Duration};
+
+use libremarkable::{
+    cgmath::{EuclideanSpace, InnerSpace, Point2, Vector2},
+    framebuffer::common::{color, mxcfb_rect},
+};
+use pb::{
+    drawing,
+    proto::hypercards::{drawing::Color, Drawing},
+};
+use tokio::time::sleep;
+
+use crate::{
+    raster::{draw_dynamic_bezier, Knot},
+    surface::{self, Refresh, Surface, Waveform},
+};
+
+/// Samples per bezier curve.
+const BEZIER_SAMPLES: i32 = 10;
+
+/// Pressure the classic ballpoint paints at its tip size.
+const NOMINAL_PRESSURE: f32 = 2048.;
+
+/// Largest absolute tilt the digitizer reports, in hundredths of a degree.
+const MAX_TILT: f32 = 9000.;
+
+/// Shape of the ink a pen lays down.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Brush {
+    /// Wider as the pen presses harder
+    #[default]
+    Ballpoint,
+    /// As wide as its tip, however hard the pen presses
+    Fineliner,
+    /// Twice as wide, barely minding pressure
+    Marker,
+    /// A flat nib, held the way the pen tilts: thin along it, wide across it
+    Calligraphy,
+    /// Grainy, darker as the pen presses harder
+    Pencil,
+}
+
+/// How pen pressure maps onto ink width, as a factor of the tip size.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum PressureCurve {
+    /// Proportional to pressure: `pressure / 2048`
+    #[default]
+    Linear,
+    /// `(pressure / 2048) ^ gamma`: below 1 for light hands, above 1 for heavy ones
+    Gamma(f32),
+    /// Pressure is ignored
+    Flat,
+}
+
+impl PressureCurve {
+    #[must_use]
+    pub fn apply(&self, pressure: i32) -> f32 {
+        let p = pressure.max(0) as f32 / NOMINAL_PRESSURE;
+        match self {
+            Self::Linear => p,
+            Self::Gamma(gamma) => p.powf(*gamma),
+            Self::Flat => 1.,
+        }
+    }
+}
+
+/// `linear`, `flat` or a gamma, e.g. `0.5`.
+impl FromStr for PressureCurve {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "linear" => Ok(Self::Linear),
+            "flat" => Ok(Self::Flat),
+            _ => match s.parse::<f32>() {
+                Ok(gamma) if gamma > 0. => Ok(Self::Gamma(gamma)),
+                _ => Err(format!("expected linear, flat or a positive gamma, got {s:?}")),
+            },
+        }
+    }
+}
+
+/// Where the pen was, how it pressed and tilted, with what tip size.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sample {
+    pub pos: Point2<f32>,
+    pub pressure: i32,
+    pub width: u32,
+    /// Towards where the pen leans, each axis within `-1..=1`.
+    pub tilt: Vector2<f32>,
+}
+
+impl Sample {
+    #[must_use]
+    pub fn new(pos: Point2<f32>, pressure: i32, width: u32) -> Self {
+        Self { pos, pressure, width, tilt: Vector2 { x: 0., y: 0. } }
+    }
+
+    /// A sample as `WacomEvent::Draw` tells it, tilts being signed.
+    #[must_use]
+    pub fn from_wacom(pos: Point2<f32>, pressure: u16, tilt: Vector2<u16>, width: u32) -> Self {
+        let signed = |t: u16| f32::from(t as i16) / MAX_TILT;
+        Self {
+            tilt: Vector2 { x: signed(tilt.x), y: signed(tilt.y) },
+            ..Self::new(pos, pressure.into(), width)
+        }
+    }
+}
+
+impl From<drawing::Point> for Sample {
+    fn from(p: drawing::Point) -> Self {
+        Self::new(Point2 { x: p.x, y: p.y }, p.pressure, p.width)
+    }
+}
+
+/// Every 3 consecutive samples of a drawing: nothing for less than 3 points
+/// or mismatched lengths.
+pub fn windows(d: &Drawing) -> impl Iterator<Item = [Sample; 3]> {
+    let samples: Vec<Sample> =
+        d.points().map(|ps| ps.map(Sample::from).collect()).unwrap_or_default();
+    (0..samples.len().saturating_sub(2)).map(move |i| [samples[i], samples[i + 1], samples[i + 2]])
+}
+
+/// The last samples of a stroke being drawn, ready to paint 3 by 3.
+#[derive(Debug, Default)]
+pub struct Trail {
+    samples: VecDeque<Sample>,
+}
+
+impl Trail {
+    /// Adds a sample, returning the 3 to paint once there are enough.
+    pub fn push(&mut self, s: Sample) -> Option<[Sample; 3]> {
+        self.samples.push_back(s);
+        if self.samples.len() < 3 {
+            return None;
+        }
+        let first = self.samples.pop_front()?;
+        Some([first, self.samples[0], self.samples[1]])
+    }
+
+    /// Forgets samples, e.g. once the pen lifts.
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+}
+
+/// A brush, a pressure curve and a color.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ink {
+    pub brush: Brush,
+    pub curve: PressureCurve,
+    pub color: color,
+}
+
+impl Default for Ink {
+    fn default() -> Self {
+        Self::new(color::BLACK)
+    }
+}
+
+impl Ink {
+    /// A ballpoint with a linear pressure curve.
+    #[must_use]
+    pub fn new(color: color) -> Self {
+        Self { brush: Brush::default(), curve: PressureCurve::default(), color }
+    }
+
+    /// How drawings sent around are painted: with a ballpoint of their color.
+    #[must_use]
+    pub fn of(d: &Drawing) -> Self {
+        Self::new(match d.color() {
+            Color::White => color::WHITE,
+            _ => color::BLACK,
+        })
+    }
+
+    #[must_use]
+    pub fn with_brush(self, brush: Brush) -> Self {
+        Self { brush, ..self }
+    }
+
+    #[must_use]
+    pub fn with_curve(self, curve: PressureCurve) -> Self {
+        Self { curve, ..self }
+    }
+
+    /// Ink radius at `s`, for a stroke heading towards `heading`.
+    fn radius(&self, s: &Sample, heading: Vector2<f32>) -> f32 {
+        let p = self.curve.apply(s.pressure);
+        let factor = match self.brush {
+            Brush::Ballpoint => p,
+            Brush::Fineliner => 1.,
+            Brush::Marker => 1.5 + p / 2.,
+            Brush::Calligraphy => {
+                // The nib lies across where the pen leans, or at 45° held upright
+                let nib = if s.tilt.magnitude2() > 1e-4 {
+                    Vector2 { x: -s.tilt.y, y: s.tilt.x }.normalize()
+                } else {
+                    Vector2 { x: 1., y: -1. }.normalize()
+                };
+                let across = if heading.magnitude2() > 0. {
+                    heading.normalize().perp_dot(nib).abs()
+                } else {
+                    1.
+                };
+                p * (0.2 + 1.3 * across)
+            }
+            Brush::Pencil => p * 0.75,
+        };
+        s.width as f32 * factor / 2.
+    }
+
+    /// The (start, ctrl, end) bezier curve 3 consecutive samples paint.
+    #[must_use]
+    pub fn knots(&self, [s0, s1, s2]: [Sample; 3]) -> [Knot; 3] {
+        let heading = s2.pos - s0.pos;
+        let [r0, r1, r2] = [s0, s1, s2].map(|s| self.radius(&s, heading));
+        [(s2.pos.midpoint(s1.pos), r2 + r1), (s1.pos, r1 * 2.), (s1.pos.midpoint(s0.pos), r1 + r0)]
+    }
+
+    /// Rasterizes 3 consecutive samples, returning the area written to.
+    pub fn rasterize<F>(&self, samples: [Sample; 3], write_pixel: &mut F) -> mxcfb_rect
+    where
+        F: FnMut(Point2<i32>),
+    {
+        let [start, ctrl, end] = self.knots(samples);
+        if self.brush != Brush::Pencil || self.color == color::WHITE {
+            return draw_dynamic_bezier(write_pixel, start, ctrl, end, BEZIER_SAMPLES);
+        }
+        let pressure = samples.iter().map(|s| s.pressure).sum::<i32>() / 3;
+        let density = (0.3 + 0.5 * self.curve.apply(pressure)).min(1.);
+        let mut grainy = |p: Point2<i32>| {
+            if dither(p) < density {
+                write_pixel(p)
+            }
+        };
+        draw_dynamic_bezier(&mut grainy, start, ctrl, end, BEZIER_SAMPLES)
+    }
+
+    /// Paints 3 consecutive samples then refreshes them, returning the area painted.
+    pub fn paint<S>(&self, s: &mut S, samples: [Sample; 3], refresh: Refresh) -> mxcfb_rect
+    where
+        S: Surface + ?Sized,
+    {
+        let rect = self.rasterize(samples, &mut |p| s.write_pixel(p, self.color));
+        let (w, h) = s.dimensions();
+        let rect = surface::clip(&rect, w, h);
+        s.refresh(&rect, Waveform::Fast, refresh);
+        rect
+    }
+}
+
+/// How drawings show up.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Pace {
+    /// All at once, with a single refresh
+    #[default]
+    Batched,
+    /// Curve by curve, this long apart, as if being drawn
+    Animated(Duration),
+}
+
+/// Paints a drawing (with its own ink) without refreshing, returning the area painted.
+pub fn draw<S>(s: &mut S, d: &Drawing) -> mxcfb_rect
+where
+    S: Surface + ?Sized,
+{
+    let ink = Ink::of(d);
+    let rect = windows(d).fold(mxcfb_rect::invalid(), |rect, samples| {
+        rect.merge_rect(&ink.rasterize(samples, &mut |p| s.write_pixel(p, ink.color)))
+    });
+    let (w, h) = s.dimensions();
+    surface::clip(&rect, w, h)
+}
+
+/// Paints a drawing (with its own ink) and shows it, at `pace`.
+/// Returns the area painted.
+pub async fn paint<S>(s: &mut S, d: &Drawing, pace: Pace, refresh: Refresh) -> mxcfb_rect
+where
+    S: Surface + ?Sized,
+{
+    match pace {
+        Pace::Batched => {
+            let rect = draw(s, d);
+            s.refresh(&rect, Waveform::for_area(&rect), refresh);
+            rect
+        }
+        Pace::Animated(step) => {
+            let ink = Ink::of(d);
+            let mut rect = mxcfb_rect::invalid();
+            for (i, samples) in windows(d).enumerate() {
+                if i != 0 {
+                    sleep(step).await;
+                }
+                rect = rect.merge_rect(&ink.paint(s, samples, refresh));
+            }
+            rect
+        }
+    }
+}
+
+/// Ordered dithering threshold of a pixel, within `0..1`.
+fn dither(p: Point2<i32>) -> f32 {
+    const BAYER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+    (f32::from(BAYER[(p.y & 3) as usize][(p.x & 3) as usize]) + 0.5) / 16.
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn line(n: usize) -> Drawing {
+        Drawing {
+            xs: (0..n).map(|i| 100. + 50. * i as f32).collect(),
+            ys: vec![10.; n],
+            pressures: vec![2048; n],
+            widths: vec![10; n],
+            color: Color::Black.into(),
+        }
+    }
+
+    fn pixels(ink: &Ink, samples: [Sample; 3]) -> usize {
+        let mut n = 0;
+        ink.rasterize(samples, &mut |_| n += 1);
+        n
+    }
+
+    #[test]
+    fn cuts_drawings_into_overlapping_curves() {
+        let ws: Vec<_> = windows(&line(4)).collect();
+        assert_eq!(ws.len(), 2);
+        let [start, ctrl, end] = Ink::default().knots(ws[0]);
+        assert_eq!(start, (Point2 { x: 175., y: 10. }, 10.));
+        assert_eq!(ctrl, (Point2 { x: 150., y: 10. }, 10.));
+        assert_eq!(end, (Point2 { x: 125., y: 10. }, 10.));
+
+        let mut short = line(4);
+        short.widths.pop();
+        assert_eq!(windows(&short).count(), 0);
+        assert_eq!(windows(&line(2)).count(), 0);
+    }
+
+    #[test]
+    fn trails_paint_3_by_3() {
+        let mut trail = Trail::default();
+        let s = |x| Sample::new(Point2 { x, y: 0. }, 2048, 2);
+        assert_eq!(trail.push(s(1.)), None);
+        assert_eq!(trail.push(s(2.)), None);
+        assert_eq!(trail.push(s(3.)), Some([s(1.), s(2.), s(3.)]));
+        assert_eq!(trail.push(s(4.)), Some([s(2.), s(3.), s(4.)]));
+        trail.clear();
+        assert_eq!(trail.push(s(5.)), None);
+    }
+
+    #[test]
+    fn curves_pressure() {
+        assert_eq!(PressureCurve::Linear.apply(1024), 0.5);
+        assert_eq!(PressureCurve::Linear.apply(-5), 0.);
+        assert_eq!(PressureCurve::Gamma(0.5).apply(512), 0.5);
+        assert_eq!(PressureCurve::Flat.apply(12), 1.);
+        assert_eq!("flat".parse(), Ok(PressureCurve::Flat));
+        assert_eq!("2".parse(), Ok(PressureCurve::Gamma(2.)));
+        assert!("-1".parse::<PressureCurve>().is_err());
+    }
+
+    #[test]
+    fn brushes_shape_ink() {
+        let light = windows(&Drawing { pressures: vec![512; 3], ..line(3) }).next().unwrap();
+        let ink = Ink::default();
+        let radius = |ink: Ink| ink.knots(light)[1].1;
+        assert_eq!(radius(ink), 2.5);
+        assert_eq!(radius(ink.with_brush(Brush::Fineliner)), 10.);
+        assert_eq!(radius(ink.with_curve(PressureCurve::Flat)), 10.);
+        assert!(radius(ink.with_brush(Brush::Marker)) > 15.);
+
+        // Strokes along the nib are thinner than strokes across it
+        let calligraphy = ink.with_brush(Brush::Calligraphy);
+        let lean = |tilt| light.map(|s| Sample { tilt, ..s });
+        let along = calligraphy.knots(lean(Vector2 { x: 0., y: 1. }))[1].1;
+        let across = calligraphy.knots(lean(Vector2 { x: 1., y: 0. }))[1].1;
+        assert!(along * 3. < across, "{along} vs {across}");
+
+        // Pencils leave gaps, erasers don't
+        let pencil = ink.with_brush(Brush::Pencil).with_curve(PressureCurve::Flat);
+        let eraser = Ink { color: color::WHITE, ..pencil };
+        assert!(pixels(&pencil, light) * 10 < pixels(&eraser, light) * 9);
+    }
+
+    #[tokio::test]
+    async fn refreshes_once_or_curve_by_curve() {
+        let mut canvas = crate::raster::Canvas::default();
+        let batched = paint(&mut canvas, &line(10), Pace::Batched, Refresh::Async).await;
+        assert_eq!(canvas.refreshes(), [(batched, Waveform::Fast)]);
+
+        let mut canvas = crate::raster::Canvas::default();
+        let animated = Pace::Animated(Duration::ZERO);
+        assert_eq!(paint(&mut canvas, &line(10), animated, Refresh::Async).await, batched);
+        assert_eq!(canvas.refreshes().len(), 8);
+    }
+
+    #[tokio::test]
+    async fn refreshes_nothing_for_too_few_points() {
+        let mut canvas = crate::raster::Canvas::default();
+        let painted = paint(&mut canvas, &line(2), Pace::Batched, Refresh::Async).await;
+        assert!(crate::surface::is_empty(&painted));
+        assert_eq!(canvas.refreshes(), []);
+    }
+
+    #[test]
+    fn reads_wacom_tilts() {
+        let s = Sample::from_wacom(
+            Point2 { x: 1., y: 2. },
+            300,
+            Vector2 { x: 4500, y: -4500i16 as u16 },
+            2,
+        );
+        assert_eq!(s.pos, Point2 { x: 1., y: 2. });
+        assert_eq!(s.tilt, Vector2 { x: 0.5, y: -0.5 });
+    }
+}
@@ -1,7 +1,18 @@
+pub mod bots;
 pub mod buttons;
+pub mod client;
 pub mod fonts;
+#[cfg(any(test, feature = "golden"))]
+pub mod golden;
+pub mod hypercard;
+pub mod ink;
 pub mod modes;
+pub mod raster;
+pub mod recording;
 pub mod shapes;
+pub mod simulator;
 pub mod strokes;
+pub mod surface;
+pub mod whiteboard;
 
 pub mod unipen;
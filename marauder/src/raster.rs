@@ -0,0 +1,383 @@
+//! Paint strokes without a framebuffer
+//!
+//! A port of libremarkable's (private) `framebuffer::graphics` rasterizer, so that
+//! images made off-device have the very same pixels as what tablets show, and
+//! [`Canvas`]: a [`Surface`] to paint them on.
+//!
+use libremarkable::{
+    cgmath::{InnerSpace, Point2, Vector2},
+    dimensions::{DISPLAYHEIGHT, DISPLAYWIDTH},
+    framebuffer::common::{color, mxcfb_rect},
+    image::{
+        imageops::{self, FilterType},
+        DynamicImage, GrayImage, Luma, Pixel, Rgb, RgbImage,
+    },
+};
+use pb::proto::hypercards::Drawing;
+
+use crate::{
+    ink,
+    surface::{self, Refresh, Surface, Waveform},
+};
+
+/// A point along with the stroke's diameter there.
+pub type Knot = (Point2<f32>, f32);
+
+/// An in-memory grayscale canvas the size of the tablet's display.
+pub struct Canvas {
+    img: GrayImage,
+    refreshes: Vec<(mxcfb_rect, Waveform)>,
+}
+
+impl Default for Canvas {
+    fn default() -> Self {
+        Self::new(DISPLAYWIDTH.into(), DISPLAYHEIGHT.into())
+    }
+}
+
+impl Canvas {
+    #[must_use]
+    pub fn new(width: u32, height: u32) -> Self {
+        Self { img: GrayImage::from_pixel(width, height, Luma([u8::MAX])), refreshes: vec![] }
+    }
+
+    #[must_use]
+    pub fn image(&self) -> &GrayImage {
+        &self.img
+    }
+
+    /// Areas refreshed so far, oldest first.
+    #[must_use]
+    pub fn refreshes(&self) -> &[(mxcfb_rect, Waveform)] {
+        &self.refreshes
+    }
+
+    /// A resized copy, e.g. `0.5` for half the width and height.
+    #[must_use]
+    pub fn scaled(&self, scale: f32) -> GrayImage {
+        let (w, h) = self.img.dimensions();
+        let (w, h) = ((w as f32 * scale).round() as u32, (h as f32 * scale).round() as u32);
+        imageops::resize(&self.img, w.max(1), h.max(1), FilterType::Triangle)
+    }
+
+    /// Paints a drawing, returning the area that changed.
+    pub fn paint(&mut self, d: &Drawing) -> mxcfb_rect {
+        ink::draw(self, d)
+    }
+}
+
+/// Pixels show as soon as they're written: refreshes only get recorded,
+/// skipping empty ones just as the display would.
+impl Surface for Canvas {
+    fn dimensions(&self) -> (u32, u32) {
+        self.img.dimensions()
+    }
+
+    fn write_pixel(&mut self, p: Point2<i32>, c: color) {
+        let (w, h) = self.img.dimensions();
+        if let (Ok(x), Ok(y)) = (u32::try_from(p.x), u32::try_from(p.y)) {
+            if x < w && y < h {
+                self.img.put_pixel(x, y, Rgb(c.to_rgb8()).to_luma());
+            }
+        }
+    }
+
+    fn refresh(&mut self, rect: &mxcfb_rect, waveform: Waveform, _: Refresh) {
+        if !surface::is_empty(rect) {
+            self.refreshes.push((*rect, waveform));
+        }
+    }
+
+    fn snapshot(&self, rect: &mxcfb_rect) -> Option<RgbImage> {
+        let (w, h) = self.img.dimensions();
+        if rect.left + rect.width > w || rect.top + rect.height > h {
+            return None;
+        }
+        let img = imageops::crop_imm(&self.img, rect.left, rect.top, rect.width, rect.height);
+        Some(DynamicImage::ImageLuma8(img.to_image()).to_rgb8())
+    }
+
+    fn clear(&mut self) {
+        self.img.fill(u8::MAX);
+        let (w, h) = self.img.dimensions();
+        self.refresh(&surface::everything(w, h), Waveform::Quality, Refresh::Wait);
+    }
+}
+
+/// Fills the area swept by a quadratic bezier curve of varying width.
+pub fn draw_dynamic_bezier<F>(
+    write_pixel: &mut F,
+    startpt: Knot,
+    ctrlpt: Knot,
+    endpt: Knot,
+    samples: i32,
+) -> mxcfb_rect
+where
+    F: FnMut(Point2<i32>),
+{
+    let mut left_edge = Vec::<Point2<i32>>::new();
+    let mut right_edge = Vec::<Point2<i32>>::new();
+    let mut prev_left_pt = Point2 { x: i32::MIN, y: i32::MIN };
+    let mut prev_right_pt = Point2 { x: i32::MIN, y: i32::MIN };
+    for (t, pt) in sample_bezier(startpt.0, ctrlpt.0, endpt.0, samples) {
+        // interpolate width
+        let width = 2.0
+            * if t < 0.5 {
+                startpt.1 * (0.5 - t) + ctrlpt.1 * t
+            } else {
+                ctrlpt.1 * (1.0 - t) + endpt.1 * (t - 0.5)
+            };
+
+        // calculate tangent
+        let velocity = 2.0 * (1.0 - t) * (ctrlpt.0 - startpt.0) + 2.0 * t * (endpt.0 - ctrlpt.0);
+        let speed = velocity.magnitude();
+        let tangent = if speed > 0.0 {
+            velocity / speed
+        } else {
+            // handle case where control point == start/end point
+            let extent = startpt.0 - endpt.0;
+            if extent.magnitude() > 0.0 {
+                extent / extent.magnitude()
+            } else {
+                // all points are the same, so no tangent exists
+                Vector2 { x: 0.0, y: 0.0 }
+            }
+        };
+        let normal = Vector2 { x: -tangent.y * width / 2.0, y: tangent.x * width / 2.0 };
+        // Samples that aren't on any pixel (e.g. NaN) have nothing to paint
+        let (Some(left_pt), Some(right_pt)) = ((pt + normal).cast(), (pt - normal).cast()) else {
+            continue;
+        };
+        if left_pt != prev_left_pt {
+            left_edge.push(left_pt);
+            prev_left_pt = left_pt;
+        }
+        if right_pt != prev_right_pt {
+            right_edge.push(right_pt);
+            prev_right_pt = right_pt;
+        }
+    }
+    right_edge.reverse();
+    left_edge.append(&mut right_edge);
+    if left_edge.len() > 2 {
+        fill_polygon(write_pixel, &left_edge)
+    } else {
+        mxcfb_rect::invalid()
+    }
+}
+
+fn sample_bezier(
+    startpt: Point2<f32>,
+    ctrlpt: Point2<f32>,
+    endpt: Point2<f32>,
+    samples: i32,
+) -> Vec<(f32, Point2<f32>)> {
+    (0..samples)
+        .map(|i| {
+            let t = (i as f32) / (samples - 1) as f32;
+            let precisept = Point2 {
+                x: (1.0 - t).powf(2.0) * startpt.x
+                    + 2.0 * (1.0 - t) * t * ctrlpt.x
+                    + t.powf(2.0) * endpt.x,
+                y: (1.0 - t).powf(2.0) * startpt.y
+                    + 2.0 * (1.0 - t) * t * ctrlpt.y
+                    + t.powf(2.0) * endpt.y,
+            };
+            (t, precisept)
+        })
+        .collect()
+}
+
+/// Scan-line polygon filling with the nonzero winding rule.
+/// https://hackernoon.com/computer-graphics-scan-line-polygon-fill-algorithm-3cb47283df6
+fn fill_polygon<F>(write_pixel: &mut F, points: &[Point2<i32>]) -> mxcfb_rect
+where
+    F: FnMut(Point2<i32>),
+{
+    #[derive(Debug, Copy, Clone)]
+    struct EdgeBucket {
+        ymax: i32,
+        ymin: i32,
+        x: i32,
+        sign: i32,
+        direction: i32,
+        dx: i32,
+        dy: i32,
+        sum: i32,
+    }
+
+    let num_edges = points.len();
+    let mut edge_table: Vec<EdgeBucket> = (0..num_edges)
+        .map(|i| {
+            let p0 = points[i];
+            let p1 = points[(i + 1) % num_edges];
+            let (lower, higher, direction) = if p0.y < p1.y { (p0, p1, 1) } else { (p1, p0, -1) };
+            EdgeBucket {
+                ymax: higher.y,
+                ymin: lower.y,
+                x: lower.x,
+                sign: if lower.x > higher.x { 1 } else { -1 },
+                direction,
+                dx: (higher.x - lower.x).abs(),
+                dy: (higher.y - lower.y).abs(),
+                sum: 0,
+            }
+        })
+        .collect();
+    edge_table.sort_unstable_by_key(|p| p.ymin);
+
+    let mut active_list = Vec::<EdgeBucket>::new();
+    let mut scanline = edge_table[0].ymin;
+    while !edge_table.is_empty() {
+        // remove edges that end on the current scanline
+        edge_table.retain(|edge| edge.ymax != scanline);
+        active_list.retain(|edge| edge.ymax != scanline);
+
+        // push edges that start on this scanline to the active list
+        for edge in edge_table.iter() {
+            if edge.ymin == scanline {
+                active_list.push(*edge);
+            }
+        }
+        active_list.sort_unstable_by_key(|p| p.x);
+
+        let mut prev_x = 0;
+        let mut winding_count = 0;
+        for edge in active_list.iter() {
+            if winding_count != 0 {
+                for x in prev_x..edge.x {
+                    write_pixel(Point2 { x, y: scanline });
+                }
+            }
+            prev_x = edge.x;
+            winding_count += edge.direction;
+        }
+
+        scanline += 1;
+
+        // adjust the x of each edge based on its gradient
+        for edge in &mut active_list {
+            if edge.dx != 0 {
+                edge.sum += edge.dx;
+            }
+            while edge.sum >= edge.dy {
+                edge.x -= edge.sign;
+                edge.sum -= edge.dy;
+            }
+        }
+    }
+
+    // Ink spills past the edges: only what's on the display counts
+    let (min_x, max_x) = (points.iter().map(|p| p.x).min(), points.iter().map(|p| p.x).max());
+    let (min_y, max_y) = (points.iter().map(|p| p.y).min(), points.iter().map(|p| p.y).max());
+    let (min_x, max_x, min_y, max_y) = (
+        min_x.unwrap_or(0).max(0),
+        max_x.unwrap_or(0).max(0),
+        min_y.unwrap_or(0).max(0),
+        max_y.unwrap_or(0).max(0),
+    );
+    mxcfb_rect {
+        top: min_y as u32,
+        left: min_x as u32,
+        width: (max_x - min_x) as u32,
+        height: (max_y - min_y) as u32,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pb::proto::hypercards::drawing::Color;
+
+    use super::*;
+    use crate::{fonts, strokes::Stroke};
+
+    fn line(y: f32, color: Color) -> Drawing {
+        Drawing {
+            xs: vec![100., 150., 200., 250.],
+            ys: vec![y; 4],
+            pressures: vec![2048; 4],
+            widths: vec![10; 4],
+            color: color.into(),
+        }
+    }
+
+    fn black_pixels(canvas: &Canvas) -> usize {
+        canvas.image().pixels().filter(|p| p.0[0] == 0).count()
+    }
+
+    #[test]
+    fn paints_then_erases() {
+        let mut canvas = Canvas::default();
+        assert_eq!(canvas.image().dimensions(), (1404, 1872));
+
+        let rect = canvas.paint(&line(500., Color::Black));
+        assert_eq!((rect.left, rect.top), (125, 494));
+        assert_eq!((rect.width, rect.height), (100, 11));
+        let painted = black_pixels(&canvas);
+        assert!((900..=1100).contains(&painted), "{painted}");
+        assert_eq!(canvas.image().get_pixel(150, 500).0, [0]);
+        assert_eq!(canvas.image().get_pixel(150, 520).0, [u8::MAX]);
+
+        canvas.paint(&line(500., Color::White));
+        assert_eq!(black_pixels(&canvas), 0);
+    }
+
+    #[test]
+    fn paints_strokes_glyphs_and_assets() {
+        let mut canvas = Canvas::default();
+        let mut stroke = Stroke::default();
+        for x in [10., 20., 30., 40.] {
+            stroke.push_back(Point2 { x, y: 1000. }, 2048);
+        }
+        stroke.set_color(color::BLACK);
+        stroke.draw(&mut canvas);
+        assert_eq!(canvas.image().get_pixel(25, 1000).0, [0]);
+
+        let font = fonts::emsdelight_swash_caps().unwrap();
+        let before = black_pixels(&canvas);
+        for d in fonts::write(&font, "HI", (100., 1200.), 60., Color::Black) {
+            canvas.paint(&d);
+        }
+        let glyphs = black_pixels(&canvas) - before;
+        assert!(glyphs > 100, "{glyphs}");
+
+        let before = black_pixels(&canvas);
+        for d in drawings::title_whiteboard::f(Color::Black) {
+            canvas.paint(&d);
+        }
+        assert!(black_pixels(&canvas) > before);
+    }
+
+    #[test]
+    fn clips_to_the_canvas() {
+        let mut canvas = Canvas::new(10, 10);
+        canvas.paint(&line(-100., Color::Black));
+        canvas.paint(&line(5., Color::Black));
+        assert_eq!(black_pixels(&canvas), 0);
+    }
+
+    #[test]
+    fn keeps_areas_on_the_canvas_at_its_edges() {
+        let mut canvas = Canvas::default();
+        let mut edge = line(0., Color::Black);
+        edge.xs = vec![0.; 4];
+        edge.ys = vec![100., 150., 200., 250.];
+        let rect = canvas.paint(&edge);
+        assert_eq!(rect.left, 0);
+        assert!(rect.width > 0 && rect.width < 10, "{rect:?}");
+        assert_eq!(canvas.image().get_pixel(0, 150).0, [0]);
+
+        let corner = canvas.paint(&line(0., Color::Black)).merge_rect(&rect);
+        assert_eq!((corner.left, corner.top), (0, 0));
+    }
+
+    #[test]
+    fn skips_samples_off_any_pixel() {
+        let mut n = 0;
+        let nan = (Point2 { x: f32::NAN, y: 10. }, 5.);
+        let ok = (Point2 { x: 10., y: 10. }, 5.);
+        let rect = draw_dynamic_bezier(&mut |_| n += 1, nan, ok, ok, 10);
+        assert_eq!(n, 0);
+        assert!(surface::is_empty(&rect));
+    }
+}
@@ -0,0 +1,306 @@
+//! Recordings of input events, to reproduce sessions off-device
+//!
+//! A [`Recorder`] appends each [`InputEvent`] it is handed, timestamped, to a
+//! JSON Lines file. [`replay`] feeds these back to an event handler, either at
+//! the pace they were recorded or all at once: handlers painting onto a
+//! [`Surface`](crate::surface::Surface) then run the same on a plain Linux
+//! machine as on the tablet.
+//!
+//! ```json
+//! {"at":0,"kind":"instrument","pen":"pen","state":true}
+//! {"at":12,"kind":"draw","x":210.5,"y":480.25,"pressure":1830,"tilt":[1200,64336]}
+//! {"at":40,"kind":"finger","phase":"press","id":3,"x":120,"y":30}
+//! {"at":95,"kind":"button","button":"middle","pressed":true}
+//! ```
+//!
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::Path,
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use libremarkable::{
+    cgmath::Point2,
+    input::{Finger, GPIOEvent, InputEvent, MultitouchEvent, PhysicalButton, WacomEvent, WacomPen},
+};
+use serde::{Deserialize, Serialize};
+use serde_jsonlines::JsonLinesWriter;
+
+/// An event, some milliseconds into a recording.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    pub at: u64,
+    #[serde(flatten)]
+    pub event: Event,
+}
+
+/// The [`InputEvent`]s worth recording, as written down.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Event {
+    Draw { x: f32, y: f32, pressure: u16, tilt: (u16, u16) },
+    Hover { x: f32, y: f32, distance: u16, tilt: (u16, u16) },
+    Instrument { pen: Pen, state: bool },
+    Finger { phase: Phase, id: i32, x: u16, y: u16 },
+    Button { button: Button, pressed: bool },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Pen {
+    Pen,
+    Rubber,
+    Touch,
+    Stylus,
+    Stylus2,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    Press,
+    Move,
+    Release,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Button {
+    Left,
+    Middle,
+    Right,
+    Power,
+    Wakeup,
+}
+
+impl Event {
+    /// `None` for events libremarkable could not make sense of.
+    #[must_use]
+    pub fn of(e: &InputEvent) -> Option<Self> {
+        Some(match *e {
+            InputEvent::WacomEvent { event } => match event {
+                WacomEvent::Draw { position, pressure, tilt } => {
+                    Self::Draw { x: position.x, y: position.y, pressure, tilt: tilt.into() }
+                }
+                WacomEvent::Hover { position, distance, tilt } => {
+                    Self::Hover { x: position.x, y: position.y, distance, tilt: tilt.into() }
+                }
+                WacomEvent::InstrumentChange { pen, state } => {
+                    Self::Instrument { pen: pen.into(), state }
+                }
+                WacomEvent::Unknown => return None,
+            },
+            InputEvent::MultitouchEvent { event } => {
+                let (phase, finger) = match event {
+                    MultitouchEvent::Press { finger } => (Phase::Press, finger),
+                    MultitouchEvent::Move { finger } => (Phase::Move, finger),
+                    MultitouchEvent::Release { finger } => (Phase::Release, finger),
+                    MultitouchEvent::Unknown => return None,
+                };
+                Self::Finger { phase, id: finger.tracking_id, x: finger.pos.x, y: finger.pos.y }
+            }
+            InputEvent::GPIO { event } => match event {
+                GPIOEvent::Press { button } => {
+                    Self::Button { button: button.into(), pressed: true }
+                }
+                GPIOEvent::Unpress { button } => {
+                    Self::Button { button: button.into(), pressed: false }
+                }
+                GPIOEvent::Unknown => return None,
+            },
+            InputEvent::Unknown {} => return None,
+        })
+    }
+}
+
+impl From<Event> for InputEvent {
+    fn from(e: Event) -> Self {
+        match e {
+            Event::Draw { x, y, pressure, tilt } => {
+                let (position, tilt) = (Point2 { x, y }, tilt.into());
+                Self::WacomEvent { event: WacomEvent::Draw { position, pressure, tilt } }
+            }
+            Event::Hover { x, y, distance, tilt } => {
+                let (position, tilt) = (Point2 { x, y }, tilt.into());
+                Self::WacomEvent { event: WacomEvent::Hover { position, distance, tilt } }
+            }
+            Event::Instrument { pen, state } => {
+                Self::WacomEvent { event: WacomEvent::InstrumentChange { pen: pen.into(), state } }
+            }
+            Event::Finger { phase, id, x, y } => {
+                let mut finger = Finger::default();
+                finger.tracking_id = id;
+                finger.pos = Point2 { x, y };
+                finger.pressed = phase != Phase::Release;
+                let event = match phase {
+                    Phase::Press => MultitouchEvent::Press { finger },
+                    Phase::Move => MultitouchEvent::Move { finger },
+                    Phase::Release => MultitouchEvent::Release { finger },
+                };
+                Self::MultitouchEvent { event }
+            }
+            Event::Button { button, pressed } => {
+                let button = button.into();
+                let event = if pressed {
+                    GPIOEvent::Press { button }
+                } else {
+                    GPIOEvent::Unpress { button }
+                };
+                Self::GPIO { event }
+            }
+        }
+    }
+}
+
+impl From<WacomPen> for Pen {
+    fn from(pen: WacomPen) -> Self {
+        match pen {
+            WacomPen::ToolPen => Self::Pen,
+            WacomPen::ToolRubber => Self::Rubber,
+            WacomPen::Touch => Self::Touch,
+            WacomPen::Stylus => Self::Stylus,
+            WacomPen::Stylus2 => Self::Stylus2,
+        }
+    }
+}
+
+impl From<Pen> for WacomPen {
+    fn from(pen: Pen) -> Self {
+        match pen {
+            Pen::Pen => Self::ToolPen,
+            Pen::Rubber => Self::ToolRubber,
+            Pen::Touch => Self::Touch,
+            Pen::Stylus => Self::Stylus,
+            Pen::Stylus2 => Self::Stylus2,
+        }
+    }
+}
+
+impl From<PhysicalButton> for Button {
+    fn from(button: PhysicalButton) -> Self {
+        match button {
+            PhysicalButton::LEFT => Self::Left,
+            PhysicalButton::MIDDLE => Self::Middle,
+            PhysicalButton::RIGHT => Self::Right,
+            PhysicalButton::POWER => Self::Power,
+            PhysicalButton::WAKEUP => Self::Wakeup,
+        }
+    }
+}
+
+impl From<Button> for PhysicalButton {
+    fn from(button: Button) -> Self {
+        match button {
+            Button::Left => Self::LEFT,
+            Button::Middle => Self::MIDDLE,
+            Button::Right => Self::RIGHT,
+            Button::Power => Self::POWER,
+            Button::Wakeup => Self::WAKEUP,
+        }
+    }
+}
+
+/// Writes events down as they come, flushing each so that crashes are captured too.
+pub struct Recorder {
+    start: Instant,
+    out: JsonLinesWriter<BufWriter<File>>,
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> Result<Self> {
+        let out = JsonLinesWriter::new(BufWriter::new(File::create(path)?));
+        Ok(Self { start: Instant::now(), out })
+    }
+
+    pub fn record(&mut self, e: &InputEvent) -> Result<()> {
+        let Some(event) = Event::of(e) else { return Ok(()) };
+        let at = self.start.elapsed().as_millis() as u64;
+        self.out.write(&Entry { at, event })?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+pub fn read(path: &Path) -> Result<Vec<Entry>> {
+    Ok(serde_jsonlines::json_lines(path)?.collect::<std::io::Result<_>>()?)
+}
+
+/// How [`replay`] spaces events out.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Timing {
+    /// As they were recorded
+    #[default]
+    Recorded,
+    /// Back to back: for tests
+    Immediate,
+}
+
+/// Hands each recorded event, in order, to `handle`.
+pub fn replay(entries: &[Entry], timing: Timing, mut handle: impl FnMut(InputEvent)) {
+    let start = Instant::now();
+    for entry in entries {
+        if timing == Timing::Recorded {
+            let due = Duration::from_millis(entry.at);
+            if let Some(wait) = due.checked_sub(start.elapsed()) {
+                sleep(wait);
+            }
+        }
+        handle(entry.event.into());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use libremarkable::cgmath::Vector2;
+
+    use super::*;
+
+    #[test]
+    fn records_then_replays_sessions() {
+        let events = [
+            InputEvent::WacomEvent {
+                event: WacomEvent::InstrumentChange { pen: WacomPen::ToolPen, state: true },
+            },
+            InputEvent::WacomEvent {
+                event: WacomEvent::Draw {
+                    position: Point2 { x: 210.5, y: 480.25 },
+                    pressure: 1830,
+                    tilt: Vector2 { x: 1200, y: 64336 },
+                },
+            },
+            InputEvent::Unknown {},
+            InputEvent::MultitouchEvent {
+                event: MultitouchEvent::Press {
+                    finger: {
+                        let mut f = Finger::default();
+                        f.tracking_id = 3;
+                        f.pos = Point2 { x: 120, y: 30 };
+                        f.pressed = true;
+                        f
+                    },
+                },
+            },
+            InputEvent::GPIO { event: GPIOEvent::Press { button: PhysicalButton::MIDDLE } },
+        ];
+
+        let path =
+            std::env::temp_dir().join(format!("marauder-session-{}.jsonl", std::process::id()));
+        let mut recorder = Recorder::create(&path).unwrap();
+        for e in &events {
+            recorder.record(e).unwrap();
+        }
+        let entries = read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(entries.len(), 4);
+        assert!(entries.windows(2).all(|w| w[0].at <= w[1].at));
+        assert_eq!(entries[2].event, Event::Finger { phase: Phase::Press, id: 3, x: 120, y: 30 });
+
+        let mut replayed = vec![];
+        replay(&entries, Timing::Immediate, |e| replayed.push(e));
+        let recorded: Vec<_> = events.into_iter().filter(|e| Event::of(e).is_some()).collect();
+        assert_eq!(replayed, recorded);
+    }
+}
@@ -1 +1,2 @@
 pub mod smileyface;
+pub mod wipe;
@@ -0,0 +1,32 @@
+use libremarkable::dimensions::{DISPLAYHEIGHT, DISPLAYWIDTH};
+use pb::proto::hypercards::{drawing::Color, Drawing};
+
+/// Eraser strokes sweeping the whole display, one per row.
+#[must_use]
+pub fn whole_display() -> Vec<Drawing> {
+    const WIDTH: u32 = 50; // Same as whiteboard's eraser
+    const PRESSURE: i32 = 2048;
+    const STEP: usize = 40;
+
+    let xs: Vec<f32> =
+        (0..=usize::from(DISPLAYWIDTH)).step_by(STEP / 2).map(|x| x as f32).collect();
+    let n = xs.len();
+    (0..=usize::from(DISPLAYHEIGHT))
+        .step_by(STEP)
+        .map(|y| Drawing {
+            xs: xs.clone(),
+            ys: vec![y as f32; n],
+            pressures: vec![PRESSURE; n],
+            widths: vec![WIDTH; n],
+            color: Color::White.into(),
+        })
+        .collect()
+}
+
+#[test]
+fn wipes_the_whole_display() {
+    let ds = whole_display();
+    assert_eq!(ds.len(), 47);
+    assert!(ds.iter().all(|d| d.xs.len() == 71 && d.color() == Color::White));
+    assert_eq!(ds.last().unwrap().ys[0], 1840.);
+}
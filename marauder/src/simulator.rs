@@ -0,0 +1,278 @@
+//! A reMarkable on the desktop
+//!
+//! [`Display`] stands in for the e-ink panel: what gets painted only shows once
+//! refreshed, in black & white only for [`Waveform::Fast`] and in grays for
+//! [`Waveform::Quality`]. [`Pointer`] and [`key`] turn mouse & keyboard into
+//! the pen, touch & button events the tablet would send.
+//!
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use libremarkable::{
+    cgmath::Point2,
+    framebuffer::common::{color, mxcfb_rect},
+    image::{GrayImage, Luma, RgbImage},
+    input::InputEvent,
+};
+
+use crate::{
+    raster::Canvas,
+    recording::{Button, Event, Pen, Phase},
+    surface::{self, Refresh, Surface, Waveform},
+};
+
+/// Pressure of mouse-drawn ink: half of what the pen reports at most.
+const PRESSURE: u16 = 2048;
+
+/// Hovering this far away makes apps let go of the line being drawn.
+const HOVER_DISTANCE: u16 = 20;
+
+/// An emulated display, the size of the tablet's.
+pub struct Display {
+    canvas: Canvas,
+    shown: GrayImage,
+    frames: u32,
+    changed: bool,
+}
+
+impl Default for Display {
+    fn default() -> Self {
+        let canvas = Canvas::default();
+        let shown = canvas.image().clone();
+        Self { canvas, shown, frames: 0, changed: true }
+    }
+}
+
+impl Display {
+    /// What the display shows.
+    #[must_use]
+    pub fn shown(&self) -> &GrayImage {
+        &self.shown
+    }
+
+    /// Whether anything got refreshed since last asked.
+    pub fn take_changed(&mut self) -> bool {
+        std::mem::take(&mut self.changed)
+    }
+
+    /// Writes what is shown to `dir/frame-NNNNN.png`, numbering frames from 0.
+    pub fn save_frame(&mut self, dir: &Path) -> Result<PathBuf> {
+        std::fs::create_dir_all(dir)?;
+        let path = dir.join(format!("frame-{:05}.png", self.frames));
+        self.shown.save(&path)?;
+        self.frames += 1;
+        Ok(path)
+    }
+}
+
+impl Surface for Display {
+    fn dimensions(&self) -> (u32, u32) {
+        self.canvas.dimensions()
+    }
+
+    fn write_pixel(&mut self, p: Point2<i32>, c: color) {
+        self.canvas.write_pixel(p, c);
+    }
+
+    fn refresh(&mut self, rect: &mxcfb_rect, waveform: Waveform, refresh: Refresh) {
+        if surface::is_empty(rect) {
+            return;
+        }
+        self.canvas.refresh(rect, waveform, refresh);
+        let (w, h) = self.dimensions();
+        let painted = self.canvas.image();
+        for y in rect.top..(rect.top + rect.height).min(h) {
+            for x in rect.left..(rect.left + rect.width).min(w) {
+                let Luma([v]) = *painted.get_pixel(x, y);
+                let v = match waveform {
+                    // DU only knows black from white
+                    Waveform::Fast if v < 128 => 0,
+                    Waveform::Fast => u8::MAX,
+                    Waveform::Quality => v,
+                };
+                self.shown.put_pixel(x, y, Luma([v]));
+            }
+        }
+        self.changed = true;
+    }
+
+    fn snapshot(&self, rect: &mxcfb_rect) -> Option<RgbImage> {
+        self.canvas.snapshot(rect)
+    }
+
+    fn clear(&mut self) {
+        self.canvas.clear();
+        let (w, h) = self.dimensions();
+        self.refresh(&surface::everything(w, h), Waveform::Quality, Refresh::Wait);
+    }
+}
+
+/// What a mouse button stands for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tool {
+    Pen,
+    Rubber,
+    Finger,
+}
+
+/// Turns mouse moves & clicks into pen or finger events.
+#[derive(Debug, Default)]
+pub struct Pointer {
+    down: Option<Tool>,
+}
+
+impl Pointer {
+    /// Events for the mouse being at `at`, with `down` the button held, if any.
+    pub fn update(&mut self, down: Option<Tool>, at: Point2<f32>) -> Vec<InputEvent> {
+        match down {
+            _ if down == self.down => self.moved(at),
+            None => self.release(at),
+            Some(tool) => self.press(tool, at),
+        }
+    }
+
+    pub fn press(&mut self, tool: Tool, at: Point2<f32>) -> Vec<InputEvent> {
+        let mut events = self.release(at);
+        self.down = Some(tool);
+        let pen = match tool {
+            Tool::Finger => {
+                events.push(finger(Phase::Press, at));
+                return events;
+            }
+            Tool::Pen => Pen::Pen,
+            Tool::Rubber => Pen::Rubber,
+        };
+        events.extend([
+            Event::Instrument { pen, state: true }.into(),
+            Event::Instrument { pen: Pen::Touch, state: true }.into(),
+            draw(at),
+        ]);
+        events
+    }
+
+    pub fn moved(&mut self, at: Point2<f32>) -> Vec<InputEvent> {
+        vec![match self.down {
+            None => {
+                Event::Hover { x: at.x, y: at.y, distance: HOVER_DISTANCE, tilt: (0, 0) }.into()
+            }
+            Some(Tool::Finger) => finger(Phase::Move, at),
+            Some(Tool::Pen | Tool::Rubber) => draw(at),
+        }]
+    }
+
+    pub fn release(&mut self, at: Point2<f32>) -> Vec<InputEvent> {
+        match self.down.take() {
+            None => vec![],
+            Some(Tool::Finger) => vec![finger(Phase::Release, at)],
+            Some(Tool::Pen | Tool::Rubber) => {
+                vec![Event::Instrument { pen: Pen::Touch, state: false }.into()]
+            }
+        }
+    }
+}
+
+fn draw(at: Point2<f32>) -> InputEvent {
+    Event::Draw { x: at.x, y: at.y, pressure: PRESSURE, tilt: (0, 0) }.into()
+}
+
+fn finger(phase: Phase, at: Point2<f32>) -> InputEvent {
+    Event::Finger { phase, id: 0, x: at.x as u16, y: at.y as u16 }.into()
+}
+
+/// A press & release of the tablet's button a key stands for:
+/// `l`eft, `m`iddle, `r`ight or `p`ower.
+#[must_use]
+pub fn key(k: char) -> Option<[InputEvent; 2]> {
+    let button = match k.to_ascii_lowercase() {
+        'l' => Button::Left,
+        'm' => Button::Middle,
+        'r' => Button::Right,
+        'p' => Button::Power,
+        _ => return None,
+    };
+    Some([
+        Event::Button { button, pressed: true }.into(),
+        Event::Button { button, pressed: false }.into(),
+    ])
+}
+
+#[cfg(test)]
+mod test {
+    use libremarkable::input::{WacomEvent, WacomPen};
+
+    use super::*;
+
+    #[test]
+    fn shows_only_what_got_refreshed() {
+        let mut display = Display::default();
+        assert!(display.take_changed());
+        for x in 0..10 {
+            display.write_pixel(Point2 { x, y: 5 }, color::GRAY(0x80));
+        }
+        display.write_pixel(Point2 { x: 20, y: 5 }, color::BLACK);
+        assert_eq!(display.shown().get_pixel(20, 5).0, [u8::MAX]);
+        assert!(!display.take_changed());
+
+        let first = mxcfb_rect { top: 5, left: 0, width: 5, height: 1 };
+        display.refresh(&first, Waveform::Fast, Refresh::Async);
+        display.refresh(&mxcfb_rect::invalid(), Waveform::Fast, Refresh::Async);
+        assert!(display.take_changed());
+        let gray = display.canvas.image().get_pixel(0, 5).0[0];
+        assert!(gray > 0 && gray < u8::MAX, "{gray}");
+        // DU rounds grays to black or white
+        assert_ne!(display.shown().get_pixel(0, 5).0, [gray]);
+        // Not refreshed yet
+        assert_eq!(display.shown().get_pixel(7, 5).0, [u8::MAX]);
+        assert_eq!(display.shown().get_pixel(20, 5).0, [u8::MAX]);
+
+        let all = mxcfb_rect { top: 0, left: 0, width: 1404, height: 1872 };
+        display.refresh(&all, Waveform::Quality, Refresh::Wait);
+        assert_eq!(display.shown().get_pixel(7, 5).0, [gray]);
+        assert_eq!(display.shown().get_pixel(20, 5).0, [0]);
+
+        display.clear();
+        assert_eq!(display.shown().get_pixel(20, 5).0, [u8::MAX]);
+    }
+
+    #[test]
+    fn maps_mouse_and_keys() {
+        let mut pointer = Pointer::default();
+        let at = Point2 { x: 10., y: 20. };
+        let wacom = |event| InputEvent::WacomEvent { event };
+
+        assert!(matches!(
+            pointer.moved(at)[..],
+            [InputEvent::WacomEvent { event: WacomEvent::Hover { .. } }]
+        ));
+        assert_eq!(
+            pointer.press(Tool::Rubber, at)[..2],
+            [
+                wacom(WacomEvent::InstrumentChange { pen: WacomPen::ToolRubber, state: true }),
+                wacom(WacomEvent::InstrumentChange { pen: WacomPen::Touch, state: true }),
+            ]
+        );
+        assert!(matches!(
+            pointer.moved(at)[..],
+            [InputEvent::WacomEvent { event: WacomEvent::Draw { pressure: PRESSURE, .. } }]
+        ));
+        assert_eq!(
+            pointer.release(at),
+            [wacom(WacomEvent::InstrumentChange { pen: WacomPen::Touch, state: false })]
+        );
+        assert_eq!(pointer.release(at), []);
+
+        let pen = pointer.update(Some(Tool::Pen), at);
+        assert_eq!(pen.len(), 3);
+        assert_eq!(pointer.update(Some(Tool::Pen), at), pen[2..]);
+        let finger = pointer.update(Some(Tool::Finger), at);
+        assert!(matches!(
+            finger[..],
+            [
+                InputEvent::WacomEvent { event: WacomEvent::InstrumentChange { state: false, .. } },
+                InputEvent::MultitouchEvent { .. },
+            ]
+        ));
+        assert!(key('M').is_some());
+        assert!(key('x').is_none());
+    }
+}
@@ -1,15 +1,19 @@
 use std::{thread::sleep, time::Duration};
 
 use itertools::Itertools;
-use libremarkable::{
-    appctx::ApplicationContext,
-    framebuffer::{
-        cgmath, cgmath::EuclideanSpace, common::*, FramebufferDraw, FramebufferRefresh,
-        PartialRefreshMode,
-    },
+use libremarkable::framebuffer::{cgmath, common::*};
+use pb::{
+    drawing::Point,
+    ingest::Malformed,
+    proto::hypercards::{drawing::Color, Drawing},
 };
 
-use crate::{modes::draw::*, unipen};
+use crate::{
+    ink::{Ink, Sample},
+    modes::draw::*,
+    surface::{Refresh, Surface},
+    unipen,
+};
 
 pub struct Stroke {
     color: color,
@@ -71,37 +75,14 @@ impl Stroke {
         (self.tip_size as f32) * (f32::from(pressure)) / 2048.
     }
 
-    pub fn draw(&self, app: &mut ApplicationContext) {
-        for (start, ctrl, end) in self.points_and_pressure.iter().tuple_windows() {
-            let points = [start, ctrl, end];
-            let radii: Vec<f32> =
-                points.iter().map(|(_, pressure)| self.pointwidth(*pressure) / 2.).collect();
-            // calculate control points
-            let start_point = points[2].0.midpoint(points[1].0);
-            let ctrl_point = points[1].0;
-            let end_point = points[1].0.midpoint(points[0].0);
-            // calculate diameters
-            let start_width = radii[2] + radii[1];
-            let ctrl_width = radii[1] * 2.;
-            let end_width = radii[1] + radii[0];
-
-            let framebuffer = app.get_framebuffer_ref();
-            let rect = framebuffer.draw_dynamic_bezier(
-                (start_point, start_width),
-                (ctrl_point, ctrl_width),
-                (end_point, end_width),
-                10,
-                self.color,
-            );
-            framebuffer.partial_refresh(
-                &rect,
-                PartialRefreshMode::Async,
-                waveform_mode::WAVEFORM_MODE_DU,
-                display_temp::TEMP_USE_REMARKABLE_DRAW,
-                dither_mode::EPDC_FLAG_EXP1,
-                DRAWING_QUANT_BIT,
-                false,
-            );
+    pub fn draw<S: Surface + ?Sized>(&self, s: &mut S) {
+        let ink = Ink::new(self.color);
+        let samples = self
+            .points_and_pressure
+            .iter()
+            .map(|(p, pressure)| Sample::new(*p, (*pressure).into(), self.tip_size));
+        for (s0, s1, s2) in samples.tuple_windows() {
+            ink.paint(s, [s0, s1, s2], Refresh::Async);
             if self.step > Duration::from_millis(1) {
                 sleep(self.step)
             }
@@ -109,6 +90,44 @@ impl Stroke {
     }
 }
 
+/// Strokes have a single tip size: the drawing's widest.
+impl TryFrom<&Drawing> for Stroke {
+    type Error = Malformed;
+
+    fn try_from(d: &Drawing) -> Result<Self, Self::Error> {
+        let points = d.points()?;
+        let color = match Color::try_from(d.color) {
+            Err(_) => return Err(Malformed::UnknownColor(d.color)),
+            Ok(Color::Invisible) => return Err(Malformed::Invisible),
+            Ok(Color::Black) => color::BLACK,
+            Ok(Color::White) => color::WHITE,
+        };
+        let mut stroke = Stroke::default();
+        let mut tip_size = 0;
+        for Point { x, y, pressure, width } in points {
+            let pressure = pressure.clamp(0, u16::MAX.into()) as u16;
+            stroke.push_back(cgmath::Point2 { x, y }, pressure);
+            tip_size = tip_size.max(width);
+        }
+        stroke.set_tip_size(tip_size);
+        stroke.set_color(color);
+        Ok(stroke)
+    }
+}
+
+impl From<&Stroke> for Drawing {
+    fn from(s: &Stroke) -> Self {
+        let c = if matches!(s.color, color::WHITE) { Color::White } else { Color::Black };
+        let points = s.points_and_pressure.iter().map(|(p, pressure)| Point {
+            x: p.x,
+            y: p.y,
+            pressure: (*pressure).into(),
+            width: s.tip_size,
+        });
+        Drawing::builder(c).points(points).build()
+    }
+}
+
 // TODO: change these (store?)
 fn canvas_width() -> f32 {
     1404.
@@ -128,9 +147,9 @@ impl From<Vec<Stroke>> for Strokes {
 }
 
 impl Strokes {
-    pub fn draw(&self, app: &mut ApplicationContext) {
+    pub fn draw<S: Surface + ?Sized>(&self, s: &mut S) {
         for stroke in self.strokes.iter() {
-            stroke.draw(app);
+            stroke.draw(s);
             sleep(2 * stroke.step);
         }
     }
@@ -200,6 +219,25 @@ impl Strokes {
     }
 }
 
+#[test]
+fn converts_to_and_from_drawings() {
+    let d = Drawing::builder(Color::White).point(1., 2., 2000, 3).point(4., 5., 70000, 3).build();
+    let stroke = Stroke::try_from(&d).unwrap();
+    assert_eq!(stroke.points_and_pressure[1].1, u16::MAX);
+    assert_eq!(stroke.tip_size, 3);
+
+    let back = Drawing::from(&stroke);
+    assert_eq!(back.color(), Color::White);
+    assert_eq!((back.xs, back.ys, back.widths), (d.xs, d.ys, d.widths));
+    assert_eq!(back.pressures, [2000, u16::MAX.into()]);
+
+    assert_eq!(Stroke::try_from(&Drawing::default()).err(), Some(Malformed::NoPoints));
+    let invisible = Drawing::builder(Color::Invisible).point(1., 2., 2000, 3).build();
+    assert_eq!(Stroke::try_from(&invisible).err(), Some(Malformed::Invisible));
+    let nan = Drawing::builder(Color::Black).point(f32::NAN, 2., 2000, 3).build();
+    assert_eq!(Stroke::try_from(&nan).err(), Some(Malformed::NotFinite));
+}
+
 fn abs_add(p: f32, q: f32) -> f32 {
     let sign = if p.is_sign_negative() { -1. } else { 1. };
     sign * (p.abs() + q)
@@ -0,0 +1,284 @@
+//! Where painting lands: the tablet's display, or an image off-device
+//!
+//! Everything that paints (ink, glyphs, `drawings` assets, `scrolls` replays)
+//! takes a [`Surface`], so that it runs on a plain Linux machine against a
+//! [`Canvas`](crate::raster::Canvas) just as it does on the [`Framebuffer`].
+//!
+//! E-ink refreshes are slow: rather than refreshing every bit painted, a
+//! [`Scheduler`] gathers what got painted and refreshes it at most so often,
+//! area by area, each with the [`Waveform`] its size calls for.
+//!
+use std::time::{Duration, Instant};
+
+use libremarkable::{
+    cgmath::Point2,
+    framebuffer::{
+        common::{color, display_temp, dither_mode, mxcfb_rect, waveform_mode, DRAWING_QUANT_BIT},
+        core::Framebuffer,
+        storage, FramebufferDraw, FramebufferIO, FramebufferRefresh, PartialRefreshMode,
+    },
+    image::RgbImage,
+};
+
+/// Areas larger than this get refreshed with [`Waveform::Quality`]: a quarter of the display.
+const LARGE_AREA: u64 = 1404 * 1872 / 4;
+
+/// Dirty areas this close to each other get refreshed as one.
+const NEARBY: u32 = 32;
+
+/// Whether refreshing returns right away or once the display is done.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Refresh {
+    #[default]
+    Async,
+    Wait,
+}
+
+impl From<Refresh> for PartialRefreshMode {
+    fn from(r: Refresh) -> Self {
+        match r {
+            Refresh::Async => Self::Async,
+            Refresh::Wait => Self::Wait,
+        }
+    }
+}
+
+/// How the display refreshes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Waveform {
+    /// Black & white only, in a blink: for ink
+    #[default]
+    Fast,
+    /// Grays and no ghosting, but flashing: for large areas
+    Quality,
+}
+
+impl Waveform {
+    /// Fast for ink, unless so much changed that ghosting would show.
+    #[must_use]
+    pub fn for_area(rect: &mxcfb_rect) -> Self {
+        if u64::from(rect.width) * u64::from(rect.height) > LARGE_AREA {
+            Self::Quality
+        } else {
+            Self::Fast
+        }
+    }
+}
+
+pub trait Surface {
+    /// Width and height, in pixels.
+    fn dimensions(&self) -> (u32, u32);
+
+    /// Sets a pixel. Pixels out of bounds are ignored.
+    fn write_pixel(&mut self, p: Point2<i32>, c: color);
+
+    /// Shows what was painted within `rect`.
+    fn refresh(&mut self, rect: &mxcfb_rect, waveform: Waveform, refresh: Refresh);
+
+    /// Reads back what was painted within `rect`, e.g. to share it.
+    fn snapshot(&self, rect: &mxcfb_rect) -> Option<RgbImage>;
+
+    /// Blanks everything, flashing it away.
+    fn clear(&mut self) {
+        let (w, h) = self.dimensions();
+        for y in 0..h as i32 {
+            for x in 0..w as i32 {
+                self.write_pixel(Point2 { x, y }, color::WHITE);
+            }
+        }
+        self.refresh(&everything(w, h), Waveform::Quality, Refresh::Wait);
+    }
+}
+
+/// Whether `rect` covers nothing, like [`mxcfb_rect::invalid`]: not worth refreshing.
+#[must_use]
+pub fn is_empty(rect: &mxcfb_rect) -> bool {
+    rect.width == 0 || rect.height == 0
+}
+
+/// The part of `rect` on a `w` by `h` surface.
+#[must_use]
+pub fn clip(rect: &mxcfb_rect, w: u32, h: u32) -> mxcfb_rect {
+    let (left, top) = (rect.left.min(w), rect.top.min(h));
+    let right = rect.left.saturating_add(rect.width).min(w);
+    let bottom = rect.top.saturating_add(rect.height).min(h);
+    mxcfb_rect { top, left, width: right - left, height: bottom - top }
+}
+
+/// The whole of a `w` by `h` surface.
+#[must_use]
+pub fn everything(w: u32, h: u32) -> mxcfb_rect {
+    mxcfb_rect { top: 0, left: 0, width: w, height: h }
+}
+
+impl Surface for Framebuffer {
+    fn dimensions(&self) -> (u32, u32) {
+        (self.var_screen_info.xres, self.var_screen_info.yres)
+    }
+
+    fn write_pixel(&mut self, p: Point2<i32>, c: color) {
+        FramebufferIO::write_pixel(self, p, c);
+    }
+
+    fn refresh(&mut self, rect: &mxcfb_rect, waveform: Waveform, refresh: Refresh) {
+        if is_empty(rect) {
+            return;
+        }
+        match waveform {
+            Waveform::Fast => self.partial_refresh(
+                rect,
+                refresh.into(),
+                waveform_mode::WAVEFORM_MODE_DU,
+                display_temp::TEMP_USE_REMARKABLE_DRAW,
+                dither_mode::EPDC_FLAG_EXP1,
+                DRAWING_QUANT_BIT,
+                false,
+            ),
+            Waveform::Quality => self.partial_refresh(
+                rect,
+                refresh.into(),
+                waveform_mode::WAVEFORM_MODE_GC16,
+                display_temp::TEMP_USE_PAPYRUS,
+                dither_mode::EPDC_FLAG_USE_DITHERING_PASSTHROUGH,
+                0,
+                false,
+            ),
+        };
+    }
+
+    fn snapshot(&self, rect: &mxcfb_rect) -> Option<RgbImage> {
+        let buff = self.dump_region(*rect).ok()?;
+        storage::rgbimage_from_u8_slice(rect.width, rect.height, &buff)
+    }
+
+    fn clear(&mut self) {
+        FramebufferDraw::clear(self);
+        let (w, h) = self.dimensions();
+        self.refresh(&everything(w, h), Waveform::Quality, Refresh::Wait);
+    }
+}
+
+/// Whether `a` and `b` overlap, or are at most [`NEARBY`] apart.
+fn nearby(a: &mxcfb_rect, b: &mxcfb_rect) -> bool {
+    let apart = |start_a: u32, len_a: u32, start_b: u32, len_b: u32| {
+        start_b.saturating_sub(start_a.saturating_add(len_a)) > NEARBY
+            || start_a.saturating_sub(start_b.saturating_add(len_b)) > NEARBY
+    };
+    !apart(a.left, a.width, b.left, b.width) && !apart(a.top, a.height, b.top, b.height)
+}
+
+/// Gathers what got painted, to refresh it at most every so often.
+///
+/// Areas painted far apart stay apart, so that ink in two corners gets
+/// refreshed as two small, fast areas rather than one that spans the display.
+#[derive(Debug)]
+pub struct Scheduler {
+    every: Duration,
+    dirty: Vec<mxcfb_rect>,
+    last: Option<Instant>,
+}
+
+impl Scheduler {
+    #[must_use]
+    pub fn new(every: Duration) -> Self {
+        Self { every, dirty: vec![], last: None }
+    }
+
+    /// Notes that `rect` got painted, merging it with the dirty areas nearby.
+    pub fn mark(&mut self, rect: &mxcfb_rect) {
+        if is_empty(rect) {
+            return;
+        }
+        let mut merged = *rect;
+        while let Some(i) = self.dirty.iter().position(|dirty| nearby(dirty, &merged)) {
+            merged = merged.merge_rect(&self.dirty.swap_remove(i));
+        }
+        self.dirty.push(merged);
+    }
+
+    #[must_use]
+    pub fn is_dirty(&self) -> bool {
+        !self.dirty.is_empty()
+    }
+
+    /// Refreshes what got painted unless the last refresh was too recent,
+    /// returning the areas refreshed.
+    pub fn tick<S: Surface + ?Sized>(&mut self, s: &mut S, now: Instant) -> Vec<mxcfb_rect> {
+        if self.dirty.is_empty()
+            || self.last.is_some_and(|last| now.duration_since(last) < self.every)
+        {
+            return vec![];
+        }
+        let dirty = std::mem::take(&mut self.dirty);
+        for rect in &dirty {
+            s.refresh(rect, Waveform::for_area(rect), Refresh::Async);
+        }
+        self.last = Some(now);
+        dirty
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::raster::Canvas;
+
+    fn rect(left: u32, top: u32, width: u32, height: u32) -> mxcfb_rect {
+        mxcfb_rect { top, left, width, height }
+    }
+
+    #[test]
+    fn picks_waveforms_by_area() {
+        assert_eq!(Waveform::for_area(&rect(0, 0, 100, 100)), Waveform::Fast);
+        assert_eq!(Waveform::for_area(&rect(0, 0, 1404, 1872)), Waveform::Quality);
+    }
+
+    #[test]
+    fn coalesces_then_rate_limits_refreshes() {
+        let mut canvas = Canvas::new(100, 100);
+        let mut scheduler = Scheduler::new(Duration::from_millis(50));
+        let t0 = Instant::now();
+        assert_eq!(scheduler.tick(&mut canvas, t0), []);
+
+        scheduler.mark(&rect(10, 10, 5, 5));
+        scheduler.mark(&mxcfb_rect::invalid());
+        scheduler.mark(&rect(20, 30, 5, 5));
+        assert_eq!(scheduler.tick(&mut canvas, t0), [rect(10, 10, 15, 25)]);
+        assert!(!scheduler.is_dirty());
+
+        scheduler.mark(&rect(0, 0, 1, 1));
+        assert_eq!(scheduler.tick(&mut canvas, t0 + Duration::from_millis(10)), []);
+        assert!(scheduler.is_dirty());
+        let later = t0 + Duration::from_millis(60);
+        assert_eq!(scheduler.tick(&mut canvas, later), [rect(0, 0, 1, 1)]);
+
+        assert_eq!(
+            canvas.refreshes(),
+            [(rect(10, 10, 15, 25), Waveform::Fast), (rect(0, 0, 1, 1), Waveform::Fast)]
+        );
+    }
+
+    #[test]
+    fn keeps_areas_apart_apart() {
+        let mut canvas = Canvas::new(1404, 1872);
+        let mut scheduler = Scheduler::new(Duration::ZERO);
+        scheduler.mark(&rect(0, 0, 10, 10));
+        scheduler.mark(&rect(1000, 1500, 10, 10));
+        scheduler.mark(&rect(1020, 1500, 10, 10));
+        scheduler.mark(&rect(5, 5, 10, 10));
+
+        let refreshed = scheduler.tick(&mut canvas, Instant::now());
+        assert_eq!(refreshed.len(), 2);
+        assert!(refreshed.contains(&rect(0, 0, 15, 15)));
+        assert!(refreshed.contains(&rect(1000, 1500, 30, 10)));
+        assert!(canvas.refreshes().iter().all(|&(_, w)| w == Waveform::Fast));
+    }
+
+    #[test]
+    fn skips_empty_areas() {
+        let mut canvas = Canvas::new(100, 100);
+        canvas.refresh(&mxcfb_rect::invalid(), Waveform::Fast, Refresh::Async);
+        canvas.refresh(&rect(10, 10, 0, 5), Waveform::Fast, Refresh::Async);
+        assert_eq!(canvas.refreshes(), []);
+    }
+}
@@ -0,0 +1,828 @@
+//! A whiteboard shared with a room
+//!
+//! A [`WhiteboardSession`] inks the pen onto whatever [`Surface`] it is handed and
+//! sends each stroke to the room. Once [connected](WhiteboardSession::connect),
+//! network loops receive others' drawings and share the screen. They only talk
+//! to the server: what they receive gets painted by [`WhiteboardSession::tick`],
+//! which is also what refreshes the display and hands screens over to share.
+//!
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc, LazyLock, Mutex, RwLock,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use clap::Parser;
+use crc_any::CRC;
+use itertools::Itertools;
+use libremarkable::{
+    cgmath::Point2,
+    framebuffer::common::{color, mxcfb_rect, DISPLAYHEIGHT, DISPLAYWIDTH},
+    image::{self, RgbImage},
+    input::{GPIOEvent, InputEvent, MultitouchEvent, WacomEvent, WacomPen},
+};
+use log::{debug, error, info, warn};
+use pb::{
+    ingest::{self, Canvas},
+    proto::hypercards::{drawing::Color, event, Drawing},
+};
+use qrcode_generator::QrCodeEcc;
+use tokio::{
+    runtime::Handle,
+    sync::{mpsc, watch},
+    task::JoinHandle,
+};
+use tokio_stream::StreamExt;
+use uuid::Uuid;
+
+use crate::{
+    buttons::Button,
+    client::{channel_with_ca, Rejection, Session},
+    fonts::{self, Font},
+    ink::{self, Brush, Ink, PressureCurve, Sample, Trail},
+    surface::{Refresh, Scheduler, Surface, Waveform},
+};
+
+/// Where to draw, and how.
+#[derive(Parser, Debug, Clone)]
+#[clap(name = "whiteboard")]
+pub struct Settings {
+    /// Room to join
+    #[arg(long, env = "WHITEBOARD_ROOM", default_value = "living-room")]
+    pub room: String,
+
+    /// Host to connect to
+    #[arg(long, env = "WHITEBOARD_HOST", default_value = "http://fknwkdacd.com:10000")]
+    pub host: String,
+
+    /// PEM to authenticate https:// hosts with: a CA bundle or the host's self-signed certificate
+    #[arg(long, env = "WHITEBOARD_CA")]
+    pub ca: Option<PathBuf>,
+
+    /// Web host to send live feed to
+    #[arg(long, env = "WHITEBOARD_WEBHOST", default_value = "http://fknwkdacd.com:18888/s")]
+    pub webhost: String,
+
+    /// Brush to draw with (erasing is always done with a ballpoint)
+    #[arg(long, env = "WHITEBOARD_BRUSH", value_enum, default_value_t)]
+    pub brush: Brush,
+
+    /// How pen pressure widens ink: linear, flat or a gamma (e.g. 0.5 for light hands)
+    #[arg(long, env = "WHITEBOARD_PRESSURE_CURVE", default_value = "linear")]
+    pub pressure_curve: PressureCurve,
+
+    /// ID to identify as
+    #[arg(skip = Uuid::new_v4().hyphenated().to_string())]
+    pub user_id: String,
+}
+
+#[derive(Debug)]
+struct Scribble {
+    color: color,
+    mult: u32,
+    pos_x: f32,
+    pos_y: f32,
+    pressure: i32,
+}
+
+const TOOLBAR_BAR_WIDTH: u32 = 2;
+const TOOLBAR_HEIGHT: u32 = 70 + TOOLBAR_BAR_WIDTH;
+const TOOLBAR_REGION: mxcfb_rect =
+    mxcfb_rect { top: 0, left: 0, height: TOOLBAR_HEIGHT, width: DISPLAYWIDTH as u32 };
+// (0,0) --x-> (x=1404,0)
+// |
+// y
+// |
+// v
+// (0,1872)
+pub const CANVAS_REGION: mxcfb_rect = mxcfb_rect {
+    top: TOOLBAR_HEIGHT,
+    left: 0,
+    height: DISPLAYHEIGHT as u32 - TOOLBAR_HEIGHT,
+    width: DISPLAYWIDTH as u32,
+};
+
+/// Where others' drawings get painted.
+const CANVAS: Canvas = Canvas { width: DISPLAYWIDTH as f32, height: DISPLAYHEIGHT as f32 };
+
+static FONT: LazyLock<Font> = LazyLock::new(|| fonts::emsdelight_swash_caps().unwrap());
+
+/// How often others' drawings get refreshed, at most: how often to [tick](WhiteboardSession::tick).
+pub const REFRESH_EVERY: Duration = Duration::from_millis(50);
+
+/// How often the screen gets shared when nothing changed, in case the web host missed it.
+const SHARE_EVERY: Duration = Duration::from_secs(10);
+
+/// Being in the room: sends our drawings, shares screens and receives others' drawings.
+/// Dropping it stops it all.
+struct Connection {
+    tx: mpsc::UnboundedSender<Drawing>,
+    screens: watch::Sender<Option<RgbImage>>,
+    loops: Vec<JoinHandle<()>>,
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        for handle in &self.loops {
+            handle.abort();
+        }
+    }
+}
+
+/// Everything a whiteboard keeps track of, shared between input handlers and
+/// network loops: what's being drawn, who's in the room, what's left to share.
+pub struct WhiteboardSession {
+    settings: Settings,
+    people_count: AtomicU32,
+    /// What the people counter shows
+    people_shown: AtomicU32,
+    wacom_in_range: AtomicBool,
+    pen_black: AtomicBool,
+    trail: Mutex<Trail>,
+    refreshes: Mutex<Scheduler>,
+    scribbles: Mutex<Vec<Scribble>>,
+    /// Others' drawings, received but not painted yet
+    received: Mutex<Vec<Drawing>>,
+    /// Why the room got left, until painted
+    notice: Mutex<Option<String>>,
+    connection: Mutex<Option<Connection>>,
+    needs_sharing: AtomicBool,
+    shared_at: Mutex<Option<Instant>>,
+    /// What the canvas showed when last shared
+    screen: Mutex<Option<RgbImage>>,
+    qrcode: RwLock<Option<RgbImage>>,
+    qrcode_shown: AtomicBool,
+    btn_erase: Button,
+    btn_times3: Button,
+}
+
+fn black(x: bool) -> color {
+    if x {
+        color::BLACK
+    } else {
+        color::WHITE
+    }
+}
+
+impl WhiteboardSession {
+    #[must_use]
+    pub fn new(settings: Settings) -> Self {
+        Self {
+            settings,
+            people_count: Default::default(),
+            people_shown: Default::default(),
+            wacom_in_range: Default::default(),
+            pen_black: AtomicBool::new(true),
+            trail: Default::default(),
+            refreshes: Mutex::new(Scheduler::new(REFRESH_EVERY)),
+            scribbles: Default::default(),
+            received: Default::default(),
+            notice: Default::default(),
+            connection: Default::default(),
+            needs_sharing: AtomicBool::new(true),
+            shared_at: Default::default(),
+            screen: Default::default(),
+            qrcode: Default::default(),
+            qrcode_shown: Default::default(),
+            btn_erase: Button::new(1, "erase"),
+            btn_times3: Button::new(2, "times3"),
+        }
+    }
+
+    #[must_use]
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    /// Joins the room, unless already in it: receives others' drawings, forwards ours
+    /// and shares the screen until [disconnected](WhiteboardSession::disconnect).
+    /// Fails outside of a Tokio runtime.
+    pub fn connect(self: &Arc<Self>) -> Result<()> {
+        let mut connection = self.connection.lock().unwrap();
+        if connection.is_some() {
+            debug!("[connect] already in {:?}", self.settings.room);
+            return Ok(());
+        }
+        let runtime = Handle::try_current()?;
+        let settings = &self.settings;
+        let ch = channel_with_ca(&settings.host, settings.ca.as_deref())?;
+        let session = Session::with_channel(ch, &settings.room, &settings.user_id);
+        let mut loops = vec![];
+
+        let (wb2, session2) = (self.clone(), session.clone());
+        info!("[connect] spawn-ing loop_recv");
+        loops.push(runtime.spawn(async move {
+            info!("[loop_recv] spawn-ed");
+            wb2.loop_recv(session2).await;
+        }));
+
+        let (screens, mut screens_rx) = watch::channel(None);
+        let session3 = session.clone();
+        info!("[connect] spawn-ing loop_screensharing");
+        loops.push(runtime.spawn(async move {
+            info!("[loop_screensharing] spawn-ed");
+            while let Err(e) = loop_screensharing(&mut screens_rx, &session3).await {
+                error!("[loop_screensharing] respawning due to: {e}");
+            }
+        }));
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        let wb5 = self.clone();
+        info!("[connect] spawn-ing loop_fwd");
+        loops.push(runtime.spawn(async move {
+            info!("[loop_fwd] spawn-ed");
+            if let Err(e) = wb5.loop_fwd(rx, session).await {
+                error!("[loop_fwd] terminating due to: {e}");
+                // TODO: find a way to survive: re-set tx?
+            }
+        }));
+
+        if self.qrcode.read().unwrap().is_none() {
+            let wb6 = self.clone();
+            info!("[connect] spawn-ing qrcoder");
+            loops.push(runtime.spawn(async move {
+                info!("[qrcoder] spawn-ed");
+                let url = format!("{}/{}/", wb6.settings.webhost, wb6.settings.room);
+                debug!("[qrcoder] generating");
+                let qrcode: Vec<u8> =
+                    qrcode_generator::to_png_to_vec(url, QrCodeEcc::Low, 64).unwrap();
+                debug!("[qrcoder] loading");
+                let img_rgb565 = image::load_from_memory(&qrcode).unwrap();
+                *wb6.qrcode.write().unwrap() = Some(img_rgb565.to_rgb8());
+                info!("[qrcoder] done");
+            }));
+        }
+
+        *connection = Some(Connection { tx, screens, loops });
+        Ok(())
+    }
+
+    /// Leaves the room, stopping the network loops.
+    pub fn disconnect(&self) {
+        if self.connection.lock().unwrap().take().is_some() {
+            info!("[disconnect] left {:?}", self.settings.room);
+        }
+    }
+
+    #[must_use]
+    pub fn is_connected(&self) -> bool {
+        self.connection.lock().unwrap().is_some()
+    }
+
+    pub fn on_input(&self, s: &mut dyn Surface, evt: InputEvent) {
+        match evt {
+            InputEvent::WacomEvent { event } => self.on_pen(s, event),
+            InputEvent::MultitouchEvent { event } => self.on_tch(event),
+            InputEvent::GPIO { event } => self.on_btn(event),
+            InputEvent::Unknown {} => {}
+        }
+    }
+
+    pub fn on_pen(&self, s: &mut dyn Surface, input: WacomEvent) {
+        match input {
+            WacomEvent::Draw { position, pressure, tilt } => {
+                let mut trail = self.trail.lock().unwrap();
+
+                if !CANVAS_REGION.contains_point(&position.cast().unwrap()) {
+                    trail.clear();
+                    self.maybe_send_drawing();
+                    return;
+                }
+
+                let from_pen = self.pen_black.load(Ordering::Relaxed);
+                let from_btn = self.btn_erase.is_pressed();
+                let col = black(from_pen && !from_btn);
+                let mult = if col == color::WHITE { 50 } else { 2 };
+                let mult = mult * if self.btn_times3.is_pressed() { 3 } else { 1 };
+
+                {
+                    let mut scribbles = self.scribbles.lock().unwrap();
+                    scribbles.push(Scribble {
+                        color: col,
+                        mult,
+                        pos_x: position.x,
+                        pos_y: position.y,
+                        pressure: i32::from(pressure),
+                    });
+                }
+
+                let ink = if col == color::WHITE {
+                    Ink::new(col)
+                } else {
+                    let settings = &self.settings;
+                    Ink::new(col).with_brush(settings.brush).with_curve(settings.pressure_curve)
+                };
+                let sample = Sample::from_wacom(position, pressure, tilt, mult);
+                if let Some(samples) = trail.push(sample) {
+                    ink.paint(s, samples, Refresh::Async);
+                }
+            }
+            WacomEvent::InstrumentChange { pen, state } => {
+                match pen {
+                    WacomPen::ToolPen | WacomPen::ToolRubber => {
+                        let in_range = state; // Whether the pen is in range
+                        self.wacom_in_range.store(in_range, Ordering::Relaxed);
+                        let is_white = matches!(pen, WacomPen::ToolRubber);
+                        info!("changing color to {:?}", black(!is_white));
+                        self.pen_black.store(!is_white, Ordering::Relaxed);
+                    }
+                    WacomPen::Touch | WacomPen::Stylus | WacomPen::Stylus2 => {
+                        // Whether the pen is actually making contact
+                        let making_contact = state;
+                        if !making_contact {
+                            self.trail.lock().unwrap().clear();
+                            self.maybe_send_drawing();
+                        }
+                    }
+                }
+            }
+            WacomEvent::Hover { position: _, distance, tilt: _ } => {
+                // If the pen is hovering, don't record its coordinates as the origin of the next line
+                if distance > 1 {
+                    self.trail.lock().unwrap().clear();
+                    self.maybe_send_drawing();
+                }
+            }
+            WacomEvent::Unknown => info!("got WacomEvent::Unknown"),
+        };
+    }
+
+    fn maybe_send_drawing(&self) {
+        let mut scribbles = self.scribbles.lock().unwrap();
+        let len = scribbles.len();
+        if len < 3 {
+            return;
+        }
+        debug!("scribbles.len() = {len:?}");
+
+        let mut ws = Vec::<u32>::with_capacity(len);
+        let mut xs = Vec::<f32>::with_capacity(len);
+        let mut ys = Vec::<f32>::with_capacity(len);
+        let mut ps = Vec::<i32>::with_capacity(len);
+        for i in 0..len {
+            let scribble = &scribbles[i];
+            ws.push(scribble.mult);
+            xs.push(scribble.pos_x);
+            ys.push(scribble.pos_y);
+            ps.push(scribble.pressure);
+        }
+
+        let col = match scribbles[0].color {
+            color::WHITE => Color::White,
+            _ => Color::Black,
+        };
+
+        self.needs_sharing.store(true, Ordering::Relaxed);
+        debug!("locking TX");
+        if let Some(ref connection) = *self.connection.lock().unwrap() {
+            let drawing = Drawing { xs, ys, pressures: ps, widths: ws, color: col as i32 };
+            if connection.tx.send(drawing).is_err() {
+                warn!("[maybe_send_drawing] loop_fwd is gone");
+            }
+            debug!("unlocked TX");
+        }
+        scribbles.clear();
+    }
+
+    pub fn on_tch(&self, input: MultitouchEvent) {
+        self.btn_erase.process_event(input);
+        self.btn_times3.process_event(input);
+    }
+
+    pub fn on_btn(&self, input: GPIOEvent) {
+        info!("[on_btn] input = {input:?}");
+    }
+
+    /// Paints what the network loops received, refreshes what got painted and
+    /// hands the screen over to share. Call it every [`REFRESH_EVERY`] or so.
+    pub fn tick(&self, s: &mut dyn Surface, now: Instant) {
+        let received = std::mem::take(&mut *self.received.lock().unwrap());
+        if !received.is_empty() {
+            debug!("[tick] painting {} drawings", received.len());
+            self.paint_vec(s, received);
+            self.needs_sharing.store(true, Ordering::Relaxed);
+        }
+
+        let count = self.people_count.load(Ordering::Relaxed);
+        let shown = self.people_shown.swap(count, Ordering::Relaxed);
+        if shown != count {
+            self.repaint_people_counter(s, shown, count);
+        }
+
+        if let Some(notice) = self.notice.lock().unwrap().take() {
+            self.paint_notice(s, &notice);
+        }
+
+        self.paint_qrcode(s);
+        self.refreshes.lock().unwrap().tick(s, now);
+        self.share(s, now);
+    }
+
+    /// Hands what the canvas shows over to `loop_screensharing`, if it changed or
+    /// some time passed. Keeps it too, to [repaint](WhiteboardSession::repaint) it.
+    fn share(&self, s: &mut dyn Surface, now: Instant) {
+        let mut shared_at = self.shared_at.lock().unwrap();
+        let due = shared_at.is_none_or(|at| now.duration_since(at) >= SHARE_EVERY);
+        if !(self.needs_sharing.swap(false, Ordering::Relaxed) || due) {
+            return;
+        }
+        debug!("[share] dumping canvas");
+        let Some(screen) = s.snapshot(&CANVAS_REGION) else {
+            warn!("[share] can't read the canvas back");
+            return;
+        };
+        *shared_at = Some(now);
+        if let Some(ref connection) = *self.connection.lock().unwrap() {
+            connection.screens.send_replace(Some(screen.clone()));
+        }
+        *self.screen.lock().unwrap() = Some(screen);
+    }
+
+    /// Paints the mouldings then the canvas as last shared, onto a blank display.
+    pub fn repaint(&self, s: &mut dyn Surface) {
+        self.paint_mouldings(s);
+        let screen = self.screen.lock().unwrap();
+        let Some(ref screen) = *screen else { return };
+        for (x, y, px) in screen.enumerate_pixels() {
+            let [r, g, b] = px.0;
+            let at =
+                Point2 { x: (CANVAS_REGION.left + x) as i32, y: (CANVAS_REGION.top + y) as i32 };
+            s.write_pixel(at, color::RGB(r, g, b));
+        }
+        self.refreshes.lock().unwrap().mark(&CANVAS_REGION);
+    }
+
+    async fn loop_fwd(
+        &self,
+        mut rx: mpsc::UnboundedReceiver<Drawing>,
+        session: Session,
+    ) -> Result<()> {
+        while let Some(drawing) = rx.recv().await {
+            debug!("[loop_fwd] FWDing...");
+            if session.rejection().is_some() {
+                debug!("[loop_fwd] not in the room anymore");
+                continue;
+            }
+            match session.send_drawing(drawing).await {
+                Ok(()) => self.needs_sharing.store(true, Ordering::Relaxed),
+                Err(e) if e.is::<Rejection>() => warn!("[loop_fwd] drawing not shared: {e}"),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    async fn loop_recv(&self, session: Session) {
+        let mut events = Box::pin(session.events());
+        info!("[loop_recv] receiving...");
+
+        while let Some(event) = events.next().await {
+            match event.event {
+                None => error!("[loop_recv] empty event in message"),
+                Some(event::Event::Drawing(drawing)) => {
+                    let drawing = match ingest::normalize(drawing, &CANVAS) {
+                        Ok(drawing) => drawing,
+                        Err(e) => {
+                            warn!("[loop_recv] dropping {:?}'s drawing: {e}", event.by_user_id);
+                            continue;
+                        }
+                    };
+                    let len = drawing.xs.len();
+                    if len < 3 {
+                        continue;
+                    }
+                    debug!("[loop_recv] received {len:?} points");
+                    self.received.lock().unwrap().push(drawing);
+                }
+                Some(
+                    event::Event::UsersInTheRoom(_)
+                    | event::Event::UserJoinedTheRoom(_)
+                    | event::Event::UserLeftTheRoom(_),
+                ) => self.people_count.store(session.people(), Ordering::Relaxed),
+                // Streamer MAY send newer revisions of proto messages, though it shouldn't:
+                // Session tells it what this build supports
+                #[allow(unreachable_patterns)]
+                Some(other) => warn!("[loop_recv] unhandled msg {other:?}"),
+            }
+        }
+
+        if let Some(rejection) = session.rejection() {
+            warn!("[loop_recv] out of the room: {rejection}");
+            *self.notice.lock().unwrap() = Some(rejection.to_string());
+        }
+    }
+
+    /// Handwrites `text` at the top of the canvas.
+    fn paint_notice(&self, s: &mut dyn Surface, text: &str) {
+        let at = (40., TOOLBAR_HEIGHT as f32 + 40.);
+        let ds = fonts::write(&FONT, text, at, 60., Color::Black);
+        self.paint_vec(s, ds.into_iter().filter(|d| d.xs.len() >= 3).collect());
+    }
+
+    /// Paints a drawing, leaving refreshing it to [`WhiteboardSession::tick`].
+    fn paint(&self, s: &mut dyn Surface, drawing: Drawing) {
+        let rect = ink::draw(s, &drawing);
+        self.refreshes.lock().unwrap().mark(&rect);
+    }
+
+    fn paint_people_counter(&self, s: &mut dyn Surface, count: u32, color: Color) {
+        let digit = match count {
+            0 => FONT.get("0"),
+            1 => FONT.get("1"),
+            2 => FONT.get("2"),
+            3 => FONT.get("3"),
+            4 => FONT.get("4"),
+            5 => FONT.get("5"),
+            6 => FONT.get("6"),
+            7 => FONT.get("7"),
+            8 => FONT.get("8"),
+            9 => FONT.get("9"),
+            _ => {
+                info!("drawing PEOPLE_COUNT of 9 even though it's at {count:?}");
+                FONT.get("9")
+            }
+        }
+        .unwrap();
+
+        let at = (-15000., -150. * 5., 0.085);
+        self.paint_glyph(s, digit, at, 3992, 3, color);
+    }
+
+    fn paint_vec(&self, s: &mut dyn Surface, xs: Vec<Drawing>) {
+        for x in xs {
+            self.paint(s, x);
+        }
+    }
+
+    fn repaint_people_counter(&self, s: &mut dyn Surface, o: u32, n: u32) {
+        self.paint_people_counter(s, o, Color::White);
+        self.paint_people_counter(s, n, Color::Black);
+        self.paint(s, top_bar(Color::Black));
+    }
+
+    /// Paints the link to the live feed in the top right corner, once generated.
+    fn paint_qrcode(&self, s: &mut dyn Surface) {
+        let qrcode = self.qrcode.read().unwrap();
+        let Some(qrcode) = qrcode.as_ref() else { return };
+        if self.qrcode_shown.swap(true, Ordering::Relaxed) {
+            return;
+        }
+        debug!("[qrcode] painting");
+        let region = mxcfb_rect {
+            top: 4,
+            left: TOOLBAR_REGION.width - (4 + qrcode.width()),
+            height: qrcode.height(),
+            width: qrcode.width(),
+        };
+        for (x, y, px) in qrcode.enumerate_pixels() {
+            let [r, g, b] = px.0;
+            let at = Point2 { x: (region.left + x) as i32, y: (region.top + y) as i32 };
+            s.write_pixel(at, color::RGB(r, g, b));
+        }
+        s.refresh(&region, Waveform::Quality, Refresh::Async);
+        info!("[qrcode] done");
+    }
+
+    /// Paints the toolbar and whatever else sits around the canvas, onto a blank display.
+    pub fn paint_mouldings(&self, s: &mut dyn Surface) {
+        let c = Color::Black;
+        debug!("[paint_mouldings] drawing UI...");
+
+        let mut parts = drawings::title_whiteboard::f(c);
+        for part in &mut parts {
+            for w in &mut part.widths {
+                *w /= 2;
+            }
+        }
+        self.paint_vec(s, parts);
+        self.paint(s, top_bar(c));
+        // self.paint_vec(s, drawings::top_left_help::f(c));
+        self.paint_vec(s, drawings::top_left_white_empty_square::f(c));
+        self.paint_vec(s, drawings::top_left_x3::f(c));
+        let count = self.people_count.load(Ordering::Relaxed);
+        self.paint_people_counter(s, count, c);
+        self.people_shown.store(count, Ordering::Relaxed);
+        self.qrcode_shown.store(false, Ordering::Relaxed);
+    }
+
+    fn paint_glyph(
+        &self,
+        s: &mut dyn Surface,
+        glyph: &[Vec<(f32, f32)>],
+        c0k: (f32, f32, f32),
+        p: i32,
+        w: u32,
+        c: Color,
+    ) {
+        let (x0, y0, k) = c0k;
+        for path in glyph {
+            let drawing: Vec<Drawing> = path
+                .iter()
+                .tuple_windows()
+                .map(|((xa, ya), (xb, yb))| {
+                    let (xa, ya) = (xa, -ya);
+                    let (xb, yb) = (xb, -yb);
+                    let xs = vec![k * (xa - x0), (k * (xa - x0 + xb - x0)) / 2., k * (xb - x0)];
+                    let ys = vec![k * (ya - y0), (k * (ya - y0 + yb - y0)) / 2., k * (yb - y0)];
+                    let points_count = xs.len();
+                    Drawing {
+                        xs,
+                        ys,
+                        pressures: vec![p; points_count],
+                        widths: vec![w; points_count],
+                        color: c.into(),
+                    }
+                })
+                .collect();
+            self.paint_vec(s, drawing);
+        }
+    }
+}
+
+/// Compresses then sends the screens handed over by [`WhiteboardSession::tick`],
+/// unless they're the same as the last one sent. Ends once disconnected.
+async fn loop_screensharing(
+    screens: &mut watch::Receiver<Option<RgbImage>>,
+    session: &Session,
+) -> Result<()> {
+    let mut previous_checksum: u64 = 0;
+    while screens.changed().await.is_ok() {
+        let Some(screen) = screens.borrow_and_update().clone() else { continue };
+
+        let mut crc32 = CRC::crc32();
+        crc32.digest(screen.as_raw());
+        let new_checksum = crc32.get_crc();
+        if new_checksum == previous_checksum {
+            continue;
+        }
+        previous_checksum = new_checksum;
+
+        debug!("[loop_screensharing] compressing canvas");
+        let img = image::DynamicImage::ImageRgb8(screen);
+        let mut compressed = std::io::Cursor::new(Vec::with_capacity(50_000));
+        img.write_to(&mut compressed, image::ImageFormat::Png)
+            .map_err(|e| anyhow::anyhow!("[loop_screensharing] failed to compress fb: {e:?}"))?;
+        let screen_png = compressed.into_inner();
+        info!("[loop_screensharing] compressed!");
+
+        debug!("[loop_screensharing] sending canvas");
+        match session.send_screen(screen_png).await {
+            Err(e) if e.is::<Rejection>() => {
+                warn!("[loop_screensharing] screen not shared: {e}")
+            }
+            res => res?,
+        }
+    }
+    Ok(())
+}
+
+fn top_bar(c: Color) -> Drawing {
+    let max_x: u32 = CANVAS_REGION.width;
+    let mut xs: Vec<f32> = Vec::with_capacity(max_x.try_into().unwrap());
+    for i in 1..xs.capacity() {
+        xs.push(i as f32);
+    }
+    let count = xs.len();
+    Drawing {
+        xs,
+        ys: vec![TOOLBAR_HEIGHT as f32 - TOOLBAR_BAR_WIDTH as f32; count],
+        pressures: vec![3992; count],
+        widths: vec![TOOLBAR_BAR_WIDTH; count],
+        color: c as i32,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        raster,
+        recording::{self, Entry, Event, Pen, Timing},
+    };
+
+    fn settings(room: &str) -> Settings {
+        Settings::parse_from(["whiteboard", "--room", room, "--host", "http://127.0.0.1:9"])
+    }
+
+    /// Stands in for being connected, keeping what would have been sent.
+    fn connected(wb: &WhiteboardSession) -> mpsc::UnboundedReceiver<Drawing> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let (screens, _) = watch::channel(None);
+        *wb.connection.lock().unwrap() = Some(Connection { tx, screens, loops: vec![] });
+        rx
+    }
+
+    #[test]
+    fn color2bool() {
+        assert_eq!(black(true), color::BLACK);
+        assert_eq!(black(false), color::WHITE);
+        assert!(matches!(black(true), color::BLACK));
+    }
+
+    /// A pen stroke, replayed through the handlers, is inked and sent to others.
+    #[test]
+    fn replays_a_recorded_stroke() {
+        let wb = WhiteboardSession::new(settings("a"));
+        let mut rx = connected(&wb);
+
+        let contact = |state| Event::Instrument { pen: Pen::Touch, state };
+        let draws = (0..20).map(|i| Event::Draw {
+            x: 300. + 10. * i as f32,
+            y: 400.,
+            pressure: 2000,
+            tilt: (0, 0),
+        });
+        let events = [Event::Instrument { pen: Pen::Pen, state: true }, contact(true)]
+            .into_iter()
+            .chain(draws)
+            .chain([contact(false)]);
+        let entries: Vec<_> =
+            events.enumerate().map(|(i, event)| Entry { at: 8 * i as u64, event }).collect();
+
+        let mut canvas = raster::Canvas::default();
+        recording::replay(&entries, Timing::Immediate, |evt| wb.on_input(&mut canvas, evt));
+
+        assert_eq!(canvas.image().get_pixel(350, 399).0, [0]);
+        assert_eq!(canvas.image().get_pixel(350, 420).0, [u8::MAX]);
+        let sent = rx.try_recv().unwrap();
+        assert_eq!(sent.xs.len(), 20);
+        assert_eq!(sent.color(), Color::Black);
+    }
+
+    /// Sessions don't share pens, scribbles nor refreshes.
+    #[test]
+    fn hosts_sessions_side_by_side() {
+        let (erasing, drawing) =
+            (WhiteboardSession::new(settings("a")), WhiteboardSession::new(settings("b")));
+        let pen = |pen, state| InputEvent::WacomEvent {
+            event: WacomEvent::InstrumentChange { pen, state },
+        };
+        let mut canvas = raster::Canvas::default();
+        erasing.on_input(&mut canvas, pen(WacomPen::ToolRubber, true));
+        drawing.on_input(&mut canvas, pen(WacomPen::ToolPen, true));
+        assert!(!erasing.pen_black.load(Ordering::Relaxed));
+        assert!(drawing.pen_black.load(Ordering::Relaxed));
+
+        drawing.paint(&mut canvas, top_bar(Color::Black));
+        assert!(drawing.refreshes.lock().unwrap().is_dirty());
+        assert!(!erasing.refreshes.lock().unwrap().is_dirty());
+    }
+
+    /// What the network loops receive gets painted, refreshed and shared on tick.
+    #[test]
+    fn paints_and_shares_what_got_received_on_tick() {
+        let wb = WhiteboardSession::new(settings("a"));
+        let _rx = connected(&wb);
+        let mut screens = wb.connection.lock().unwrap().as_ref().unwrap().screens.subscribe();
+        let drawing = Drawing {
+            xs: vec![300., 350., 400.],
+            ys: vec![400.; 3],
+            pressures: vec![2000; 3],
+            widths: vec![4; 3],
+            color: Color::Black.into(),
+        };
+        wb.received.lock().unwrap().push(drawing);
+        wb.people_count.store(2, Ordering::Relaxed);
+
+        let mut canvas = raster::Canvas::default();
+        wb.tick(&mut canvas, Instant::now());
+        assert!(wb.received.lock().unwrap().is_empty());
+        assert_eq!(canvas.image().get_pixel(350, 400).0, [0]);
+        assert!(!wb.refreshes.lock().unwrap().is_dirty());
+        assert!(!canvas.refreshes().is_empty());
+
+        assert!(screens.has_changed().unwrap());
+        let screen = screens.borrow_and_update().clone().unwrap();
+        assert_eq!(screen.dimensions(), (CANVAS_REGION.width, CANVAS_REGION.height));
+        let at = (350, 400 - CANVAS_REGION.top);
+        assert_eq!(screen.get_pixel(at.0, at.1).0, [0, 0, 0]);
+    }
+
+    /// Connecting twice doesn't spawn loops twice, and disconnecting stops them all.
+    #[tokio::test]
+    async fn connects_once_then_disconnects() {
+        let wb = Arc::new(WhiteboardSession::new(settings("a")));
+        wb.connect().unwrap();
+        let loops = wb.connection.lock().unwrap().as_ref().unwrap().loops.len();
+        let held = Arc::strong_count(&wb);
+        assert!(held > 1);
+
+        wb.connect().unwrap();
+        assert_eq!(wb.connection.lock().unwrap().as_ref().unwrap().loops.len(), loops);
+        assert_eq!(Arc::strong_count(&wb), held);
+
+        wb.disconnect();
+        assert!(!wb.is_connected());
+        for _ in 0..100 {
+            if Arc::strong_count(&wb) == 1 {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("loops still hold {} references", Arc::strong_count(&wb) - 1);
+    }
+}
@@ -1,5 +1,43 @@
+/// Enum fields, written by name with these `json` modules.
+const ENUM_FIELDS: &[(&str, &str)] = &[
+    (".hypercards.Drawing.color", "color"),
+    (".hypercards.RoomSettings.on_idle", "on_idle"),
+    (".hypercards.ExportRoomReq.format", "export_room_format"),
+    (".hypercards.TimelapseReq.format", "timelapse_format"),
+];
+
+/// Bytes fields, written in base64.
+const BYTES_FIELDS: &[&str] = &[
+    ".hypercards.RecvScreenRep.canvas_png",
+    ".hypercards.SendScreenReq.screen_png",
+    ".hypercards.ExportRoomRep.data",
+    ".hypercards.TimelapseRep.data",
+];
+
+/// See `json` for the shape messages take.
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut config = tonic_build::configure()
+        .message_attribute(
+            ".hypercards",
+            "#[derive(serde::Serialize, serde::Deserialize)] #[serde(default)]",
+        )
+        .enum_attribute(
+            ".hypercards.Event.event",
+            "#[derive(serde::Serialize, serde::Deserialize)] #[serde(rename_all = \"snake_case\")]",
+        )
+        // Not ".hypercards.Event.event", which the oneof's variants match too
+        .field_attribute("hypercards.Event.event", "#[serde(flatten)]");
+    for (field, module) in ENUM_FIELDS {
+        config =
+            config.field_attribute(field, format!("#[serde(with = \"crate::json::{module}\")]"));
+    }
+    for field in BYTES_FIELDS {
+        config = config.field_attribute(field, "#[serde(with = \"crate::json::base64\")]");
+    }
     // Uses `protoc` from $PATH (or $PROTOC if set). Requires protobuf-compiler >= 3.6.1.
-    tonic_build::compile_protos("proto/whiteboard.proto")?;
+    config.compile_protos(
+        &["proto/whiteboard.proto", "proto/archive.proto", "proto/admin.proto"],
+        &["proto"],
+    )?;
     Ok(())
 }
@@ -0,0 +1,144 @@
+//! What peers support, told when joining a room
+//!
+//! Clients send their [`Capabilities`] along with `RecvEvents`, servers answer
+//! with theirs in the [`HEADER`] response header. Peers that tell nothing (or
+//! leave fields empty) get what protocol version 1 supports. Each side then
+//! only sends the other what it [`accepts`](Capabilities::accepts).
+//!
+use prost::Message;
+
+use crate::proto::hypercards::{event, Capabilities, Event};
+
+/// Binary response header servers tell their capabilities with.
+pub const HEADER: &str = "x-capabilities-bin";
+
+/// Version of the protocol this build speaks.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Largest message gRPC peers decode by default.
+pub const MAX_EVENT_BYTES: u64 = 4 << 20;
+
+/// Drawings as parallel `xs`, `ys`, `pressures` & `widths` arrays.
+pub const PLAIN: &str = "plain";
+
+/// Event kinds of protocol version 1.
+const V1_EVENT_KINDS: [&str; 4] =
+    ["drawing", "user_left_the_room", "user_joined_the_room", "users_in_the_room"];
+
+/// Name of the `Event.event` field `e` is.
+#[must_use]
+pub fn event_kind(e: &event::Event) -> &'static str {
+    match e {
+        event::Event::Drawing(_) => "drawing",
+        event::Event::UserLeftTheRoom(_) => "user_left_the_room",
+        event::Event::UserJoinedTheRoom(_) => "user_joined_the_room",
+        event::Event::UsersInTheRoom(_) => "users_in_the_room",
+    }
+}
+
+impl Capabilities {
+    /// What this build supports.
+    #[must_use]
+    pub fn ours() -> Self {
+        Self {
+            protocol_version: PROTOCOL_VERSION,
+            event_kinds: V1_EVENT_KINDS.map(str::to_owned).into(),
+            encodings: vec![PLAIN.to_owned()],
+            max_event_bytes: MAX_EVENT_BYTES,
+        }
+    }
+
+    /// What a peer supports, given what it told if anything.
+    #[must_use]
+    pub fn of_peer(told: Option<Self>) -> Self {
+        let Self { protocol_version, event_kinds, encodings, max_event_bytes } =
+            told.unwrap_or_default();
+        Self {
+            protocol_version: protocol_version.max(1),
+            event_kinds: if event_kinds.is_empty() {
+                V1_EVENT_KINDS.map(str::to_owned).into()
+            } else {
+                event_kinds
+            },
+            encodings: if encodings.is_empty() { vec![PLAIN.to_owned()] } else { encodings },
+            max_event_bytes,
+        }
+    }
+
+    /// Reads what a server told in its [`HEADER`], if anything.
+    #[must_use]
+    pub fn from_header(md: &tonic::metadata::MetadataMap) -> Option<Self> {
+        Self::decode(md.get_bin(HEADER)?.to_bytes().ok()?).ok()
+    }
+
+    /// Tells these capabilities in the [`HEADER`].
+    pub fn to_header(&self, md: &mut tonic::metadata::MetadataMap) {
+        let v = tonic::metadata::BinaryMetadataValue::from_bytes(&self.encode_to_vec());
+        md.insert_bin(HEADER, v);
+    }
+
+    /// Whether a peer with these capabilities can be sent `e`.
+    /// Events of no known kind are left for peers to skip.
+    #[must_use]
+    pub fn accepts(&self, e: &Event) -> bool {
+        if self.max_event_bytes != 0 && e.encoded_len() as u64 > self.max_event_bytes {
+            return false;
+        }
+        let Some(kind) = &e.event else { return true };
+        if matches!(kind, event::Event::Drawing(_)) && !self.encodings.iter().any(|e| e == PLAIN) {
+            return false;
+        }
+        self.event_kinds.iter().any(|k| k == event_kind(kind))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::proto::hypercards::Drawing;
+
+    fn ev(e: event::Event) -> Event {
+        Event { event: Some(e), ..Default::default() }
+    }
+
+    fn drawing(points: usize) -> Event {
+        ev(event::Event::Drawing(Drawing {
+            xs: vec![1.; points],
+            ys: vec![1.; points],
+            ..Default::default()
+        }))
+    }
+
+    #[test]
+    fn old_peers_get_version_1() {
+        let old = Capabilities::of_peer(None);
+        assert_eq!(old.protocol_version, 1);
+        assert!(old.accepts(&drawing(1_000_000)));
+        assert!(old.accepts(&ev(event::Event::UserLeftTheRoom(true))));
+        assert_eq!(Capabilities::of_peer(Some(Capabilities::default())), old);
+    }
+
+    #[test]
+    fn peers_only_get_what_they_support() {
+        let told = Capabilities {
+            event_kinds: vec!["drawing".into()],
+            max_event_bytes: 1000,
+            ..Default::default()
+        };
+        let peer = Capabilities::of_peer(Some(told));
+        assert!(peer.accepts(&drawing(10)));
+        assert!(!peer.accepts(&drawing(1000)));
+        assert!(!peer.accepts(&ev(event::Event::UsersInTheRoom(3))));
+
+        let packed_only = Capabilities { encodings: vec!["packed".into()], ..Default::default() };
+        assert!(!Capabilities::of_peer(Some(packed_only)).accepts(&drawing(1)));
+    }
+
+    #[test]
+    fn goes_through_headers() {
+        let mut md = tonic::metadata::MetadataMap::new();
+        assert_eq!(Capabilities::from_header(&md), None);
+        Capabilities::ours().to_header(&mut md);
+        assert_eq!(Capabilities::from_header(&md), Some(Capabilities::ours()));
+    }
+}
@@ -0,0 +1,309 @@
+//! Drawings point by point, rather than as parallel vectors
+//!
+//! [`Drawing::builder`] makes drawings, [`Drawing::points`] walks them (once
+//! their vectors line up), the rest moves, merges and cuts them.
+//!
+use std::ops::Range;
+
+use crate::{
+    ingest::Malformed,
+    proto::hypercards::{drawing::Color, Drawing},
+};
+
+/// Where the pen was and how it pressed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Point {
+    pub x: f32,
+    pub y: f32,
+    pub pressure: i32,
+    pub width: u32,
+}
+
+/// Smallest rectangle containing a drawing's points.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BBox {
+    pub min_x: f32,
+    pub min_y: f32,
+    pub max_x: f32,
+    pub max_y: f32,
+}
+
+impl BBox {
+    #[must_use]
+    pub fn width(&self) -> f32 {
+        self.max_x - self.min_x
+    }
+
+    #[must_use]
+    pub fn height(&self) -> f32 {
+        self.max_y - self.min_y
+    }
+}
+
+/// Maps `(x, y)` to `(a*x + b*y + c, d*x + e*y + f)`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Affine {
+    pub a: f32,
+    pub b: f32,
+    pub c: f32,
+    pub d: f32,
+    pub e: f32,
+    pub f: f32,
+}
+
+impl Default for Affine {
+    fn default() -> Self {
+        Self::translation(0., 0.)
+    }
+}
+
+impl Affine {
+    #[must_use]
+    pub fn translation(dx: f32, dy: f32) -> Self {
+        Self { a: 1., b: 0., c: dx, d: 0., e: 1., f: dy }
+    }
+
+    /// Scales away from the origin.
+    #[must_use]
+    pub fn scaling(sx: f32, sy: f32) -> Self {
+        Self { a: sx, b: 0., c: 0., d: 0., e: sy, f: 0. }
+    }
+
+    /// Rotates clockwise (as `y` grows downwards) around the origin.
+    #[must_use]
+    pub fn rotation(radians: f32) -> Self {
+        let (sin, cos) = radians.sin_cos();
+        Self { a: cos, b: -sin, c: 0., d: sin, e: cos, f: 0. }
+    }
+
+    /// Applies `self` then `next`.
+    #[must_use]
+    pub fn then(&self, next: &Self) -> Self {
+        Self {
+            a: next.a * self.a + next.b * self.d,
+            b: next.a * self.b + next.b * self.e,
+            c: next.a * self.c + next.b * self.f + next.c,
+            d: next.d * self.a + next.e * self.d,
+            e: next.d * self.b + next.e * self.e,
+            f: next.d * self.c + next.e * self.f + next.f,
+        }
+    }
+
+    #[must_use]
+    pub fn apply(&self, x: f32, y: f32) -> (f32, f32) {
+        (self.a * x + self.b * y + self.c, self.d * x + self.e * y + self.f)
+    }
+}
+
+/// Makes a drawing one point at a time.
+#[derive(Debug, Clone)]
+pub struct Builder {
+    d: Drawing,
+}
+
+impl Builder {
+    #[must_use]
+    pub fn point(mut self, x: f32, y: f32, pressure: i32, width: u32) -> Self {
+        self.push(Point { x, y, pressure, width });
+        self
+    }
+
+    #[must_use]
+    pub fn points(mut self, points: impl IntoIterator<Item = Point>) -> Self {
+        for p in points {
+            self.push(p);
+        }
+        self
+    }
+
+    fn push(&mut self, Point { x, y, pressure, width }: Point) {
+        self.d.xs.push(x);
+        self.d.ys.push(y);
+        self.d.pressures.push(pressure);
+        self.d.widths.push(width);
+    }
+
+    #[must_use]
+    pub fn build(self) -> Drawing {
+        self.d
+    }
+}
+
+impl Drawing {
+    #[must_use]
+    pub fn builder(color: Color) -> Builder {
+        Builder { d: Drawing { color: color.into(), ..Default::default() } }
+    }
+
+    /// The drawing's points, provided it has some, its vectors have the same length
+    /// and its coordinates are finite.
+    pub fn points(&self) -> Result<impl ExactSizeIterator<Item = Point> + '_, Malformed> {
+        let n = self.xs.len();
+        if n == 0 {
+            return Err(Malformed::NoPoints);
+        }
+        if [self.ys.len(), self.pressures.len(), self.widths.len()] != [n; 3] {
+            return Err(Malformed::MismatchedLengths);
+        }
+        if !self.xs.iter().chain(&self.ys).all(|c| c.is_finite()) {
+            return Err(Malformed::NotFinite);
+        }
+        Ok((0..n).map(|i| Point {
+            x: self.xs[i],
+            y: self.ys[i],
+            pressure: self.pressures[i],
+            width: self.widths[i],
+        }))
+    }
+
+    /// `None` without coordinates. Extra `xs` or `ys` are ignored.
+    #[must_use]
+    pub fn bbox(&self) -> Option<BBox> {
+        let mut points = self.xs.iter().zip(&self.ys);
+        let (&x, &y) = points.next()?;
+        let init = BBox { min_x: x, min_y: y, max_x: x, max_y: y };
+        Some(points.fold(init, |b, (&x, &y)| BBox {
+            min_x: b.min_x.min(x),
+            min_y: b.min_y.min(y),
+            max_x: b.max_x.max(x),
+            max_y: b.max_y.max(y),
+        }))
+    }
+
+    /// Moves coordinates, leaving pressures & widths be.
+    #[must_use]
+    pub fn transform(mut self, m: &Affine) -> Self {
+        for (x, y) in self.xs.iter_mut().zip(self.ys.iter_mut()) {
+            (*x, *y) = m.apply(*x, *y);
+        }
+        self
+    }
+
+    #[must_use]
+    pub fn translate(self, dx: f32, dy: f32) -> Self {
+        self.transform(&Affine::translation(dx, dy))
+    }
+
+    /// Scales away from the origin.
+    #[must_use]
+    pub fn scale(self, sx: f32, sy: f32) -> Self {
+        self.transform(&Affine::scaling(sx, sy))
+    }
+
+    /// Appends `other`'s points, keeping this drawing's color.
+    #[must_use]
+    pub fn concat(mut self, other: Self) -> Self {
+        self.xs.extend(other.xs);
+        self.ys.extend(other.ys);
+        self.pressures.extend(other.pressures);
+        self.widths.extend(other.widths);
+        self
+    }
+
+    /// Cuts into drawings of at most `max_points` (at least 3) points.
+    /// Consecutive pieces share 2 points, so they paint just like the whole.
+    #[must_use]
+    pub fn split(self, max_points: usize) -> Vec<Self> {
+        let max_points = max_points.max(3);
+        let n = self.xs.len();
+        if n <= max_points {
+            return vec![self];
+        }
+        let step = max_points - 2;
+        (0..n - 2)
+            .step_by(step)
+            .map(|start| {
+                let at = start..(start + max_points).min(n);
+                Self {
+                    xs: piece(&self.xs, &at),
+                    ys: piece(&self.ys, &at),
+                    pressures: piece(&self.pressures, &at),
+                    widths: piece(&self.widths, &at),
+                    color: self.color,
+                }
+            })
+            .collect()
+    }
+}
+
+/// What's of `at` in `v`, however short `v` is.
+fn piece<T: Clone>(v: &[T], at: &Range<usize>) -> Vec<T> {
+    v[at.start.min(v.len())..at.end.min(v.len())].to_vec()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn line(n: usize) -> Drawing {
+        Drawing::builder(Color::Black)
+            .points((0..n).map(|i| Point {
+                x: i as f32,
+                y: 2. * i as f32,
+                pressure: 2000,
+                width: 2,
+            }))
+            .build()
+    }
+
+    #[test]
+    fn builds_then_walks_points() {
+        let d = Drawing::builder(Color::White).point(1., 2., 3, 4).point(5., 6., 7, 8).build();
+        assert_eq!(d.xs, [1., 5.]);
+        assert_eq!(d.widths, [4, 8]);
+        assert_eq!(d.color(), Color::White);
+        let points: Vec<_> = d.points().unwrap().collect();
+        assert_eq!(points[1], Point { x: 5., y: 6., pressure: 7, width: 8 });
+    }
+
+    #[test]
+    fn walks_only_well_formed_drawings() {
+        assert_eq!(Drawing::default().points().err(), Some(Malformed::NoPoints));
+        let d = Drawing { pressures: vec![], ..line(3) };
+        assert_eq!(d.points().err(), Some(Malformed::MismatchedLengths));
+        for c in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+            let d = Drawing { ys: vec![0., c, 2.], ..line(3) };
+            assert_eq!(d.points().err(), Some(Malformed::NotFinite));
+        }
+    }
+
+    #[test]
+    fn bounds_points() {
+        let d = line(3).translate(-1., 1.);
+        let b = d.bbox().unwrap();
+        assert_eq!(b, BBox { min_x: -1., min_y: 1., max_x: 1., max_y: 5. });
+        assert_eq!((b.width(), b.height()), (2., 4.));
+        assert_eq!(Drawing::default().bbox(), None);
+    }
+
+    #[test]
+    fn transforms_coordinates_only() {
+        let d = line(2).scale(2., 3.).translate(1., 1.);
+        assert_eq!((d.xs, d.ys, d.pressures), (vec![1., 3.], vec![1., 7.], vec![2000, 2000]));
+
+        let m = Affine::scaling(2., 2.).then(&Affine::translation(1., 0.));
+        assert_eq!(m.apply(1., 1.), (3., 2.));
+        let (x, y) = Affine::rotation(std::f32::consts::FRAC_PI_2).apply(1., 0.);
+        assert!(x.abs() < 1e-6 && (y - 1.).abs() < 1e-6);
+        assert_eq!(line(3).transform(&Affine::default()), line(3));
+    }
+
+    #[test]
+    fn splits_then_concatenates() {
+        let d = line(10);
+        let pieces = d.clone().split(4);
+        assert_eq!(pieces.iter().map(|p| p.xs.len()).collect::<Vec<_>>(), [4, 4, 4, 4]);
+        // Pieces overlap by 2 points
+        assert_eq!(pieces[1].xs, [2., 3., 4., 5.]);
+        assert_eq!(pieces[3].xs, [6., 7., 8., 9.]);
+        assert!(pieces.iter().all(|p| p.points().is_ok()));
+
+        assert_eq!(line(3).split(4), [line(3)]);
+    }
+
+    #[test]
+    fn concatenates() {
+        let tail = line(3).translate(2., 4.);
+        assert_eq!(line(2).concat(tail), line(5));
+    }
+}
@@ -0,0 +1,167 @@
+//! Drawings from the network or from files, made safe to paint
+//!
+//! [`normalize`] rejects what can't be painted and reconciles the rest:
+//! per-point vectors get the length of the shortest coordinates vector
+//! (missing pressures & widths repeat the last known one), points are clamped
+//! to the canvas, pressures & widths to what a pen produces.
+//!
+use std::fmt;
+
+use crate::proto::hypercards::{drawing::Color, Drawing};
+
+/// Hardest a pen presses.
+pub const MAX_PRESSURE: i32 = 4096;
+/// Widest a tip gets, erasers included.
+pub const MAX_WIDTH: u32 = 128;
+/// Pressure of points that came without any.
+pub const DEFAULT_PRESSURE: i32 = 2000;
+/// Width of points that came without any.
+pub const DEFAULT_WIDTH: u32 = 2;
+
+/// Where drawings get painted, from `(0, 0)` to `(width, height)`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Canvas {
+    pub width: f32,
+    pub height: f32,
+}
+
+/// Why a drawing can't be painted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Malformed {
+    /// No point has both coordinates.
+    NoPoints,
+    /// Coordinates, pressures & widths don't line up.
+    MismatchedLengths,
+    /// Some coordinate is NaN or infinite.
+    NotFinite,
+    Invisible,
+    /// A color this build doesn't know about.
+    UnknownColor(i32),
+}
+
+impl fmt::Display for Malformed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoPoints => write!(f, "drawing has no points"),
+            Self::MismatchedLengths => write!(f, "drawing has vectors of different lengths"),
+            Self::NotFinite => write!(f, "drawing has non-finite coordinates"),
+            Self::Invisible => write!(f, "drawing is invisible"),
+            Self::UnknownColor(c) => write!(f, "drawing has unknown color {c}"),
+        }
+    }
+}
+
+impl std::error::Error for Malformed {}
+
+/// Makes `d` safe to paint on `canvas`, or tells why it can't be.
+pub fn normalize(d: Drawing, canvas: &Canvas) -> Result<Drawing, Malformed> {
+    let Drawing { mut xs, mut ys, pressures, widths, color } = d;
+    match Color::try_from(color) {
+        Err(_) => return Err(Malformed::UnknownColor(color)),
+        Ok(Color::Invisible) => return Err(Malformed::Invisible),
+        Ok(Color::Black | Color::White) => {}
+    }
+
+    let n = xs.len().min(ys.len());
+    if n == 0 {
+        return Err(Malformed::NoPoints);
+    }
+    xs.truncate(n);
+    ys.truncate(n);
+    if !xs.iter().chain(&ys).all(|c| c.is_finite()) {
+        return Err(Malformed::NotFinite);
+    }
+
+    let xs = xs.into_iter().map(|x| x.clamp(0., canvas.width)).collect();
+    let ys = ys.into_iter().map(|y| y.clamp(0., canvas.height)).collect();
+    let pressures = fit(pressures, n, DEFAULT_PRESSURE, |p| p.clamp(0, MAX_PRESSURE));
+    let widths = fit(widths, n, DEFAULT_WIDTH, |w| w.min(MAX_WIDTH));
+    Ok(Drawing { xs, ys, pressures, widths, color })
+}
+
+/// Resizes to `n`, repeating the last value (or `default`), and caps values.
+fn fit<T: Copy>(mut vs: Vec<T>, n: usize, default: T, cap: impl Fn(T) -> T) -> Vec<T> {
+    let last = vs.last().copied().unwrap_or(default);
+    vs.resize(n, last);
+    vs.into_iter().map(cap).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const CANVAS: Canvas = Canvas { width: 100., height: 200. };
+
+    fn d() -> Drawing {
+        Drawing {
+            xs: vec![1., 2., 3.],
+            ys: vec![4., 5., 6.],
+            pressures: vec![2000, 2001, 2002],
+            widths: vec![2, 3, 4],
+            color: Color::Black.into(),
+        }
+    }
+
+    #[test]
+    fn keeps_well_formed_drawings() {
+        assert_eq!(normalize(d(), &CANVAS), Ok(d()));
+    }
+
+    #[test]
+    fn rejects_drawings_without_points() {
+        assert_eq!(normalize(Drawing { xs: vec![], ..d() }, &CANVAS), Err(Malformed::NoPoints));
+        assert_eq!(normalize(Drawing { ys: vec![], ..d() }, &CANVAS), Err(Malformed::NoPoints));
+    }
+
+    #[test]
+    fn rejects_non_finite_coordinates() {
+        for bad in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+            let xs = Drawing { xs: vec![1., bad, 3.], ..d() };
+            assert_eq!(normalize(xs, &CANVAS), Err(Malformed::NotFinite));
+            let ys = Drawing { ys: vec![4., 5., bad], ..d() };
+            assert_eq!(normalize(ys, &CANVAS), Err(Malformed::NotFinite));
+        }
+        // Unless they are dropped for lack of the other coordinate
+        let trailing = Drawing { xs: vec![1., 2., 3., f32::NAN], ..d() };
+        assert_eq!(normalize(trailing, &CANVAS), Ok(d()));
+    }
+
+    #[test]
+    fn rejects_invisible_and_unknown_colors() {
+        let invisible = Drawing { color: Color::Invisible.into(), ..d() };
+        assert_eq!(normalize(invisible, &CANVAS), Err(Malformed::Invisible));
+        let unknown = Drawing { color: 42, ..d() };
+        assert_eq!(normalize(unknown, &CANVAS), Err(Malformed::UnknownColor(42)));
+    }
+
+    #[test]
+    fn reconciles_lengths() {
+        let long = Drawing { ys: vec![4., 5., 6., 7.], ..d() };
+        assert_eq!(normalize(long, &CANVAS), Ok(d()));
+
+        let short = Drawing { pressures: vec![2000], widths: vec![], ..d() };
+        let got = normalize(short, &CANVAS).unwrap();
+        assert_eq!(got.pressures, [2000; 3]);
+        assert_eq!(got.widths, [DEFAULT_WIDTH; 3]);
+
+        let extra = Drawing { pressures: vec![1; 9], widths: vec![1; 9], ..d() };
+        let got = normalize(extra, &CANVAS).unwrap();
+        assert_eq!((got.pressures.len(), got.widths.len()), (3, 3));
+    }
+
+    #[test]
+    fn clamps_to_canvas_and_caps_pen() {
+        let wild = Drawing {
+            xs: vec![-1., 50., 1e9],
+            ys: vec![-1e9, 50., 201.],
+            pressures: vec![-5, 2000, i32::MAX],
+            widths: vec![0, 2, u32::MAX],
+            color: Color::White.into(),
+        };
+        let got = normalize(wild, &CANVAS).unwrap();
+        assert_eq!(got.xs, [0., 50., 100.]);
+        assert_eq!(got.ys, [0., 50., 200.]);
+        assert_eq!(got.pressures, [0, 2000, MAX_PRESSURE]);
+        assert_eq!(got.widths, [0, 2, MAX_WIDTH]);
+    }
+}
@@ -0,0 +1,143 @@
+//! How messages look in JSON: over the REST API, in .jsonl files, out of `hc`...
+//!
+//! Every message (de)serializes with serde. Fields keep their protobuf names,
+//! oneofs are flattened into their message, enums are written by name (read by
+//! name, any case, or by number) and bytes in base64. Missing fields get their
+//! default value:
+//! `{"by_user_id":"joe","drawing":{"xs":[1.0],"ys":[2.0],...,"color":"BLACK"}}`
+//!
+use serde::{de, Deserialize, Deserializer, Serializer};
+
+/// Enum fields are `i32`s: numbers unknown to this build get written as is.
+macro_rules! enum_by_name {
+    ($module:ident, $enum:path) => {
+        pub(crate) mod $module {
+            use super::*;
+
+            pub(crate) fn serialize<S: Serializer>(v: &i32, s: S) -> Result<S::Ok, S::Error> {
+                match <$enum>::try_from(*v) {
+                    Ok(e) => s.serialize_str(e.as_str_name()),
+                    Err(_) => s.serialize_i32(*v),
+                }
+            }
+
+            pub(crate) fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<i32, D::Error> {
+                match NameOrNumber::deserialize(d)? {
+                    NameOrNumber::Number(v) => Ok(v),
+                    NameOrNumber::Name(name) => {
+                        [name.clone(), name.to_uppercase(), name.to_lowercase()]
+                            .iter()
+                            .find_map(|n| <$enum>::from_str_name(n))
+                            .map(Into::into)
+                            .ok_or_else(|| {
+                                de::Error::custom(format!(
+                                    "unknown {}: {name:?}",
+                                    stringify!($module)
+                                ))
+                            })
+                    }
+                }
+            }
+        }
+    };
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum NameOrNumber {
+    Name(String),
+    Number(i32),
+}
+
+enum_by_name!(color, crate::proto::hypercards::drawing::Color);
+enum_by_name!(on_idle, crate::proto::hypercards::room_settings::OnIdle);
+enum_by_name!(export_room_format, crate::proto::hypercards::export_room_req::Format);
+enum_by_name!(timelapse_format, crate::proto::hypercards::timelapse_req::Format);
+
+pub(crate) mod base64 {
+    use ::base64::{engine::general_purpose::STANDARD, Engine};
+
+    use super::*;
+
+    pub(crate) fn serialize<S: Serializer>(v: &[u8], s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&STANDARD.encode(v))
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<u8>, D::Error> {
+        let s = String::deserialize(d)?;
+        STANDARD.decode(s).map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use crate::proto::hypercards::{
+        drawing::Color, event, timelapse_req, Drawing, Event, RecvScreenRep, TimelapseReq,
+    };
+
+    fn event() -> Event {
+        let d = Drawing {
+            xs: vec![1.],
+            ys: vec![2.],
+            pressures: vec![2000],
+            widths: vec![2],
+            color: Color::Black.into(),
+        };
+        Event {
+            by_user_id: "joe".into(),
+            event: Some(event::Event::Drawing(d)),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn events_round_trip() {
+        let json = serde_json::to_value(event()).unwrap();
+        assert_eq!(
+            json,
+            json!({
+                "created_at": 0,
+                "by_user_id": "joe",
+                "in_room_id": "",
+                "drawing": {
+                    "xs": [1.0],
+                    "ys": [2.0],
+                    "pressures": [2000],
+                    "widths": [2],
+                    "color": "BLACK",
+                },
+                "origin_server_id": "",
+                "via_server_ids": [],
+            })
+        );
+        assert_eq!(serde_json::from_value::<Event>(json).unwrap(), event());
+
+        let joined: Event = serde_json::from_value(json!({"user_joined_the_room": true})).unwrap();
+        assert_eq!(joined.event, Some(event::Event::UserJoinedTheRoom(true)));
+        let none: Event = serde_json::from_value(json!({"by_user_id": "joe"})).unwrap();
+        assert_eq!(none.event, None);
+    }
+
+    #[test]
+    fn reads_colors_by_name_or_number() {
+        for color in [json!("WHITE"), json!("white"), json!(2)] {
+            let d: Drawing = serde_json::from_value(json!({"color": color})).unwrap();
+            assert_eq!(d.color(), Color::White);
+        }
+        assert!(serde_json::from_value::<Drawing>(json!({"color": "pink"})).is_err());
+
+        let req: TimelapseReq = serde_json::from_value(json!({"format": "apng"})).unwrap();
+        assert_eq!(req.format(), timelapse_req::Format::Apng);
+        assert_eq!(serde_json::to_value(&req).unwrap()["format"], "APNG");
+    }
+
+    #[test]
+    fn writes_bytes_in_base64() {
+        let rep = RecvScreenRep { canvas_png: b"\x89PNG".to_vec() };
+        let json = serde_json::to_value(&rep).unwrap();
+        assert_eq!(json, json!({"canvas_png": "iVBORw=="}));
+        assert_eq!(serde_json::from_value::<RecvScreenRep>(json).unwrap(), rep);
+    }
+}
@@ -1,3 +1,8 @@
+pub mod capabilities;
+pub mod drawing;
+pub mod ingest;
+mod json;
+
 pub mod proto {
     pub mod hypercards {
         // subl $(ls -t target/*/*/build/marauder-*/out/*.rs|head -n1)
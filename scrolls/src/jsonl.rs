@@ -1,24 +1,29 @@
-use std::{collections::HashSet, env};
+use std::{
+    env,
+    io::{self, Write},
+};
 
 use anyhow::Result;
-use libremarkable::appctx::ApplicationContext;
-use log::info;
+use log::{info, warn};
+use marauder::surface::Surface;
 use pb::proto::hypercards::{drawing::Color, Drawing};
 use ringbuffer::{AllocRingBuffer, RingBuffer};
-use serde::Deserialize;
+use serde_jsonlines::WriteExt;
 use tokio::time::sleep;
 
-use crate::paint::{paint, DRAWING_PACE, INTER_DRAWING_PACE};
+use crate::{
+    ingest,
+    paint::{paint, DRAWING_PACE, INTER_DRAWING_PACE},
+};
 
 const PAUSE: bool = true;
 const SYNC: bool = false;
 
-pub(crate) async fn read_and_paint(app: &mut ApplicationContext<'_>, fpath: String) -> Result<()> {
+pub async fn read_and_paint(s: &mut impl Surface, fpath: String) -> Result<()> {
     let mut ring = AllocRingBuffer::new(37);
 
-    for d in serde_jsonlines::json_lines(fpath)? {
-        let d: DrawingBis = d?;
-        let d: Drawing = d.into();
+    for d in serde_jsonlines::json_lines(&fpath)? {
+        let Some(d) = drawing(&fpath, d)? else { continue };
         let c = d.color();
 
         info!(target:env!("CARGO_PKG_NAME"), "{act} XxYxPxW: {x}x{y}x{p}x{w}",
@@ -29,15 +34,10 @@ pub(crate) async fn read_and_paint(app: &mut ApplicationContext<'_>, fpath: Stri
             w = d.widths.len(),
         );
 
-        assert_eq!(
-            HashSet::from([d.xs.len()]),
-            HashSet::from([d.ys.len(), d.pressures.len(), d.widths.len()])
-        );
-
         if PAUSE {
             sleep(if true { DRAWING_PACE } else { INTER_DRAWING_PACE }).await;
         }
-        paint(app, &d, PAUSE, SYNC).await;
+        paint(s, &d, PAUSE, SYNC).await;
 
         if c == Color::Black {
             ring.enqueue(d.clone());
@@ -45,69 +45,130 @@ pub(crate) async fn read_and_paint(app: &mut ApplicationContext<'_>, fpath: Stri
         while ring.is_full() {
             let Some(x) = ring.dequeue() else { break };
             let x = Drawing { color: Color::White.into(), ..x };
-            paint(app, &x, PAUSE, SYNC).await;
+            paint(s, &x, PAUSE, SYNC).await;
         }
     }
 
     for x in ring.drain() {
         let x = Drawing { color: Color::White.into(), ..x };
-        paint(app, &x, PAUSE, SYNC).await;
+        paint(s, &x, PAUSE, SYNC).await;
     }
     Ok(())
 }
 
-#[derive(Debug, Deserialize)]
-pub(crate) struct DrawingBis {
-    pub xs: Vec<f32>,
-    pub ys: Vec<f32>,
-    pub pressures: Vec<i32>,
-    pub widths: Vec<u32>,
-    pub color: String,
+/// Reads all (paintable) drawings at once, e.g. to send them elsewhere.
+pub fn read(fpath: &str) -> Result<Vec<Drawing>> {
+    let mut ds = vec![];
+    for d in serde_jsonlines::json_lines(fpath)? {
+        ds.extend(drawing(fpath, d)?);
+    }
+    Ok(ds)
 }
 
-impl From<DrawingBis> for Drawing {
-    fn from(DrawingBis { xs, ys, pressures, widths, color }: DrawingBis) -> Self {
-        let color = match color.to_lowercase().as_ref() {
-            "black" => Color::Black,
-            "white" => Color::White,
-            _ => Color::Invisible,
-        } as i32;
-        Self { xs, ys, pressures, widths, color }
+/// Makes a line's drawing safe to paint. Lines that are no drawings this build
+/// knows of (e.g. of an unknown color) get skipped, like unpaintable drawings.
+fn drawing(fpath: &str, line: io::Result<Drawing>) -> Result<Option<Drawing>> {
+    match line {
+        Ok(d) => Ok(ingest(fpath, d)),
+        Err(e)
+            if e.get_ref()
+                .and_then(|e| e.downcast_ref::<serde_json::Error>())
+                .is_some_and(serde_json::Error::is_data) =>
+        {
+            warn!(target:env!("CARGO_PKG_NAME"), "skipping from {fpath}: {e}");
+            Ok(None)
+        }
+        Err(e) => Err(e.into()),
     }
 }
 
+/// Writes drawings one per line, in the shape [`read_and_paint`] replays.
+pub fn write<'a, W: Write>(mut w: W, ds: impl IntoIterator<Item = &'a Drawing>) -> Result<()> {
+    w.write_json_lines(ds)?;
+    Ok(())
+}
+
 #[test]
 fn reads_a_drawing_from_jsonl() {
-    #[inline]
-    fn d() -> DrawingBis {
-        DrawingBis {
-            xs: [
-                99.82016, 99.82016, 99.90944, 100.08801, 100.17729, 100.355865, 100.62372,
-                100.802284, 101.159424, 101.60585, 102.05227, 102.58798, 103.0344, 103.57011,
-                104.10582, 104.730804, 105.53437,
-            ]
-            .into(),
-            ys: [
-                72.14423, 73.126396, 74.10855, 74.91214, 75.71573, 76.43003, 77.23361, 77.85863,
-                78.39435, 78.93008, 79.287224, 79.733665, 80.1801, 80.62653, 81.072975, 81.43012,
-                81.78727,
-            ]
-            .into(),
-            widths: [2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2].into(),
-            pressures: [
-                2326, 2294, 2244, 2191, 2149, 2083, 1989, 1899, 1794, 1674, 1542, 1420, 1330, 1256,
-                1228, 1223, 20,
-            ]
-            .into(),
-            color: "BLACK".into(),
-        }
-    }
+    let line = concat!(
+        r#"{"xs":[99.82016,99.82016,99.90944,100.08801,100.17729,100.355865,100.62372,"#,
+        r#"100.802284,101.159424,101.60585,102.05227,102.58798,103.0344,103.57011,"#,
+        r#"104.10582,104.730804,105.53437],"#,
+        r#""ys":[72.14423,73.126396,74.10855,74.91214,75.71573,76.43003,77.23361,77.85863,"#,
+        r#"78.39435,78.93008,79.287224,79.733665,80.1801,80.62653,81.072975,81.43012,"#,
+        r#"81.78727],"#,
+        r#""pressures":[2326,2294,2244,2191,2149,2083,1989,1899,1794,1674,1542,1420,1330,1256,"#,
+        r#"1228,1223,20],"#,
+        r#""widths":[2,2,2,2,2,2,2,2,2,2,2,2,2,2,2,2,2],"#,
+        r#""color":"BLACK"}"#,
+    );
+    let p: Drawing = serde_json::from_str(line).unwrap();
 
-    let p: Drawing = d().into();
-
-    assert_eq!(d().xs, p.xs);
-    assert_eq!(d().ys, p.ys);
-    assert_eq!(d().pressures, p.pressures);
-    assert_eq!(d().widths, p.widths);
+    assert_eq!(
+        p.xs,
+        [
+            99.82016, 99.82016, 99.90944, 100.08801, 100.17729, 100.355865, 100.62372, 100.802284,
+            101.159424, 101.60585, 102.05227, 102.58798, 103.0344, 103.57011, 104.10582,
+            104.730804, 105.53437,
+        ]
+    );
+    assert_eq!(
+        p.ys,
+        [
+            72.14423, 73.126396, 74.10855, 74.91214, 75.71573, 76.43003, 77.23361, 77.85863,
+            78.39435, 78.93008, 79.287224, 79.733665, 80.1801, 80.62653, 81.072975, 81.43012,
+            81.78727,
+        ]
+    );
+    assert_eq!(
+        p.pressures,
+        [
+            2326, 2294, 2244, 2191, 2149, 2083, 1989, 1899, 1794, 1674, 1542, 1420, 1330, 1256,
+            1228, 1223, 20,
+        ]
+    );
+    assert_eq!(p.widths, [2; 17]);
     assert_eq!(Color::Black, p.color());
 }
+
+#[test]
+fn writes_drawings_as_jsonl() {
+    let d = Drawing {
+        xs: [1., 2.5].into(),
+        ys: [3., 4.].into(),
+        pressures: [2000, 2001].into(),
+        widths: [2, 3].into(),
+        color: Color::White.into(),
+    };
+
+    let mut buf = vec![];
+    write(&mut buf, [&d, &d]).unwrap();
+    let line =
+        r#"{"xs":[1.0,2.5],"ys":[3.0,4.0],"pressures":[2000,2001],"widths":[2,3],"color":"WHITE"}"#;
+    assert_eq!(String::from_utf8(buf.clone()).unwrap(), format!("{line}\n{line}\n"));
+
+    let read: Vec<Drawing> = serde_jsonlines::JsonLinesReader::new(buf.as_slice())
+        .read_all::<Drawing>()
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(read, vec![d.clone(), d]);
+}
+
+#[test]
+fn skips_or_fixes_malformed_drawings() {
+    let fpath = env::temp_dir().join(format!("scrolls-{}.jsonl", std::process::id()));
+    let lines = [
+        r#"{"xs":[1.0,2.0],"ys":[3.0,4.0],"pressures":[2000,2001],"widths":[2,3],"color":"BLACK"}"#,
+        r#"{"xs":[1.0,2.0],"ys":[3.0],"pressures":[],"widths":[2,3,4],"color":"BLACK"}"#,
+        r#"{"xs":[],"ys":[],"pressures":[],"widths":[],"color":"BLACK"}"#,
+        r#"{"xs":[1.0],"ys":[3.0],"pressures":[1],"widths":[1],"color":"PURPLE"}"#,
+        r#"{"xs":[-5.0],"ys":[1e9],"pressures":[1],"widths":[1],"color":"WHITE"}"#,
+    ];
+    std::fs::write(&fpath, lines.join("\n")).unwrap();
+    let ds = read(fpath.to_str().unwrap()).unwrap();
+    std::fs::remove_file(&fpath).unwrap();
+
+    assert_eq!(ds.len(), 3);
+    assert_eq!((ds[1].xs.len(), ds[1].pressures.len(), ds[1].widths.len()), (1, 1, 1));
+    assert_eq!((ds[2].xs[0], ds[2].ys[0]), (0., crate::CANVAS.height));
+}
@@ -0,0 +1,34 @@
+//! Read scribbles, drawings, doodles, convos... and paint them
+//!
+pub mod jsonl;
+pub mod ndjson;
+pub mod paint;
+pub mod svg;
+
+use anyhow::{bail, Result};
+use libremarkable::dimensions::{DISPLAYHEIGHT, DISPLAYWIDTH};
+use log::warn;
+use pb::{
+    ingest::{self, Canvas},
+    proto::hypercards::Drawing,
+};
+
+/// Where scrolls get painted.
+pub const CANVAS: Canvas = Canvas { width: DISPLAYWIDTH as f32, height: DISPLAYHEIGHT as f32 };
+
+/// Reads a whole .jsonl, .ndjson or .svg file, picking the format from its extension.
+pub fn read(fpath: &str) -> Result<Vec<Drawing>> {
+    match fpath {
+        _ if fpath.ends_with(".jsonl") => jsonl::read(fpath),
+        _ if fpath.ends_with(".ndjson") => ndjson::read(fpath),
+        _ if fpath.ends_with(".svg") => svg::read(fpath),
+        _ => bail!("No idea how to read {fpath}"),
+    }
+}
+
+/// Makes a drawing read from `fpath` safe to paint, or logs why it gets skipped.
+pub(crate) fn ingest(fpath: &str, d: Drawing) -> Option<Drawing> {
+    ingest::normalize(d, &CANVAS)
+        .inspect_err(|e| warn!(target:env!("CARGO_PKG_NAME"), "skipping from {fpath}: {e}"))
+        .ok()
+}
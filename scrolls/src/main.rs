@@ -8,13 +8,9 @@ use std::{env, process::Command};
 use anyhow::Result;
 use libremarkable::appctx::{self, ApplicationContext};
 use log::{debug, error, info};
+use scrolls::{jsonl, ndjson, paint::Replay, svg};
 use tokio::task::spawn_blocking;
 
-mod jsonl;
-mod ndjson;
-mod paint;
-mod svg;
-
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     env_logger::init();
@@ -41,12 +37,13 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 }
 
 async fn paint_scrolls(app: &mut ApplicationContext<'_>) -> Result<()> {
+    let fb = &mut Replay(app.get_framebuffer_ref());
     for fpath in env::args().skip(1) {
         debug!(target:env!("CARGO_PKG_NAME"), "opening {fpath}...");
         match fpath {
-            _ if fpath.ends_with(".jsonl") => jsonl::read_and_paint(app, fpath).await?,
-            _ if fpath.ends_with(".ndjson") => ndjson::read_and_paint(app, fpath).await?,
-            _ if fpath.ends_with(".svg") => svg::read_and_paint(app, fpath).await?,
+            _ if fpath.ends_with(".jsonl") => jsonl::read_and_paint(fb, fpath).await?,
+            _ if fpath.ends_with(".ndjson") => ndjson::read_and_paint(fb, fpath).await?,
+            _ if fpath.ends_with(".svg") => svg::read_and_paint(fb, fpath).await?,
             _ => error!(target:env!("CARGO_PKG_NAME"), "No idea how to read {fpath}"),
         }
     }
@@ -6,20 +6,21 @@
 //! https://github.com/googlecreativelab/quickdraw-dataset/issues/19#issuecomment-402247262
 //! https://www.wikiwand.com/fr/Algorithme_de_Knuth-Morris-Pratt
 //!
-use std::{env, iter::repeat_n, time::Duration};
+use std::{env, time::Duration};
 
 use anyhow::Result;
-use libremarkable::{
-    appctx::ApplicationContext,
-    dimensions::{DISPLAYHEIGHT, DISPLAYWIDTH},
-};
+use libremarkable::dimensions::{DISPLAYHEIGHT, DISPLAYWIDTH};
 use log::info;
-use pb::proto::hypercards::{drawing::Color, Drawing};
+use marauder::surface::Surface;
+use pb::{
+    drawing::Point,
+    proto::hypercards::{drawing::Color, Drawing},
+};
 use ringbuffer::{AllocRingBuffer, RingBuffer};
 use serde::Deserialize;
 use tokio::time::sleep;
 
-use crate::paint::paint;
+use crate::{ingest, paint::paint};
 
 #[test]
 fn sizes() {
@@ -27,18 +28,33 @@ fn sizes() {
     assert_eq!(DISPLAYWIDTH / 75, 18);
 }
 
-pub(crate) async fn read_and_paint(app: &mut ApplicationContext<'_>, fpath: String) -> Result<()> {
-    const W: f32 = 2. * 75.;
-    const H: f32 = 2. * 100.;
-    const COLS: f32 = (DISPLAYWIDTH as f32 / W) * 2. - 1. - 1.;
-    const ROWS: f32 = (DISPLAYHEIGHT as f32 / H) * 2. - 1.;
+const W: f32 = 2. * 75.;
+const H: f32 = 2. * 100.;
+const COLS: f32 = (DISPLAYWIDTH as f32 / W) * 2. - 1. - 1.;
+const ROWS: f32 = (DISPLAYHEIGHT as f32 / H) * 2. - 1.;
+
+/// Places the `i`th doodle's drawing on a grid that fills the screen.
+fn layout(i: f32, d: Drawing) -> Drawing {
+    d.scale(0.5, 0.5).translate(W * ((i / ROWS) % COLS), H * (i % ROWS))
+}
+
+/// Reads all (recognized) doodles at once, laid out as [`read_and_paint`] would.
+pub fn read(fpath: &str) -> Result<Vec<Drawing>> {
+    let mut all = vec![];
+    for (i, ds) in serde_jsonlines::json_lines(fpath)?.enumerate() {
+        let ds: DrawingBis = ds?;
+        all.extend(ds.into_vec().into_iter().filter_map(|d| ingest(fpath, layout(i as f32, d))));
+    }
+    Ok(all)
+}
 
+pub async fn read_and_paint(s: &mut impl Surface, fpath: String) -> Result<()> {
     const PAUSE: Duration = Duration::from_millis(50);
     const SYNC: bool = false;
 
     let mut ring = AllocRingBuffer::new((COLS / 2.) as usize);
 
-    for (i, ds) in serde_jsonlines::json_lines(fpath)?.enumerate() {
+    for (i, ds) in serde_jsonlines::json_lines(&fpath)?.enumerate() {
         let ds: DrawingBis = ds?;
         let ds: Vec<Drawing> = ds.into_vec();
 
@@ -53,13 +69,9 @@ pub(crate) async fn read_and_paint(app: &mut ApplicationContext<'_>, fpath: Stri
                 y = d.ys.len(),
             );
 
-            let d = Drawing {
-                xs: d.xs.into_iter().map(|x| 0.5 * x + W * ((i / ROWS) % COLS)).collect(),
-                ys: d.ys.into_iter().map(|y| 0.5 * y + H * (i % ROWS)).collect(),
-                ..d
-            };
+            let Some(d) = ingest(&fpath, layout(i, d)) else { continue };
 
-            paint(app, &d, true, SYNC).await;
+            paint(s, &d, true, SYNC).await;
             sleep(PAUSE).await;
 
             if c == Color::Black {
@@ -68,7 +80,7 @@ pub(crate) async fn read_and_paint(app: &mut ApplicationContext<'_>, fpath: Stri
             while ring.is_full() {
                 let Some(x) = ring.dequeue() else { break };
                 let x = Drawing { color: Color::White.into(), ..x };
-                paint(app, &x, true, SYNC).await;
+                paint(s, &x, true, SYNC).await;
                 // sleep(PAUSE).await;
             }
         }
@@ -76,7 +88,7 @@ pub(crate) async fn read_and_paint(app: &mut ApplicationContext<'_>, fpath: Stri
 
     for x in ring.drain() {
         let x = Drawing { color: Color::White.into(), ..x };
-        paint(app, &x, true, SYNC).await;
+        paint(s, &x, true, SYNC).await;
         sleep(PAUSE).await;
     }
     Ok(())
@@ -113,15 +125,14 @@ impl DrawingBis {
         drawing
             .into_iter()
             .map(|[xs, ys]| {
-                let n = xs.len();
-                assert_eq!(n, ys.len());
-                Drawing {
-                    xs: xs.into_iter().map(f32::from).collect(),
-                    ys: ys.into_iter().map(f32::from).collect(),
-                    pressures: repeat_n(PRESSURE, n).collect(),
-                    widths: repeat_n(WIDTH, n).collect(),
-                    color: Color::Black as i32,
-                }
+                assert_eq!(xs.len(), ys.len());
+                let points = xs.into_iter().zip(ys).map(|(x, y)| Point {
+                    x: x.into(),
+                    y: y.into(),
+                    pressure: PRESSURE,
+                    width: WIDTH,
+                });
+                Drawing::builder(Color::Black).points(points).build()
             })
             .collect()
     }
@@ -1,82 +1,52 @@
 use std::time::Duration;
 
 use libremarkable::{
-    appctx::ApplicationContext,
+    cgmath::Point2,
     framebuffer::{
-        cgmath,
-        cgmath::EuclideanSpace,
-        common::{color, display_temp, dither_mode, waveform_mode, DRAWING_QUANT_BIT_3},
-        FramebufferDraw, FramebufferRefresh, PartialRefreshMode,
+        common::{
+            color, display_temp, dither_mode, mxcfb_rect, waveform_mode, DRAWING_QUANT_BIT_3,
+        },
+        core::Framebuffer,
+        FramebufferRefresh,
     },
+    image::RgbImage,
 };
-use pb::proto::hypercards::{drawing::Color, Drawing};
-use tokio::time::sleep;
+use marauder::{
+    ink::{self, Pace},
+    surface::{self, Refresh, Surface, Waveform},
+};
+use pb::proto::hypercards::Drawing;
 
 pub(crate) const DRAWING_PACE: Duration = Duration::from_millis(2);
 pub(crate) const INTER_DRAWING_PACE: Duration = Duration::from_millis(8);
 
-pub(crate) async fn paint(
-    app: &mut ApplicationContext<'_>,
-    drawing: &Drawing,
-    pause: bool,
-    sync: bool,
-) {
-    if drawing.xs.len() < 3 {
-        return;
+/// Replays a drawing curve by curve when pausing, or shows it all at once.
+pub(crate) async fn paint(s: &mut impl Surface, drawing: &Drawing, pause: bool, sync: bool) {
+    let pace = if pause { Pace::Animated(DRAWING_PACE) } else { Pace::Batched };
+    let refresh = if sync { Refresh::Wait } else { Refresh::Async };
+    ink::paint(s, drawing, pace, refresh).await;
+}
+
+/// The display, refreshing ink the way replays always have: with `DRAWING_QUANT_BIT_3`
+/// rather than the live pen's `DRAWING_QUANT_BIT`.
+pub struct Replay<'a>(pub &'a mut Framebuffer);
+
+impl Surface for Replay<'_> {
+    fn dimensions(&self) -> (u32, u32) {
+        self.0.dimensions()
     }
 
-    let col = match drawing.color() {
-        Color::White => color::WHITE,
-        _ => color::BLACK,
-    };
-    for i in 0..(drawing.xs.len() - 2) {
-        if i != 0 && pause {
-            sleep(DRAWING_PACE).await;
-        }
-        let points: Vec<(cgmath::Point2<f32>, i32, u32)> = vec![
-            // start
-            (
-                cgmath::Point2 { x: drawing.xs[i], y: drawing.ys[i] },
-                drawing.pressures[i],
-                drawing.widths[i],
-            ),
-            // ctrl
-            (
-                cgmath::Point2 { x: drawing.xs[i + 1], y: drawing.ys[i + 1] },
-                drawing.pressures[i + 1],
-                drawing.widths[i + 1],
-            ),
-            // end
-            (
-                cgmath::Point2 { x: drawing.xs[i + 2], y: drawing.ys[i + 2] },
-                drawing.pressures[i + 2],
-                drawing.widths[i + 2],
-            ),
-        ];
-        let radii: Vec<f32> = points
-            .iter()
-            .map(|(_, pressure, tip)| (((*tip as f32) * (*pressure as f32)) / 2048.) / 2.)
-            .collect();
-        // calculate control points
-        let start_point = points[2].0.midpoint(points[1].0);
-        let ctrl_point = points[1].0;
-        let end_point = points[1].0.midpoint(points[0].0);
-        // calculate diameters
-        let start_width = radii[2] + radii[1];
-        let ctrl_width = radii[1] * 2.;
-        let end_width = radii[1] + radii[0];
+    fn write_pixel(&mut self, p: Point2<i32>, c: color) {
+        Surface::write_pixel(self.0, p, c);
+    }
 
-        let framebuffer = app.get_framebuffer_ref();
-        let rect = framebuffer.draw_dynamic_bezier(
-            (start_point, start_width),
-            (ctrl_point, ctrl_width),
-            (end_point, end_width),
-            10,
-            col,
-        );
-        framebuffer.partial_refresh(
-            &rect,
-            if sync { PartialRefreshMode::Wait } else { PartialRefreshMode::Async },
+    fn refresh(&mut self, rect: &mxcfb_rect, waveform: Waveform, refresh: Refresh) {
+        if waveform == Waveform::Quality || surface::is_empty(rect) {
+            return Surface::refresh(self.0, rect, waveform, refresh);
+        }
+        self.0.partial_refresh(
+            rect,
+            refresh.into(),
             waveform_mode::WAVEFORM_MODE_DU,
             display_temp::TEMP_USE_REMARKABLE_DRAW,
             dither_mode::EPDC_FLAG_EXP1,
@@ -84,4 +54,12 @@ pub(crate) async fn paint(
             false,
         );
     }
+
+    fn snapshot(&self, rect: &mxcfb_rect) -> Option<RgbImage> {
+        self.0.snapshot(rect)
+    }
+
+    fn clear(&mut self) {
+        Surface::clear(self.0);
+    }
 }
@@ -11,20 +11,26 @@
 use std::io::BufRead;
 
 use anyhow::Result;
-use libremarkable::appctx::ApplicationContext;
 use log::info;
-use pb::proto::hypercards::{drawing::Color, Drawing};
+use marauder::surface::Surface;
+use pb::{
+    drawing::Point,
+    proto::hypercards::{drawing::Color, Drawing},
+};
 use quick_xml::events::Event;
 use svg_path_parser::parse_with_resolution;
 use tokio::time::sleep;
 
-use crate::paint::{paint, DRAWING_PACE, INTER_DRAWING_PACE};
+use crate::{
+    ingest,
+    paint::{paint, DRAWING_PACE, INTER_DRAWING_PACE},
+};
 
 const PAUSE: bool = true;
 const SYNC: bool = false;
 
-pub(crate) async fn read_and_paint(app: &mut ApplicationContext<'_>, fpath: String) -> Result<()> {
-    let mut reader = quick_xml::Reader::from_file(fpath)?;
+pub async fn read_and_paint(s: &mut impl Surface, fpath: String) -> Result<()> {
+    let mut reader = quick_xml::Reader::from_file(&fpath)?;
     reader.trim_text(true);
 
     for (path, _transform) in browse(reader)? {
@@ -32,9 +38,9 @@ pub(crate) async fn read_and_paint(app: &mut ApplicationContext<'_>, fpath: Stri
         for (_closed, line) in parse_with_resolution(path.as_str(), 64) {
             info!(target:env!("CARGO_PKG_NAME"), "drawing XxY: {n}x{n}", n = line.len());
 
-            let d = into_drawing(line);
+            let Some(d) = ingest(&fpath, into_drawing(line)) else { continue };
 
-            paint(app, &d, PAUSE, SYNC).await;
+            paint(s, &d, PAUSE, SYNC).await;
 
             if PAUSE {
                 sleep(if false { DRAWING_PACE } else { INTER_DRAWING_PACE }).await;
@@ -47,6 +53,19 @@ pub(crate) async fn read_and_paint(app: &mut ApplicationContext<'_>, fpath: Stri
     Ok(())
 }
 
+/// Reads all paths' lines at once, e.g. to send them elsewhere.
+pub fn read(fpath: &str) -> Result<Vec<Drawing>> {
+    let mut reader = quick_xml::Reader::from_file(fpath)?;
+    reader.trim_text(true);
+
+    let ds = browse(reader)?
+        .into_iter()
+        .flat_map(|(path, _transform)| parse_with_resolution(path.as_str(), 64).collect::<Vec<_>>())
+        .filter_map(|(_closed, line)| ingest(fpath, into_drawing(line)))
+        .collect();
+    Ok(ds)
+}
+
 fn browse<T: BufRead>(mut reader: quick_xml::Reader<T>) -> Result<Vec<(String, Option<String>)>> {
     let mut paths = vec![];
 
@@ -62,7 +81,7 @@ fn browse<T: BufRead>(mut reader: quick_xml::Reader<T>) -> Result<Vec<(String, O
                     let extract = || {
                         attr.unescaped_value()
                             .ok()
-                            .and_then(|v| std::str::from_utf8(&v).map(str::to_string).ok())
+                            .and_then(|v| std::str::from_utf8(&v).map(str::to_owned).ok())
                     };
                     match attr.key {
                         b"d" => path_expr = extract(),
@@ -87,18 +106,15 @@ fn browse<T: BufRead>(mut reader: quick_xml::Reader<T>) -> Result<Vec<(String, O
 fn into_drawing(line: Vec<(f64, f64)>) -> Drawing {
     const PRESSURE: i32 = 2000;
     const WIDTH: u32 = 2;
-    const OFF_X: f64 = 0.;
-    const MUL_X: f64 = 1.;
-    const OFF_Y: f64 = 0.;
-    const MUL_Y: f64 = MUL_X;
-
-    Drawing {
-        xs: line.iter().map(|(x, _)| (MUL_X * (x + OFF_X)) as f32).collect(),
-        ys: line.iter().map(|(_, y)| (MUL_Y * (y + OFF_Y)) as f32).collect(),
-        pressures: line.iter().map(|_| PRESSURE).collect(),
-        widths: line.iter().map(|_| WIDTH).collect(),
-        color: Color::Black as i32,
-    }
+
+    Drawing::builder(Color::Black)
+        .points(line.into_iter().map(|(x, y)| Point {
+            x: x as f32,
+            y: y as f32,
+            pressure: PRESSURE,
+            width: WIDTH,
+        }))
+        .build()
 }
 
 #[test]
@@ -424,3 +440,31 @@ x-height="300"
         )]
     );
 }
+
+#[tokio::test]
+async fn replays_onto_a_canvas() {
+    let fpath = std::env::temp_dir().join(format!("scrolls-{}.svg", std::process::id()));
+    std::fs::write(
+        &fpath,
+        r#"<svg><path d="M 100 100 L 150 100 L 200 100 L 250 100 L 300 100" /></svg>"#,
+    )
+    .unwrap();
+    let mut canvas = marauder::raster::Canvas::new(400, 200);
+    read_and_paint(&mut canvas, fpath.to_str().unwrap().to_owned()).await.unwrap();
+    std::fs::remove_file(&fpath).unwrap();
+
+    let img = canvas.image();
+    assert!((130..270).all(|x| img.get_pixel(x, 99).0 == [0]));
+    assert_eq!(img.get_pixel(200, 120).0, [u8::MAX]);
+}
+
+#[test]
+fn paints_wiresphere_like_its_golden_image() {
+    let root = std::path::Path::new(env!("CARGO_MANIFEST_DIR"));
+    let ds = read(root.join("../misc/wiresphere.svg").to_str().unwrap()).unwrap();
+    let mut canvas = marauder::raster::Canvas::default();
+    for d in &ds {
+        canvas.paint(d);
+    }
+    marauder::golden::assert_matches(&root.join("golden"), "wiresphere", canvas.image());
+}
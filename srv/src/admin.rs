@@ -0,0 +1,227 @@
+use log::info;
+use pb::proto::hypercards::{
+    admin_server::Admin, BanRep, BanReq, ClearRoomRep, ClearRoomReq, DeleteRoomRep, DeleteRoomReq,
+    KickRep, KickReq, LockRoomRep, LockRoomReq, RenameRoomRep, RenameRoomReq,
+};
+use tonic::{Request, Response, Status};
+
+use crate::server::{ntui, Server};
+
+#[tonic::async_trait]
+impl Admin for Server {
+    async fn kick(&self, req: Request<KickReq>) -> Result<Response<KickRep>, Status> {
+        self.moderation.authorize(&req)?;
+        let KickReq { room_id, user_id, reason } = req.into_inner();
+        ntui(&user_id)?;
+        let room_id = (!room_id.is_empty()).then_some(room_id);
+        if let Some(room_id) = &room_id {
+            ntui(room_id)?;
+        }
+        info!("[kick] {user_id:?} out of {room_id:?}: {reason:?}");
+        let reason = match reason.as_str() {
+            "" => "kicked".to_owned(),
+            _ => format!("kicked: {reason}"),
+        };
+        self.moderation.kick(room_id.as_deref(), Some(&user_id), &reason);
+        Ok(Response::new(KickRep {}))
+    }
+
+    async fn ban(&self, req: Request<BanReq>) -> Result<Response<BanRep>, Status> {
+        self.moderation.authorize(&req)?;
+        let BanReq { user_id, reason, lift } = req.into_inner();
+        ntui(&user_id)?;
+        if lift {
+            info!("[ban] lifting {user_id:?}'s ban");
+            self.moderation.unban(&user_id);
+        } else {
+            info!("[ban] banning {user_id:?}: {reason:?}");
+            self.moderation.ban(&user_id, &reason);
+        }
+        Ok(Response::new(BanRep {}))
+    }
+
+    async fn clear_room(
+        &self,
+        req: Request<ClearRoomReq>,
+    ) -> Result<Response<ClearRoomRep>, Status> {
+        self.moderation.authorize(&req)?;
+        let ClearRoomReq { room_id } = req.into_inner();
+        ntui(&room_id)?;
+        info!("[clear_room] clearing {room_id:?}");
+        self.rooms.clear(&room_id).await?;
+        Ok(Response::new(ClearRoomRep {}))
+    }
+
+    async fn lock_room(&self, req: Request<LockRoomReq>) -> Result<Response<LockRoomRep>, Status> {
+        self.moderation.authorize(&req)?;
+        let LockRoomReq { room_id, unlock } = req.into_inner();
+        ntui(&room_id)?;
+        info!("[lock_room] {room_id:?} locked: {}", !unlock);
+        if unlock {
+            self.moderation.unlock(&room_id);
+        } else {
+            self.moderation.lock(&room_id);
+        }
+        Ok(Response::new(LockRoomRep {}))
+    }
+
+    async fn rename_room(
+        &self,
+        req: Request<RenameRoomReq>,
+    ) -> Result<Response<RenameRoomRep>, Status> {
+        self.moderation.authorize(&req)?;
+        let RenameRoomReq { room_id, new_room_id } = req.into_inner();
+        ntui(&room_id)?;
+        ntui(&new_room_id)?;
+        info!("[rename_room] {room_id:?} -> {new_room_id:?}");
+        self.rooms.rename(&room_id, &new_room_id).await?;
+        let reason = format!("room renamed to {new_room_id:?}");
+        self.moderation.kick(Some(&room_id), None, &reason);
+        Ok(Response::new(RenameRoomRep {}))
+    }
+
+    async fn delete_room(
+        &self,
+        req: Request<DeleteRoomReq>,
+    ) -> Result<Response<DeleteRoomRep>, Status> {
+        self.moderation.authorize(&req)?;
+        let DeleteRoomReq { room_id, reason } = req.into_inner();
+        ntui(&room_id)?;
+        info!("[delete_room] deleting {room_id:?}: {reason:?}");
+        let reason = match reason.as_str() {
+            "" => "room deleted".to_owned(),
+            _ => format!("room deleted: {reason}"),
+        };
+        self.moderation.kick(Some(&room_id), None, &reason);
+        self.rooms.remove(&room_id).await?;
+        Ok(Response::new(DeleteRoomRep {}))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pb::proto::hypercards::{
+        drawing::Color, event, screen_sharing_server::ScreenSharing, whiteboard_server::Whiteboard,
+        SendScreenReq,
+    };
+    use tokio_stream::StreamExt;
+
+    use super::*;
+    use crate::{
+        moderation::Moderation,
+        rooms::Rooms,
+        server::random_id,
+        testing::{recv, send, user},
+        throttle::Throttle,
+    };
+
+    fn srv() -> Server {
+        let moderation = Moderation::new(Some("s3cret".into()));
+        Server::new(random_id(), Rooms::default(), Throttle::default(), moderation)
+    }
+
+    fn admin<T>(msg: T) -> Request<T> {
+        let mut req = Request::new(msg);
+        req.metadata_mut().insert("authorization", "Bearer s3cret".parse().unwrap());
+        req
+    }
+
+    #[tokio::test]
+    async fn needs_the_admin_token() {
+        let srv = srv();
+        let req = user("joe", LockRoomReq { room_id: "r".into(), unlock: false });
+        let err = srv.lock_room(req).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::Unauthenticated);
+    }
+
+    #[tokio::test]
+    async fn kicked_streams_end_with_the_reason() {
+        let srv = srv();
+        let mut joe = srv.recv_events(user("joe", recv("r"))).await.unwrap().into_inner();
+        let mut jane = srv.recv_events(user("jane", recv("r"))).await.unwrap().into_inner();
+        joe.next().await.unwrap().unwrap();
+        jane.next().await.unwrap().unwrap();
+
+        let kick = KickReq { room_id: "r".into(), user_id: "joe".into(), reason: "spam".into() };
+        srv.kick(admin(kick)).await.unwrap();
+        let status = joe.next().await.unwrap().unwrap_err();
+        assert_eq!(
+            (status.code(), status.message()),
+            (tonic::Code::PermissionDenied, "kicked: spam")
+        );
+        assert!(joe.next().await.is_none());
+
+        // Jane stays
+        srv.send_event(user("bob", send(&["r"]))).await.unwrap();
+        let got = jane.next().await.unwrap().unwrap();
+        assert_eq!(got.by_user_id, "bob");
+    }
+
+    #[tokio::test]
+    async fn bans_last_until_lifted() {
+        let srv = srv();
+        let ban = BanReq { user_id: "joe".into(), reason: "spam".into(), lift: false };
+        srv.ban(admin(ban)).await.unwrap();
+        let err = srv.recv_events(user("joe", recv("r"))).await.err().unwrap();
+        assert_eq!(err.message(), "banned: spam");
+        assert!(srv.send_event(user("joe", send(&["r"]))).await.is_err());
+        let screen = SendScreenReq { room_id: "r".into(), screen_png: vec![1] };
+        assert!(srv.send_screen(user("joe", screen)).await.is_err());
+
+        let lift = BanReq { user_id: "joe".into(), reason: String::new(), lift: true };
+        srv.ban(admin(lift)).await.unwrap();
+        srv.send_event(user("joe", send(&["r"]))).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn locked_rooms_are_read_only() {
+        let srv = srv();
+        srv.lock_room(admin(LockRoomReq { room_id: "r".into(), unlock: false })).await.unwrap();
+        let err = srv.send_event(user("joe", send(&["r"]))).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::FailedPrecondition);
+        srv.send_event(user("joe", send(&["q"]))).await.unwrap();
+        srv.recv_events(user("joe", recv("r"))).await.unwrap();
+
+        srv.lock_room(admin(LockRoomReq { room_id: "r".into(), unlock: true })).await.unwrap();
+        srv.send_event(user("joe", send(&["r"]))).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn clears_renames_and_deletes_rooms() {
+        let srv = srv();
+        let mut joe = srv.recv_events(user("joe", recv("r"))).await.unwrap().into_inner();
+        joe.next().await.unwrap().unwrap();
+        srv.send_event(user("jane", send(&["r"]))).await.unwrap();
+        srv.send_event(user("jane", send(&["r"]))).await.unwrap();
+        joe.next().await.unwrap().unwrap();
+        joe.next().await.unwrap().unwrap();
+
+        srv.clear_room(admin(ClearRoomReq { room_id: "r".into() })).await.unwrap();
+        assert_eq!(srv.rooms.drawings("r").await.unwrap(), Some(vec![]));
+        let erased = joe.next().await.unwrap().unwrap();
+        assert!(
+            matches!(erased.event, Some(event::Event::Drawing(d)) if d.color() == Color::White)
+        );
+
+        srv.send_event(user("jane", send(&["r"]))).await.unwrap();
+        let rename = RenameRoomReq { room_id: "r".into(), new_room_id: "q".into() };
+        srv.rename_room(admin(rename)).await.unwrap();
+        assert_eq!(srv.rooms.drawings("r").await.unwrap(), None);
+        let moved = srv.rooms.drawings("q").await.unwrap().unwrap();
+        assert_eq!(moved.len(), 1);
+        let gone = RenameRoomReq { room_id: "r".into(), new_room_id: "p".into() };
+        let err = srv.rename_room(admin(gone)).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::NotFound);
+        let status = joe.filter_map(Result::err).next().await.unwrap();
+        assert_eq!(status.message(), "room renamed to \"q\"");
+
+        srv.send_event(user("jane", send(&["r"]))).await.unwrap();
+        let taken = RenameRoomReq { room_id: "r".into(), new_room_id: "q".into() };
+        let err = srv.rename_room(admin(taken)).await.unwrap_err();
+        assert_eq!(err.code(), tonic::Code::AlreadyExists);
+
+        let delete = DeleteRoomReq { room_id: "q".into(), reason: String::new() };
+        srv.delete_room(admin(delete)).await.unwrap();
+        assert_eq!(srv.rooms.drawings("q").await.unwrap(), None);
+    }
+}
@@ -0,0 +1,117 @@
+use log::info;
+use pb::proto::hypercards::{
+    archive_server::Archive, export_room_req::Format, timelapse_req, Drawing, ExportRoomRep,
+    ExportRoomReq, TimelapseRep, TimelapseReq,
+};
+use tonic::{Request, Response, Status};
+
+use crate::{
+    export,
+    server::{ntui, Server},
+    timelapse::{self, Settings},
+};
+
+#[tonic::async_trait]
+impl Archive for Server {
+    // Anonymous calls are allowed
+    async fn export_room(
+        &self,
+        req: Request<ExportRoomReq>,
+    ) -> Result<Response<ExportRoomRep>, Status> {
+        let req = req.into_inner();
+        let format = req.format();
+        let ExportRoomReq { room_id, .. } = req;
+        ntui(&room_id)?;
+        let drawings = self.drawings(&room_id).await?;
+        info!("[export_room] exporting {} drawings of {room_id:?} as {format:?}", drawings.len());
+
+        let (data, content_type) = match format {
+            Format::Svg => (export::svg(&drawings).into_bytes(), "image/svg+xml"),
+            Format::Pdf => (export::pdf(&drawings), "application/pdf"),
+            Format::Jsonl => {
+                let mut data = vec![];
+                scrolls::jsonl::write(&mut data, &drawings)
+                    .map_err(|e| Status::internal(format!("encoding JSONL: {e}")))?;
+                (data, "application/jsonl")
+            }
+        };
+        Ok(Response::new(ExportRoomRep { data, content_type: content_type.to_owned() }))
+    }
+
+    // Anonymous calls are allowed
+    async fn timelapse(
+        &self,
+        req: Request<TimelapseReq>,
+    ) -> Result<Response<TimelapseRep>, Status> {
+        let req = req.into_inner();
+        let format = req.format();
+        let settings = settings(&req)?;
+        let TimelapseReq { room_id, .. } = req;
+        ntui(&room_id)?;
+        let drawings = self.drawings(&room_id).await?;
+        info!("[timelapse] animating {} drawings of {room_id:?} as {format:?}", drawings.len());
+
+        // Painting every frame is CPU-bound
+        let (data, content_type) = tokio::task::spawn_blocking(move || match format {
+            timelapse_req::Format::Gif => {
+                timelapse::gif(&drawings, &settings).map(|d| (d, "image/gif"))
+            }
+            timelapse_req::Format::Apng => {
+                timelapse::apng(&drawings, &settings).map(|d| (d, "image/apng"))
+            }
+        })
+        .await
+        .map_err(|e| Status::internal(format!("rendering frames: {e}")))?
+        .map_err(|e| Status::internal(format!("encoding {format:?}: {e}")))?;
+        Ok(Response::new(TimelapseRep { data, content_type: content_type.to_owned() }))
+    }
+}
+
+impl Server {
+    async fn drawings(&self, room_id: &str) -> Result<Vec<Drawing>, Status> {
+        self.rooms
+            .drawings(room_id)
+            .await?
+            .ok_or_else(|| Status::not_found(format!("no such room {room_id:?}")))
+    }
+}
+
+fn settings(req: &TimelapseReq) -> Result<Settings, Status> {
+    let bad = |why: &str| Err(Status::invalid_argument(why.to_owned()));
+    let default = Settings::default();
+
+    let strokes_per_frame = match req.strokes_per_frame {
+        0 => default.strokes_per_frame,
+        n => n.try_into().unwrap_or(usize::MAX),
+    };
+    let frame_delay_ms = match req.frame_delay_ms {
+        0 => default.frame_delay_ms,
+        ms => match u16::try_from(ms) {
+            Ok(ms) => ms,
+            Err(_) => return bad("frame_delay_ms must be at most 65535"),
+        },
+    };
+    let scale = match req.scale {
+        0. => default.scale,
+        s if s.is_finite() && 0. < s && s <= 1. => s,
+        _ => return bad("scale must be within (0, 1]"),
+    };
+    Ok(Settings { strokes_per_frame, frame_delay_ms, scale })
+}
+
+#[test]
+fn defaults_and_bounds_timelapse_settings() {
+    let req = |strokes_per_frame, frame_delay_ms, scale| TimelapseReq {
+        strokes_per_frame,
+        frame_delay_ms,
+        scale,
+        ..Default::default()
+    };
+    assert_eq!(settings(&req(0, 0, 0.)).unwrap(), Settings::default());
+    let s = settings(&req(5, 40, 1.)).unwrap();
+    assert_eq!((s.strokes_per_frame, s.frame_delay_ms, s.scale), (5, 40, 1.));
+
+    for r in [req(1, 70_000, 0.5), req(1, 100, -1.), req(1, 100, 2.), req(1, 100, f32::NAN)] {
+        assert_eq!(settings(&r).unwrap_err().code(), tonic::Code::InvalidArgument);
+    }
+}
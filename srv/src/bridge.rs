@@ -0,0 +1,175 @@
+//! Mirrors a room between two servers, e.g. one on the office LAN and one in the cloud
+//!
+//! The bridge joins the room on both sides as the same user, so neither server
+//! sends it back what it published there. Drawings keep the ID of the server
+//! they were first published on and collect those of the servers bridges took
+//! them from: they are never republished to any of these, so bridges can be
+//! doubled up, chained or even set in rings without events going round in circles.
+//! Only drawings are mirrored, each server keeps count of its own members.
+//!
+use anyhow::{bail, Result};
+use log::{debug, info, warn};
+use marauder::client::{Rejection, Session};
+use pb::proto::hypercards::{event, Event};
+use tokio_stream::StreamExt;
+
+/// Mirrors `a`'s room to `b`'s and back, until either side turns the bridge away.
+pub(crate) async fn run(a: Session, b: Session) -> Result<()> {
+    info!("[bridge] mirroring {:?} <-> {:?}", a.room_id(), b.room_id());
+    tokio::try_join!(mirror(&a, &b), mirror(&b, &a))?;
+    Ok(())
+}
+
+async fn mirror(from: &Session, to: &Session) -> Result<()> {
+    let mut events = Box::pin(from.events());
+    while let Some(event) = events.next().await {
+        let Event { by_user_id, event, origin_server_id, mut via_server_ids, .. } = event;
+        let Some(drawing @ event::Event::Drawing(_)) = event else { continue };
+        let from_id = from.server_id().unwrap_or_default();
+        let origin_server_id = match origin_server_id.as_str() {
+            "" => from_id.clone(),
+            _ => origin_server_id,
+        };
+        if !from_id.is_empty() && !via_server_ids.contains(&from_id) {
+            via_server_ids.push(from_id);
+        }
+        if let Some(to_id) = to.server_id() {
+            if to_id == origin_server_id || via_server_ids.contains(&to_id) {
+                debug!("[mirror] not sending {to_id:?} an event that went through it already");
+                continue;
+            }
+        }
+
+        let event =
+            Event { event: Some(drawing), origin_server_id, via_server_ids, ..Default::default() };
+        match to.send_event(event).await {
+            Ok(()) => debug!("[mirror] mirrored {by_user_id:?}'s drawing"),
+            Err(e) if e.is::<Rejection>() => warn!("[mirror] drawing not mirrored: {e}"),
+            Err(e) => warn!("[mirror] drawing lost: {e}"),
+        }
+    }
+    match from.rejection() {
+        Some(rejection) => bail!("[mirror] left {:?}: {rejection}", from.room_id()),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use tokio::net::TcpListener;
+    use tokio_stream::wrappers::TcpListenerStream;
+
+    use super::*;
+    use crate::{server::Server, testing::drawing};
+
+    async fn serve(id: &str) -> (String, Server) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let host = format!("http://{}", listener.local_addr().unwrap());
+        let srv =
+            Server::new(id.to_owned(), Default::default(), Default::default(), Default::default());
+        let router = crate::router(srv.clone(), None).await.unwrap();
+        tokio::spawn(router.serve_with_incoming(TcpListenerStream::new(listener)));
+        (host, srv)
+    }
+
+    async fn eventually(what: &str, f: impl AsyncFn() -> bool) {
+        for _ in 0..500 {
+            if f().await {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("never happened: {what}");
+    }
+
+    #[tokio::test]
+    async fn mirrors_both_ways_without_looping() {
+        let (a_host, a) = serve("lan").await;
+        let (b_host, b) = serve("cloud").await;
+        // Two bridges, as if one was forgotten running
+        for user_id in ["bridge-1", "bridge-2"] {
+            let a = Session::connect(&a_host, "r", user_id).unwrap();
+            let b = Session::connect(&b_host, "r", user_id).unwrap();
+            tokio::spawn(run(a, b));
+        }
+        let members =
+            async |srv: &Server| srv.rooms.members("r").await.unwrap().unwrap_or_default();
+        eventually("bridges joined", async || {
+            members(&a).await.len() == 2 && members(&b).await.len() == 2
+        })
+        .await;
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let jane = Session::connect(&b_host, "r", "jane").unwrap();
+        tokio::spawn(async move {
+            let mut events = Box::pin(jane.events());
+            while let Some(e) = events.next().await {
+                let _ = tx.send(e);
+            }
+        });
+        eventually("jane joined", async || members(&b).await.len() == 3).await;
+
+        let joe = Session::connect(&a_host, "r", "joe").unwrap();
+        joe.send_drawing(drawing()).await.unwrap();
+        let drawings = async |srv: &Server| srv.rooms.drawings("r").await.unwrap().unwrap().len();
+        eventually("drawing mirrored by both bridges", async || drawings(&b).await == 2).await;
+        let mirrored = loop {
+            let e = rx.recv().await.unwrap();
+            if e.by_user_id.starts_with("bridge-") {
+                break e;
+            }
+        };
+        assert_eq!(mirrored.origin_server_id, "lan");
+
+        // Neither bridge sends the other's copy back
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!((drawings(&a).await, drawings(&b).await), (1, 2));
+
+        let jane = Session::connect(&b_host, "r", "jane").unwrap();
+        jane.send_drawing(drawing()).await.unwrap();
+        eventually("drawing mirrored back", async || drawings(&a).await == 3).await;
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!((drawings(&a).await, drawings(&b).await), (3, 3));
+    }
+
+    #[tokio::test]
+    async fn mirrors_through_rings_without_looping() {
+        // a - b, then the ring b - c - d - b, which a isn't part of
+        let mut servers = vec![];
+        for id in ["a", "b", "c", "d"] {
+            servers.push(serve(id).await);
+        }
+        for (i, (x, y)) in [(0, 1), (1, 2), (2, 3), (3, 1)].into_iter().enumerate() {
+            let user_id = format!("bridge-{i}");
+            let x = Session::connect(&servers[x].0, "r", &user_id).unwrap();
+            let y = Session::connect(&servers[y].0, "r", &user_id).unwrap();
+            tokio::spawn(run(x, y));
+        }
+        let members =
+            async |srv: &Server| srv.rooms.members("r").await.unwrap().unwrap_or_default();
+        eventually("bridges joined", async || {
+            let mut joined = vec![];
+            for (_, srv) in &servers {
+                joined.push(members(srv).await.len());
+            }
+            joined == [1, 3, 2, 2]
+        })
+        .await;
+
+        let joe = Session::connect(&servers[0].0, "r", "joe").unwrap();
+        joe.send_drawing(drawing()).await.unwrap();
+        let drawings = async || {
+            let mut drawings = vec![];
+            for (_, srv) in &servers {
+                drawings.push(srv.rooms.drawings("r").await.unwrap().unwrap().len());
+            }
+            drawings
+        };
+        // c & d get it both ways round the ring, b once, then it stops there
+        eventually("drawing went round", async || drawings().await == [1, 1, 2, 2]).await;
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert_eq!(drawings().await, [1, 1, 2, 2]);
+    }
+}
@@ -0,0 +1,180 @@
+//! Render a room's drawings as files
+//!
+//! Coordinates are those of the tablet's display: 1404x1872 pixels at 226 DPI,
+//! with (0,0) at the top left. Drawings reaching further down than a screenful
+//! spill onto more pages.
+//!
+use std::fmt::Write;
+
+use pb::proto::hypercards::{drawing::Color, Drawing};
+
+const PAGE_WIDTH: f32 = 1404.;
+const PAGE_HEIGHT: f32 = 1872.;
+const DPI: f32 = 226.;
+
+/// Stroke diameter, averaged over a drawing's points.
+/// Same formula as when painting: `radius = tip * pressure / 2048 / 2`
+fn stroke_width(d: &Drawing) -> f32 {
+    let n = d.pressures.len().min(d.widths.len());
+    if n == 0 {
+        return 0.;
+    }
+    let sum: f32 = d.pressures.iter().zip(&d.widths).map(|(&p, &w)| w as f32 * p as f32).sum();
+    sum / 2048. / n as f32
+}
+
+fn visible(ds: &[Drawing]) -> impl Iterator<Item = &Drawing> {
+    ds.iter().filter(|d| d.color() != Color::Invisible && !d.xs.is_empty())
+}
+
+/// Amount of screenfuls needed to show every drawing, at least one.
+pub(crate) fn pages(ds: &[Drawing]) -> usize {
+    let bottom = visible(ds).flat_map(|d| &d.ys).fold(0f32, |acc, &y| acc.max(y));
+    (bottom / PAGE_HEIGHT).ceil().max(1.) as usize
+}
+
+fn points(d: &Drawing) -> impl Iterator<Item = (f32, f32)> + '_ {
+    d.xs.iter().copied().zip(d.ys.iter().copied())
+}
+
+/// One `<path>` per drawing. Erasing drawings are painted white.
+pub(crate) fn svg(ds: &[Drawing]) -> String {
+    let height = pages(ds) as f32 * PAGE_HEIGHT;
+    let mut svg = String::new();
+    svg.push_str(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    svg.push('\n');
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{PAGE_WIDTH}" height="{height}" viewBox="0 0 {PAGE_WIDTH} {height}">"#
+    );
+    svg.push_str(r#"<rect width="100%" height="100%" fill="white"/>"#);
+    svg.push('\n');
+    for d in visible(ds) {
+        let mut path = String::new();
+        for (i, (x, y)) in points(d).enumerate() {
+            let _ = write!(path, "{}{x} {y}", if i == 0 { "M" } else { " L" });
+        }
+        if d.xs.len() == 1 {
+            // Zero-length paths with round caps show as dots
+            let _ = write!(path, " L{} {}", d.xs[0], d.ys[0]);
+        }
+        let _ = writeln!(
+            svg,
+            r#"<path d="{path}" fill="none" stroke="{stroke}" stroke-width="{width}" stroke-linecap="round" stroke-linejoin="round"/>"#,
+            stroke = if d.color() == Color::White { "white" } else { "black" },
+            width = stroke_width(d),
+        );
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// One page per screenful, sized as the tablet's display.
+pub(crate) fn pdf(ds: &[Drawing]) -> Vec<u8> {
+    let k = 72. / DPI; // pixels to points
+    let (w, h) = (PAGE_WIDTH * k, PAGE_HEIGHT * k);
+
+    let mut strokes = String::new();
+    for d in visible(ds) {
+        let gray = if d.color() == Color::White { 1 } else { 0 };
+        let _ = write!(strokes, "{gray} G {} w", stroke_width(d));
+        for (i, (x, y)) in points(d).enumerate() {
+            let _ = write!(strokes, " {x} {y} {}", if i == 0 { "m" } else { "l" });
+        }
+        if d.xs.len() == 1 {
+            let _ = write!(strokes, " {} {} l", d.xs[0], d.ys[0]);
+        }
+        strokes.push_str(" S\n");
+    }
+
+    let pages = pages(ds);
+    let mut objects = vec![
+        "<< /Type /Catalog /Pages 2 0 R >>".to_owned(),
+        format!(
+            "<< /Type /Pages /Kids [{}] /Count {pages} >>",
+            (0..pages).map(|i| format!("{} 0 R", 3 + 2 * i)).collect::<Vec<_>>().join(" ")
+        ),
+    ];
+    for page in 0..pages {
+        // Flip the Y axis, scale pixels down to points then scroll to this page
+        let offset = page as f32 * PAGE_HEIGHT;
+        let content =
+            format!("q {k} 0 0 {} 0 {h} cm 1 0 0 1 0 {} cm 1 J 1 j\n{strokes}Q\n", -k, -offset);
+        objects.push(format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {w} {h}] /Contents {} 0 R >>",
+            4 + 2 * page
+        ));
+        objects.push(format!("<< /Length {} >>\nstream\n{content}endstream", content.len()));
+    }
+
+    let mut pdf = b"%PDF-1.4\n".to_vec();
+    let mut offsets = Vec::with_capacity(objects.len());
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        pdf.extend(format!("{} 0 obj\n{object}\nendobj\n", i + 1).into_bytes());
+    }
+    let xref = pdf.len();
+    let mut trailer = format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1);
+    for offset in offsets {
+        let _ = writeln!(trailer, "{offset:010} 00000 n ");
+    }
+    let _ = write!(
+        trailer,
+        "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{xref}\n%%EOF\n",
+        objects.len() + 1
+    );
+    pdf.extend(trailer.into_bytes());
+    pdf
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn d(ys: Vec<f32>, color: Color) -> Drawing {
+        let n = ys.len();
+        Drawing {
+            xs: (0..n).map(|i| i as f32).collect(),
+            ys,
+            pressures: vec![2048; n],
+            widths: vec![3; n],
+            color: color.into(),
+        }
+    }
+
+    #[test]
+    fn counts_pages() {
+        assert_eq!(pages(&[]), 1);
+        assert_eq!(pages(&[d(vec![10., 1872.], Color::Black)]), 1);
+        assert_eq!(pages(&[d(vec![10., 1873.], Color::Black)]), 2);
+        assert_eq!(pages(&[d(vec![10., 9999.], Color::Invisible)]), 1);
+    }
+
+    #[test]
+    fn svg_has_one_path_per_drawing() {
+        let ds = [d(vec![1., 2.], Color::Black), d(vec![3.], Color::White)];
+        let svg = svg(&ds);
+        assert!(svg.contains(r#"height="1872""#));
+        assert_eq!(svg.matches("<path ").count(), 2);
+        assert!(svg.contains(r#"<path d="M0 1 L1 2" fill="none" stroke="black" stroke-width="3" "#));
+        assert!(svg.contains(r#"<path d="M0 3 L0 3" fill="none" stroke="white" "#));
+    }
+
+    #[test]
+    fn pdf_has_one_page_per_screenful() {
+        let pdf = pdf(&[d(vec![1., 4000.], Color::Black)]);
+        let pdf = String::from_utf8(pdf).unwrap();
+        assert!(pdf.starts_with("%PDF-1.4\n"));
+        assert!(pdf.ends_with("%%EOF\n"));
+        assert!(pdf.contains("/Count 3 >>"));
+        assert_eq!(pdf.matches("/Type /Page ").count(), 3);
+        assert_eq!(pdf.matches("0 G 3 w 0 1 m 1 4000 l S").count(), 3);
+
+        // Every xref entry points to its object
+        let (_, xref) = pdf.split_once("xref\n").unwrap();
+        for (i, entry) in xref.lines().skip(2).take(7).enumerate() {
+            let offset: usize = entry[..10].parse().unwrap();
+            assert!(pdf[offset..].starts_with(&format!("{} 0 obj", i + 1)), "{entry}");
+        }
+    }
+}
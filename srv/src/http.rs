@@ -0,0 +1,121 @@
+//! Serves the HTTP routes (REST, metrics) the way the gRPC services are served
+//!
+//! Given `--tls-cert` & `--tls-key`, routes are only reachable over TLS, with
+//! the same certificate: a user's `x-user` header never goes out in cleartext.
+//!
+use std::{net::SocketAddr, sync::Arc};
+
+use anyhow::{anyhow, Result};
+use hyper_util::{rt::TokioIo, service::TowerToHyperService};
+use log::warn;
+use tokio::net::TcpListener;
+use tokio_rustls::{
+    rustls::{crypto::ring, ServerConfig},
+    TlsAcceptor,
+};
+
+/// Accepts TLS connections with this PEM certificate (chain) & private key.
+pub(crate) fn acceptor(cert: &[u8], key: &[u8]) -> Result<TlsAcceptor> {
+    let certs = rustls_pemfile::certs(&mut &cert[..]).collect::<Result<Vec<_>, _>>()?;
+    let key = rustls_pemfile::private_key(&mut &key[..])?
+        .ok_or_else(|| anyhow!("no private key in PEM"))?;
+    let mut config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)?;
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// The scheme routes are served with.
+pub(crate) fn scheme(tls: Option<&TlsAcceptor>) -> &'static str {
+    if tls.is_some() {
+        "https"
+    } else {
+        "http"
+    }
+}
+
+/// Serves `app` on `listen`, over TLS if given an acceptor.
+pub(crate) async fn serve(
+    listen: SocketAddr,
+    app: axum::Router,
+    tls: Option<TlsAcceptor>,
+) -> Result<()> {
+    let listener = TcpListener::bind(listen).await?;
+    serve_with_listener(listener, app, tls).await
+}
+
+async fn serve_with_listener(
+    listener: TcpListener,
+    app: axum::Router,
+    tls: Option<TlsAcceptor>,
+) -> Result<()> {
+    let Some(tls) = tls else {
+        axum::serve(listener, app).await?;
+        return Ok(());
+    };
+    loop {
+        let (tcp, peer) = listener.accept().await?;
+        let (tls, app) = (tls.clone(), app.clone());
+        tokio::spawn(async move {
+            let stream = match tls.accept(tcp).await {
+                Ok(stream) => stream,
+                Err(e) => return warn!("[serve] TLS handshake with {peer}: {e}"),
+            };
+            let conn = hyper::server::conn::http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), TowerToHyperService::new(app));
+            if let Err(e) = conn.await {
+                warn!("[serve] {peer}: {e}");
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use axum::routing::get;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_rustls::{
+        rustls::{pki_types::ServerName, ClientConfig, RootCertStore},
+        TlsConnector,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn serves_routes_over_tls_only() {
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_owned()]).unwrap();
+        let (cert_pem, key_pem) = (cert.cert.pem(), cert.signing_key.serialize_pem());
+        let tls = acceptor(cert_pem.as_bytes(), key_pem.as_bytes()).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = axum::Router::new().route("/hi", get(|| async { "hello" }));
+        tokio::spawn(serve_with_listener(listener, app, Some(tls)));
+
+        let get = b"GET /hi HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n";
+        let mut roots = RootCertStore::empty();
+        roots.add(cert.cert.der().clone()).unwrap();
+        let config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let tcp = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let name = ServerName::try_from("localhost").unwrap();
+        let mut stream = TlsConnector::from(Arc::new(config)).connect(name, tcp).await.unwrap();
+        stream.write_all(get).await.unwrap();
+        let mut rep = String::new();
+        stream.read_to_string(&mut rep).await.unwrap();
+        assert!(rep.starts_with("HTTP/1.1 200 OK"), "{rep}");
+        assert!(rep.ends_with("hello"), "{rep}");
+
+        // Cleartext requests get nothing back
+        let mut tcp = tokio::net::TcpStream::connect(addr).await.unwrap();
+        tcp.write_all(get).await.unwrap();
+        let mut rep = String::new();
+        let _ = tcp.read_to_string(&mut rep).await;
+        assert!(!rep.contains("hello"), "{rep}");
+    }
+}
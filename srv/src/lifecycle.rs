@@ -0,0 +1,355 @@
+//! Rooms' limits and the purging (or archiving) of the idle ones
+//!
+//! Every room gets the server's default [`RoomSettings`], unless overridden with
+//! e.g. `--room=living-room:max_members=8,idle_ttl_secs=0`.
+//!
+use std::{collections::HashMap, path::PathBuf, time::Duration};
+
+use anyhow::{bail, Context, Result};
+use log::{error, info};
+use pb::proto::hypercards::{room_settings::OnIdle, RoomSettings};
+use tonic::Status;
+
+use crate::rooms::{now, Rooms};
+
+/// How often idle rooms are looked for.
+const GC_EVERY: Duration = Duration::from_secs(60);
+
+#[derive(clap::Args, Debug, Clone)]
+#[group(id = "lifecycle")]
+pub(crate) struct Args {
+    /// Most open streams a room accepts, 0 for no limit
+    #[arg(long, env = "SRV_MAX_MEMBERS", default_value_t = 0)]
+    max_members: u32,
+
+    /// Most events a room keeps, 0 for no limit
+    #[arg(long, env = "SRV_MAX_HISTORY", default_value_t = 0)]
+    max_history: u64,
+
+    /// Seconds an empty room is kept after its last activity, 0 to keep rooms forever
+    #[arg(long, env = "SRV_IDLE_TTL_SECS", default_value_t = 0)]
+    idle_ttl_secs: u64,
+
+    /// What becomes of idle rooms: purge or archive (to --archive-dir)
+    #[arg(long, env = "SRV_ON_IDLE", default_value = "purge", value_parser = on_idle)]
+    on_idle: OnIdle,
+
+    /// Where idle rooms get archived, as JSONL (replayable by `scrolls`) and PNG
+    #[arg(long, env = "SRV_ARCHIVE_DIR")]
+    archive_dir: Option<PathBuf>,
+
+    /// Settings of one room, e.g. living-room:max_members=8,on_idle=archive (repeatable)
+    #[arg(long = "room", value_parser = room_override)]
+    rooms: Vec<Override>,
+}
+
+/// Per-room settings, as picked by the server's operator.
+#[derive(Debug, Default)]
+pub(crate) struct Policy {
+    defaults: RoomSettings,
+    overrides: HashMap<String, RoomSettings>,
+    archive_dir: Option<PathBuf>,
+}
+
+impl Policy {
+    pub(crate) fn new(args: Args) -> Result<Self> {
+        let Args { max_members, max_history, idle_ttl_secs, on_idle, archive_dir, rooms } = args;
+        let defaults =
+            RoomSettings { max_members, max_history, idle_ttl_secs, on_idle: on_idle.into() };
+
+        let mut overrides = HashMap::new();
+        for Override { room_id, settings } in rooms {
+            let room = overrides.entry(room_id).or_insert(defaults);
+            for setting in settings {
+                setting.apply(room);
+            }
+        }
+
+        let archives = |s: &RoomSettings| s.idle_ttl_secs != 0 && s.on_idle() == OnIdle::Archive;
+        if archive_dir.is_none() && (archives(&defaults) || overrides.values().any(archives)) {
+            bail!("Archiving idle rooms needs an --archive-dir");
+        }
+        Ok(Self { defaults, overrides, archive_dir })
+    }
+
+    #[cfg(test)]
+    pub(crate) fn uniform(defaults: RoomSettings) -> Self {
+        Self { defaults, ..Default::default() }
+    }
+
+    pub(crate) fn settings(&self, room_id: &str) -> RoomSettings {
+        self.overrides.get(room_id).copied().unwrap_or(self.defaults)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Override {
+    room_id: String,
+    settings: Vec<Setting>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Setting {
+    MaxMembers(u32),
+    MaxHistory(u64),
+    IdleTtlSecs(u64),
+    OnIdle(OnIdle),
+}
+
+impl Setting {
+    fn apply(self, s: &mut RoomSettings) {
+        match self {
+            Self::MaxMembers(n) => s.max_members = n,
+            Self::MaxHistory(n) => s.max_history = n,
+            Self::IdleTtlSecs(n) => s.idle_ttl_secs = n,
+            Self::OnIdle(o) => s.on_idle = o.into(),
+        }
+    }
+}
+
+fn on_idle(s: &str) -> Result<OnIdle> {
+    match s {
+        "purge" => Ok(OnIdle::Purge),
+        "archive" => Ok(OnIdle::Archive),
+        _ => bail!("expected purge or archive, got {s:?}"),
+    }
+}
+
+/// Parses `room:key=value,key=value`
+fn room_override(s: &str) -> Result<Override> {
+    let Some((room_id, kvs)) = s.split_once(':') else { bail!("expected room:key=value,...") };
+    crate::server::ntui(room_id).map_err(|e| anyhow::anyhow!("{}", e.message()))?;
+
+    let mut settings = vec![];
+    for kv in kvs.split(',').filter(|kv| !kv.is_empty()) {
+        let Some((k, v)) = kv.split_once('=') else { bail!("expected key=value, got {kv:?}") };
+        let bad = || format!("bad {k} {v:?}");
+        settings.push(match k {
+            "max_members" => Setting::MaxMembers(v.parse().with_context(bad)?),
+            "max_history" => Setting::MaxHistory(v.parse().with_context(bad)?),
+            "idle_ttl_secs" => Setting::IdleTtlSecs(v.parse().with_context(bad)?),
+            "on_idle" => Setting::OnIdle(on_idle(v)?),
+            _ => bail!("no such setting {k:?}"),
+        });
+    }
+    Ok(Override { room_id: room_id.to_owned(), settings })
+}
+
+/// Removes the idle rooms, every so often.
+pub(crate) async fn collect(rooms: Rooms) {
+    let mut every = tokio::time::interval(GC_EVERY);
+    loop {
+        every.tick().await;
+        if let Err(e) = collect_idle(&rooms, now()).await {
+            error!("[collect] {e}");
+        }
+    }
+}
+
+/// Removes the rooms nobody is in nor was for longer than their TTL, as of `now`.
+pub(crate) async fn collect_idle(rooms: &Rooms, now: i64) -> Result<Vec<String>, Status> {
+    let mut collected = vec![];
+    for stats in rooms.stats().await? {
+        let settings = rooms.policy().settings(&stats.room_id);
+        let ttl = i64::try_from(settings.idle_ttl_secs).unwrap_or(i64::MAX);
+        let ttl = ttl.saturating_mul(1_000_000_000);
+        if ttl == 0 || stats.streams != 0 || now.saturating_sub(stats.last_active_at) < ttl {
+            continue;
+        }
+
+        let room_id = stats.room_id;
+        let archive = if settings.on_idle() == OnIdle::Archive {
+            match Archive::write(rooms, &room_id, stats.last_active_at).await {
+                Ok(archive) => Some(archive),
+                Err(e) => {
+                    error!("[collect_idle] keeping {room_id:?}: {}", e.message());
+                    continue;
+                }
+            }
+        } else {
+            None
+        };
+        // Someone may have joined or published since the stats were taken
+        let removed = match rooms.remove_idle(&room_id, now.saturating_sub(ttl)).await {
+            Ok(true) => true,
+            Ok(false) => {
+                info!("[collect_idle] {room_id:?} is no longer idle");
+                false
+            }
+            Err(e) => {
+                error!("[collect_idle] keeping {room_id:?}: {}", e.message());
+                false
+            }
+        };
+        if !removed {
+            if let Some(archive) = archive {
+                archive.discard().await;
+            }
+            continue;
+        }
+        if let Some(archive) = archive {
+            archive.keep().await;
+        }
+        info!("[collect_idle] removed {room_id:?} ({} events)", stats.history_len);
+        collected.push(room_id);
+    }
+    Ok(collected)
+}
+
+/// An idle room's files, written aside (as `.part`) until the room is removed.
+struct Archive {
+    /// Where each file was written, and where it's kept
+    files: Vec<(PathBuf, PathBuf)>,
+}
+
+impl Archive {
+    /// Writes `<room>-<last activity>.jsonl` and, if any, its screenshot as `.png`.
+    async fn write(rooms: &Rooms, room_id: &str, last_active_at: i64) -> Result<Self, Status> {
+        let Some(dir) = &rooms.policy().archive_dir else {
+            return Err(Status::failed_precondition("no archive directory"));
+        };
+        let failed = |e: &dyn std::fmt::Display| {
+            Status::internal(format!("archiving {room_id:?} in {}: {e}", dir.display()))
+        };
+        let base = dir.join(format!("{room_id}-{last_active_at}"));
+
+        let drawings = rooms.drawings(room_id).await?.unwrap_or_default();
+        let mut jsonl = vec![];
+        scrolls::jsonl::write(&mut jsonl, &drawings).map_err(|e| failed(&e))?;
+        let png = rooms.screen(room_id).await?.unwrap_or_default();
+        tokio::fs::create_dir_all(dir).await.map_err(|e| failed(&e))?;
+
+        let mut archive = Self { files: vec![] };
+        for (ext, data) in [("jsonl", jsonl), ("png", png)] {
+            if ext == "png" && data.is_empty() {
+                continue;
+            }
+            let part = base.with_extension(format!("{ext}.part"));
+            archive.files.push((part.clone(), base.with_extension(ext)));
+            if let Err(e) = tokio::fs::write(&part, data).await {
+                archive.discard().await;
+                return Err(failed(&e));
+            }
+        }
+        Ok(archive)
+    }
+
+    /// The room is gone: its files are now the archive.
+    async fn keep(self) {
+        for (part, kept) in self.files {
+            if let Err(e) = tokio::fs::rename(&part, &kept).await {
+                error!("[archive] {} -> {}: {e}", part.display(), kept.display());
+            }
+        }
+    }
+
+    /// The room stays: its files go.
+    async fn discard(self) {
+        for (part, _) in self.files {
+            let _ = tokio::fs::remove_file(part).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use pb::proto::hypercards::event;
+
+    use super::*;
+    use crate::{rooms::status_event, storage::Memory, testing::drawing};
+
+    fn policy(args: &[&str]) -> Result<Policy> {
+        #[derive(clap::Parser)]
+        struct Cli {
+            #[command(flatten)]
+            args: Args,
+        }
+        let cli = <Cli as clap::Parser>::try_parse_from(["srv"].iter().chain(args))?;
+        Policy::new(cli.args)
+    }
+
+    #[test]
+    fn overrides_apply_on_top_of_defaults() {
+        let p = policy(&["--max-members=4", "--room=big:max_members=40,max_history=9"]).unwrap();
+        assert_eq!(p.settings("small").max_members, 4);
+        assert_eq!(p.settings("big").max_members, 40);
+        assert_eq!(p.settings("big").max_history, 9);
+        assert_eq!(p.settings("small").max_history, 0);
+
+        assert!(policy(&["--room=big:max_members=lots"]).is_err());
+        assert!(policy(&["--room=big:colour=red"]).is_err());
+        assert!(policy(&["--room=a.b:max_members=1"]).is_err());
+        assert!(policy(&["--idle-ttl-secs=1", "--on-idle=archive"]).is_err());
+        assert!(policy(&["--room=a:on_idle=archive"]).is_ok()); // Never idle anyway
+    }
+
+    #[tokio::test]
+    async fn archives_then_removes_idle_rooms() {
+        let dir = std::env::temp_dir().join(format!("srv-archive-{}", std::process::id()));
+        let p = policy(&[
+            "--idle-ttl-secs=60",
+            "--on-idle=archive",
+            &format!("--archive-dir={}", dir.display()),
+            "--room=forever:idle_ttl_secs=0",
+        ])
+        .unwrap();
+        let rooms = Rooms::new(Arc::new(Memory::default()), p);
+
+        let drawing = event::Event::Drawing(drawing());
+        for room_id in ["r", "forever", "busy"] {
+            rooms.publish(status_event(room_id, "joe", drawing.clone())).await.unwrap();
+        }
+        let (_rx, _) = rooms.join("busy", "joe").await.unwrap();
+
+        assert_eq!(collect_idle(&rooms, now()).await.unwrap(), Vec::<String>::new());
+        let later = now() + 61 * 1_000_000_000;
+        assert_eq!(collect_idle(&rooms, later).await.unwrap(), ["r"]);
+        assert_eq!(rooms.drawings("r").await.unwrap(), None);
+        assert_eq!(rooms.stats().await.unwrap().len(), 2);
+
+        let archived: Vec<_> =
+            std::fs::read_dir(&dir).unwrap().map(|e| e.unwrap().path()).collect();
+        assert_eq!(archived.len(), 1);
+        assert_eq!(archived[0].extension().unwrap(), "jsonl");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn keeps_rooms_failing_to_archive_and_collects_the_others() {
+        let dir = std::env::temp_dir().join(format!("srv-unarchivable-{}", std::process::id()));
+        let p = policy(&[
+            "--idle-ttl-secs=60",
+            "--on-idle=archive",
+            &format!("--archive-dir={}", dir.display()),
+        ])
+        .unwrap();
+        let rooms = Rooms::new(Arc::new(Memory::default()), p);
+        let drawing = event::Event::Drawing(drawing());
+        for room_id in ["a", "b", "c"] {
+            rooms.publish(status_event(room_id, "joe", drawing.clone())).await.unwrap();
+        }
+        // Something's in the way of b's archive
+        let stats = rooms.stats().await.unwrap();
+        let b = stats.iter().find(|s| s.room_id == "b").unwrap();
+        let in_the_way = dir.join(format!("b-{}.jsonl.part", b.last_active_at));
+        std::fs::create_dir_all(&in_the_way).unwrap();
+
+        let later = now() + 61 * 1_000_000_000;
+        let mut collected = collect_idle(&rooms, later).await.unwrap();
+        collected.sort();
+        assert_eq!(collected, ["a", "c"]);
+        assert_eq!(rooms.drawings("b").await.unwrap().unwrap().len(), 1);
+
+        let mut archived: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .filter(|path| *path != in_the_way)
+            .collect();
+        archived.sort();
+        assert_eq!(archived.len(), 2);
+        assert!(archived.iter().all(|path| path.extension().unwrap() == "jsonl"), "{archived:?}");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1 +1,385 @@
-fn main() {}
+// tonic::Status is what our RPCs return
+#![allow(clippy::result_large_err)]
+
+use std::{
+    fs,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use clap::{Parser, Subcommand};
+use log::{error, info};
+use marauder::client::{channel_with_ca, AdminToken, Session};
+use pb::proto::hypercards::{
+    admin_server::AdminServer, archive_client::ArchiveClient, archive_server::ArchiveServer,
+    export_room_req::Format, screen_sharing_server::ScreenSharingServer, timelapse_req,
+    whiteboard_server::WhiteboardServer, ExportRoomReq, TimelapseReq,
+};
+use tonic::transport::{server::Router, Channel, Identity, ServerTlsConfig};
+use tonic_web::GrpcWebLayer;
+use tower::layer::util::{Identity as NoLayer, Stack};
+use tower_http::cors::CorsLayer;
+
+mod admin;
+mod archive;
+mod bridge;
+mod export;
+mod http;
+mod lifecycle;
+mod metrics;
+mod moderation;
+mod rest;
+mod rooms;
+mod screen_sharing;
+mod server;
+mod storage;
+#[cfg(test)]
+mod testing;
+mod throttle;
+mod timelapse;
+mod whiteboard;
+
+#[derive(Parser, Debug)]
+#[clap(name = "srv", about = "HyperCards rooms server")]
+struct Args {
+    #[command(subcommand)]
+    cmd: Cmd,
+}
+
+#[derive(Subcommand, Debug)]
+enum Cmd {
+    /// Host rooms
+    Serve {
+        /// Address to listen on
+        #[arg(long, env = "SRV_LISTEN", default_value = "0.0.0.0:10000")]
+        listen: SocketAddr,
+
+        /// Where to keep rooms: memory, file:///some/dir or redis://[:password@]host:port/db
+        #[arg(long, env = "SRV_STORAGE", default_value = "memory")]
+        storage: String,
+
+        /// ID events published here carry along bridges (random by default)
+        #[arg(long, env = "SRV_SERVER_ID")]
+        server_id: Option<String>,
+
+        /// Address to serve Prometheus metrics on at /metrics, over HTTPS given --tls-cert
+        #[arg(long, env = "SRV_METRICS_LISTEN")]
+        metrics_listen: Option<SocketAddr>,
+
+        /// Address to serve a JSON API on under /v1, over HTTPS given --tls-cert
+        #[arg(long, env = "SRV_REST_LISTEN")]
+        rest_listen: Option<SocketAddr>,
+
+        /// Secret the admin API must be called with, as "authorization: Bearer <token>"
+        #[arg(long, env = "SRV_ADMIN_TOKEN", hide_env_values = true)]
+        admin_token: Option<AdminToken>,
+
+        /// PEM certificate (chain) to serve TLS with, along with --tls-key
+        #[arg(long, env = "SRV_TLS_CERT", requires = "tls_key")]
+        tls_cert: Option<PathBuf>,
+
+        /// PEM private key of --tls-cert
+        #[arg(long, env = "SRV_TLS_KEY", requires = "tls_cert")]
+        tls_key: Option<PathBuf>,
+
+        #[command(flatten)]
+        lifecycle: lifecycle::Args,
+
+        #[command(flatten)]
+        throttle: throttle::Args,
+    },
+
+    /// Mirror a room between two servers, both ways
+    Bridge {
+        /// One server, e.g. on the LAN
+        #[arg(long)]
+        a: String,
+
+        /// PEM to authenticate --a with, if https://
+        #[arg(long)]
+        a_ca: Option<PathBuf>,
+
+        /// The other server, e.g. in the cloud
+        #[arg(long)]
+        b: String,
+
+        /// PEM to authenticate --b with, if https://
+        #[arg(long)]
+        b_ca: Option<PathBuf>,
+
+        /// Room to mirror
+        #[arg(long, env = "WHITEBOARD_ROOM", default_value = "living-room")]
+        room: String,
+
+        /// ID to join both servers as (random by default)
+        #[arg(long, env = "SRV_BRIDGE_USER")]
+        user: Option<String>,
+    },
+
+    /// Save a room's drawings as .svg, .pdf or .jsonl (replayable by `scrolls`)
+    Export {
+        /// Host to connect to
+        #[arg(long, env = "WHITEBOARD_HOST", default_value = "http://fknwkdacd.com:10000")]
+        host: String,
+
+        /// PEM to authenticate https:// hosts with: a CA bundle or the host's self-signed certificate
+        #[arg(long, env = "WHITEBOARD_CA")]
+        ca: Option<PathBuf>,
+
+        /// Room to export
+        #[arg(long, env = "WHITEBOARD_ROOM", default_value = "living-room")]
+        room: String,
+
+        /// File to write, its extension selects the format
+        output: PathBuf,
+    },
+
+    /// Animate how a room's drawings came to be, as .gif or .png (APNG)
+    Timelapse {
+        /// Host to connect to
+        #[arg(long, env = "WHITEBOARD_HOST", default_value = "http://fknwkdacd.com:10000")]
+        host: String,
+
+        /// PEM to authenticate https:// hosts with: a CA bundle or the host's self-signed certificate
+        #[arg(long, env = "WHITEBOARD_CA")]
+        ca: Option<PathBuf>,
+
+        /// Room to animate
+        #[arg(long, env = "WHITEBOARD_ROOM", default_value = "living-room")]
+        room: String,
+
+        /// Drawings painted between frames
+        #[arg(long, default_value_t = 1)]
+        strokes_per_frame: u32,
+
+        /// How long each frame shows, in milliseconds
+        #[arg(long, default_value_t = 100)]
+        frame_delay_ms: u32,
+
+        /// Resolution, relative to the tablet's display
+        #[arg(long, default_value_t = 0.5)]
+        scale: f32,
+
+        /// File to write, its extension selects the format
+        output: PathBuf,
+    },
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    env_logger::init();
+
+    let args = Args::parse();
+    info!("args = {args:?}");
+
+    match args.cmd {
+        Cmd::Serve {
+            listen,
+            storage,
+            server_id,
+            metrics_listen,
+            rest_listen,
+            admin_token,
+            tls_cert,
+            tls_key,
+            lifecycle,
+            throttle,
+        } => {
+            let tls = match (tls_cert, tls_key) {
+                (Some(cert), Some(key)) => Some(tls(&cert, &key)?),
+                _ => None,
+            };
+            let moderation =
+                moderation::Moderation::new(admin_token.map(|t| t.secret().to_owned()));
+            let id = server_id.unwrap_or_else(server::random_id);
+            server::ntui(&id).map_err(|e| anyhow!("--server-id: {}", e.message()))?;
+            let policy = lifecycle::Policy::new(lifecycle)?;
+            let rooms = rooms::Rooms::new(storage::open(&storage).await?, policy);
+            let throttle = throttle::Throttle::new(throttle);
+            let srv = server::Server::new(id, rooms, throttle, moderation);
+            serve(listen, metrics_listen, rest_listen, tls, srv).await
+        }
+        Cmd::Bridge { a, a_ca, b, b_ca, room, user } => {
+            let user_id = user.unwrap_or_else(|| format!("bridge-{}", server::random_id()));
+            let a = Session::with_channel(channel_with_ca(&a, a_ca.as_deref())?, &room, &user_id);
+            let b = Session::with_channel(channel_with_ca(&b, b_ca.as_deref())?, &room, &user_id);
+            bridge::run(a, b).await
+        }
+        Cmd::Export { host, ca, room, output } => {
+            export(channel_with_ca(&host, ca.as_deref())?, room, output).await
+        }
+        Cmd::Timelapse { host, ca, room, strokes_per_frame, frame_delay_ms, scale, output } => {
+            let req =
+                TimelapseReq { room_id: room, format: 0, strokes_per_frame, frame_delay_ms, scale };
+            timelapse(channel_with_ca(&host, ca.as_deref())?, req, output).await
+        }
+    }
+}
+
+/// What TLS gets served with, by the gRPC services & the HTTP routes alike.
+struct Tls {
+    grpc: ServerTlsConfig,
+    http: tokio_rustls::TlsAcceptor,
+}
+
+fn tls(cert: &Path, key: &Path) -> Result<Tls> {
+    let read = |p: &Path| fs::read(p).with_context(|| format!("reading {}", p.display()));
+    let (cert, key) = (read(cert)?, read(key)?);
+    let http = http::acceptor(&cert, &key)?;
+    let grpc = ServerTlsConfig::new().identity(Identity::from_pem(cert, key));
+    Ok(Tls { grpc, http })
+}
+
+async fn serve(
+    listen: SocketAddr,
+    metrics_listen: Option<SocketAddr>,
+    rest_listen: Option<SocketAddr>,
+    tls: Option<Tls>,
+    srv: server::Server,
+) -> Result<()> {
+    let (grpc_tls, http_tls) = match tls {
+        Some(Tls { grpc, http }) => (Some(grpc), Some(http)),
+        None => (None, None),
+    };
+    info!("[serve] server ID: {}", srv.id);
+    tokio::spawn(lifecycle::collect(srv.rooms.clone()));
+    tokio::spawn(throttle::prune(srv.throttle.clone()));
+    if let Some(addr) = metrics_listen {
+        let (srv, tls) = (srv.clone(), http_tls.clone());
+        tokio::spawn(async move {
+            if let Err(e) = metrics::serve(addr, srv, tls).await {
+                error!("[serve] metrics: {e}");
+            }
+        });
+    }
+    if let Some(addr) = rest_listen {
+        let (srv, tls) = (srv.clone(), http_tls);
+        tokio::spawn(async move {
+            if let Err(e) = rest::serve(addr, srv, tls).await {
+                error!("[serve] rest: {e}");
+            }
+        });
+    }
+
+    info!("[serve] listening on {listen}");
+    router(srv, grpc_tls).await?.serve(listen).await?;
+    Ok(())
+}
+
+type GrpcWeb = Stack<GrpcWebLayer, Stack<CorsLayer, NoLayer>>;
+
+/// Our services, along with the standard gRPC health service.
+/// Browsers get to call them with gRPC-Web, from any origin.
+async fn router(srv: server::Server, tls: Option<ServerTlsConfig>) -> Result<Router<GrpcWeb>> {
+    let (mut health, health_service) = tonic_health::server::health_reporter();
+    health.set_serving::<WhiteboardServer<server::Server>>().await;
+    health.set_serving::<ScreenSharingServer<server::Server>>().await;
+    health.set_serving::<ArchiveServer<server::Server>>().await;
+    health.set_serving::<AdminServer<server::Server>>().await;
+
+    let mut builder = tonic::transport::Server::builder();
+    if let Some(tls) = tls {
+        builder = builder.tls_config(tls)?;
+    }
+    let router = builder
+        .accept_http1(true)
+        .layer(CorsLayer::permissive())
+        .layer(GrpcWebLayer::new())
+        .add_service(health_service)
+        .add_service(WhiteboardServer::new(srv.clone()))
+        .add_service(ScreenSharingServer::new(srv.clone()))
+        .add_service(ArchiveServer::new(srv.clone()))
+        .add_service(AdminServer::new(srv));
+    Ok(router)
+}
+
+async fn export(ch: Channel, room_id: String, output: PathBuf) -> Result<()> {
+    let format = match output.extension().and_then(|ext| ext.to_str()) {
+        Some("svg") => Format::Svg,
+        Some("pdf") => Format::Pdf,
+        Some("jsonl") => Format::Jsonl,
+        _ => bail!("No idea how to write {output:?}"),
+    };
+
+    let mut client = ArchiveClient::new(ch);
+    let req = ExportRoomReq { room_id, format: format.into() };
+    let rep = client.export_room(req).await?.into_inner();
+    info!("[export] got {} bytes of {}", rep.data.len(), rep.content_type);
+
+    fs::write(&output, rep.data)?;
+    Ok(())
+}
+
+async fn timelapse(ch: Channel, mut req: TimelapseReq, output: PathBuf) -> Result<()> {
+    let format = match output.extension().and_then(|ext| ext.to_str()) {
+        Some("gif") => timelapse_req::Format::Gif,
+        Some("png" | "apng") => timelapse_req::Format::Apng,
+        _ => bail!("No idea how to write {output:?}"),
+    };
+    req.format = format.into();
+
+    let mut client = ArchiveClient::new(ch);
+    let rep = client.timelapse(req).await?.into_inner();
+    info!("[timelapse] got {} bytes of {}", rep.data.len(), rep.content_type);
+
+    fs::write(&output, rep.data)?;
+    Ok(())
+}
+
+#[test]
+fn verify_cli() {
+    use clap::CommandFactory;
+    Args::command().debug_assert();
+}
+
+#[tokio::test]
+async fn serves_tls_to_clients_trusting_its_certificate() {
+    use pb::proto::hypercards::{whiteboard_client::WhiteboardClient, ListRoomsReq};
+    use tokio_stream::wrappers::TcpListenerStream;
+
+    let dir = std::env::temp_dir().join(format!("srv-tls-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_owned()]).unwrap();
+    let (cert_pem, key_pem) = (dir.join("cert.pem"), dir.join("key.pem"));
+    fs::write(&cert_pem, cert.cert.pem()).unwrap();
+    fs::write(&key_pem, cert.signing_key.serialize_pem()).unwrap();
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let tls = Some(tls(&cert_pem, &key_pem).unwrap().grpc);
+    let router = router(server::Server::default(), tls).await.unwrap();
+    tokio::spawn(router.serve_with_incoming(TcpListenerStream::new(listener)));
+
+    let list = |ch: Channel| async move {
+        let mut req = tonic::Request::new(ListRoomsReq {});
+        marauder::client::add_xuser(&mut req, "joe").unwrap();
+        WhiteboardClient::new(ch).list_rooms(req).await
+    };
+    let host = format!("https://localhost:{port}");
+    list(channel_with_ca(&host, Some(&cert_pem)).unwrap()).await.unwrap();
+    // Self-signed certificates aren't trusted by default
+    assert!(list(channel_with_ca(&host, None).unwrap()).await.is_err());
+    assert!(channel_with_ca(&format!("http://localhost:{port}"), Some(&cert_pem)).is_err());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn reports_health() {
+    use tonic_health::pb::{
+        health_check_response::ServingStatus, health_client::HealthClient, HealthCheckRequest,
+    };
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let host = format!("http://{}", listener.local_addr().unwrap());
+    let router = router(server::Server::default(), None).await.unwrap();
+    let incoming = tokio_stream::wrappers::TcpListenerStream::new(listener);
+    tokio::spawn(router.serve_with_incoming(incoming));
+
+    let mut client = HealthClient::new(channel_with_ca(&host, None).unwrap());
+    for service in ["", "hypercards.Whiteboard", "hypercards.ScreenSharing", "hypercards.Archive"] {
+        let req = HealthCheckRequest { service: service.to_owned() };
+        let rep = client.check(req).await.unwrap().into_inner();
+        assert_eq!(rep.status(), ServingStatus::Serving, "{service:?}");
+    }
+}
@@ -0,0 +1,102 @@
+//! What operators get to monitor, in Prometheus' text format
+//!
+use std::net::SocketAddr;
+
+use anyhow::Result;
+use axum::{extract::State, http::StatusCode, routing::get};
+use log::{error, info};
+use prometheus_client::{
+    encoding::text::encode,
+    metrics::{
+        counter::Counter,
+        gauge::Gauge,
+        histogram::{exponential_buckets, Histogram},
+    },
+    registry::Registry,
+};
+use tokio_rustls::TlsAcceptor;
+
+use crate::{http, server::Server};
+
+pub(crate) struct Metrics {
+    registry: Registry,
+    rooms: Gauge,
+    pub(crate) streams: Gauge,
+    pub(crate) events_in: Counter,
+    pub(crate) events_out: Counter,
+    pub(crate) bytes_in: Counter,
+    pub(crate) bytes_out: Counter,
+    pub(crate) screenshot_bytes: Histogram,
+    pub(crate) send_event_seconds: Histogram,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        let mut m = Self {
+            registry: Registry::with_prefix("srv"),
+            rooms: Gauge::default(),
+            streams: Gauge::default(),
+            events_in: Counter::default(),
+            events_out: Counter::default(),
+            bytes_in: Counter::default(),
+            bytes_out: Counter::default(),
+            // 4KiB to 16MiB
+            screenshot_bytes: Histogram::new(exponential_buckets(4096., 4., 7)),
+            // 100µs to ~1.6s
+            send_event_seconds: Histogram::new(exponential_buckets(0.0001, 4., 8)),
+        };
+        let r = &mut m.registry;
+        r.register("rooms", "Rooms in storage", m.rooms.clone());
+        r.register("streams", "Open RecvEvents streams", m.streams.clone());
+        r.register("events_received", "Events published, once per room", m.events_in.clone());
+        r.register("events_sent", "Events streamed to users", m.events_out.clone());
+        r.register("received_bytes", "Bytes of events & screenshots received", m.bytes_in.clone());
+        r.register("sent_bytes", "Bytes of events & screenshots sent", m.bytes_out.clone());
+        r.register("screenshot_bytes", "Sizes of screenshots received", m.screenshot_bytes.clone());
+        r.register(
+            "send_event_seconds",
+            "Time taken to publish events",
+            m.send_event_seconds.clone(),
+        );
+        m
+    }
+}
+
+impl Metrics {
+    fn render(&self) -> String {
+        let mut text = String::new();
+        // Only fails on formatter errors, which Strings don't have
+        encode(&mut text, &self.registry).expect("encoding metrics");
+        text
+    }
+}
+
+/// Serves `GET /metrics`, over TLS if given an acceptor.
+pub(crate) async fn serve(listen: SocketAddr, srv: Server, tls: Option<TlsAcceptor>) -> Result<()> {
+    let app = axum::Router::new().route("/metrics", get(scrape)).with_state(srv);
+    info!("[metrics] listening on {}://{listen}/metrics", http::scheme(tls.as_ref()));
+    http::serve(listen, app, tls).await
+}
+
+async fn scrape(State(srv): State<Server>) -> Result<String, StatusCode> {
+    let stats = srv.rooms.stats().await.map_err(|e| {
+        error!("[scrape] {e}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    srv.metrics.rooms.set(stats.len().try_into().unwrap_or(i64::MAX));
+    Ok(srv.metrics.render())
+}
+
+#[tokio::test]
+async fn renders_prometheus_text() {
+    let srv = Server::default();
+    srv.rooms.join("r", "joe").await.unwrap();
+    srv.metrics.events_in.inc_by(3);
+    srv.metrics.screenshot_bytes.observe(5000.);
+
+    let text = scrape(State(srv)).await.unwrap();
+    assert!(text.contains("# TYPE srv_rooms gauge\nsrv_rooms 1\n"), "{text}");
+    assert!(text.contains("srv_events_received_total 3\n"), "{text}");
+    assert!(text.contains("srv_screenshot_bytes_bucket{le=\"16384.0\"} 1\n"), "{text}");
+    assert!(text.ends_with("# EOF\n"));
+}
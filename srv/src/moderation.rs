@@ -0,0 +1,176 @@
+//! Who got kicked or banned, which rooms are locked, and who may decide
+//!
+//! Admin calls carry `authorization: Bearer <token>`, matching `--admin-token`.
+//! Without a token the admin API turns everything down. Bans and locks are
+//! kept in memory: they last until the server restarts.
+//!
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+};
+
+use tokio::sync::broadcast;
+use tonic::{Request, Status};
+
+const AUTHORIZATION_HEADER: &str = "authorization";
+
+/// Kicks nobody listened to yet are dropped past this many.
+const KICKS_CAPACITY: usize = 64;
+
+/// Closes the `RecvEvents` streams it applies to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Kick {
+    /// `None` means every room.
+    room_id: Option<String>,
+    /// `None` means every user.
+    user_id: Option<String>,
+    pub(crate) reason: String,
+}
+
+impl Kick {
+    pub(crate) fn applies(&self, room_id: &str, user_id: &str) -> bool {
+        self.room_id.as_deref().is_none_or(|r| r == room_id)
+            && self.user_id.as_deref().is_none_or(|u| u == user_id)
+    }
+
+    /// What a kicked stream ends with.
+    pub(crate) fn status(&self) -> Status {
+        Status::permission_denied(self.reason.clone())
+    }
+}
+
+pub(crate) struct Moderation {
+    token: Option<String>,
+    bans: Mutex<HashMap<String, String>>, // user ID -> reason
+    locks: Mutex<HashSet<String>>,
+    kicks: broadcast::Sender<Kick>,
+}
+
+impl Default for Moderation {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+impl Moderation {
+    pub(crate) fn new(token: Option<String>) -> Self {
+        Self {
+            token: token.filter(|t| !t.is_empty()),
+            bans: Default::default(),
+            locks: Default::default(),
+            kicks: broadcast::channel(KICKS_CAPACITY).0,
+        }
+    }
+
+    /// Fails unless `req` carries the admin token.
+    pub(crate) fn authorize<T>(&self, req: &Request<T>) -> Result<(), Status> {
+        let Some(token) = &self.token else {
+            return Err(Status::permission_denied("admin API disabled, see --admin-token"));
+        };
+        let given = req
+            .metadata()
+            .get(AUTHORIZATION_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .unwrap_or_default();
+        // Compares every byte, so timing tells nothing about the token
+        let same = given.len() == token.len()
+            && given.bytes().zip(token.bytes()).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0;
+        if !same {
+            return Err(Status::unauthenticated("bad or missing admin token"));
+        }
+        Ok(())
+    }
+
+    /// Kicks announced from now on.
+    pub(crate) fn kicks(&self) -> broadcast::Receiver<Kick> {
+        self.kicks.subscribe()
+    }
+
+    pub(crate) fn kick(&self, room_id: Option<&str>, user_id: Option<&str>, reason: &str) {
+        let kick = Kick {
+            room_id: room_id.map(str::to_owned),
+            user_id: user_id.map(str::to_owned),
+            reason: reason.to_owned(),
+        };
+        // Errors only mean nobody is listening
+        let _ = self.kicks.send(kick);
+    }
+
+    /// Bans then kicks `user_id` out of every room.
+    pub(crate) fn ban(&self, user_id: &str, reason: &str) {
+        let reason = banned(reason);
+        self.bans.lock().unwrap().insert(user_id.to_owned(), reason.clone());
+        self.kick(None, Some(user_id), &reason);
+    }
+
+    pub(crate) fn unban(&self, user_id: &str) {
+        self.bans.lock().unwrap().remove(user_id);
+    }
+
+    /// Fails if `user_id` is banned.
+    pub(crate) fn check_user(&self, user_id: &str) -> Result<(), Status> {
+        match self.bans.lock().unwrap().get(user_id) {
+            Some(reason) => Err(Status::permission_denied(reason.clone())),
+            None => Ok(()),
+        }
+    }
+
+    pub(crate) fn lock(&self, room_id: &str) {
+        self.locks.lock().unwrap().insert(room_id.to_owned());
+    }
+
+    pub(crate) fn unlock(&self, room_id: &str) {
+        self.locks.lock().unwrap().remove(room_id);
+    }
+
+    /// Fails if `room_id` is locked.
+    pub(crate) fn check_room(&self, room_id: &str) -> Result<(), Status> {
+        if self.locks.lock().unwrap().contains(room_id) {
+            return Err(Status::failed_precondition(format!("room {room_id:?} is locked")));
+        }
+        Ok(())
+    }
+}
+
+fn banned(reason: &str) -> String {
+    match reason {
+        "" => "banned".to_owned(),
+        _ => format!("banned: {reason}"),
+    }
+}
+
+#[test]
+fn authorizes_the_admin_token_only() {
+    let with = |token: &str| {
+        let mut req = Request::new(());
+        req.metadata_mut().insert(AUTHORIZATION_HEADER, token.parse().unwrap());
+        req
+    };
+    let m = Moderation::new(Some("s3cret".to_owned()));
+    m.authorize(&with("Bearer s3cret")).unwrap();
+    for bad in ["Bearer s3cre", "Bearer s3cret!", "s3cret", "Bearer "] {
+        assert_eq!(m.authorize(&with(bad)).unwrap_err().code(), tonic::Code::Unauthenticated);
+    }
+    assert!(m.authorize(&Request::new(())).is_err());
+
+    let disabled = Moderation::new(Some(String::new()));
+    let err = disabled.authorize(&with("Bearer ")).unwrap_err();
+    assert_eq!(err.code(), tonic::Code::PermissionDenied);
+}
+
+#[test]
+fn bans_kick_everywhere() {
+    let m = Moderation::default();
+    let mut kicks = m.kicks();
+    m.ban("joe", "spam");
+    assert_eq!(m.check_user("joe").unwrap_err().message(), "banned: spam");
+    m.check_user("jane").unwrap();
+
+    let kick = kicks.try_recv().unwrap();
+    assert!(kick.applies("r", "joe") && kick.applies("q", "joe"));
+    assert!(!kick.applies("r", "jane"));
+
+    m.unban("joe");
+    m.check_user("joe").unwrap();
+}
@@ -0,0 +1,169 @@
+//! JSON over HTTP, for browsers & scripts that can't speak gRPC
+//!
+//! Maps a few RPCs onto routes, with the same headers (e.g. `x-user`) and the
+//! JSON shape of `pb` messages:
+//! * `POST /v1/events` takes a `SendEventReq`
+//! * `GET /v1/rooms` returns a `ListRoomsRep`
+//! * `GET /v1/rooms/{room_id}/screen` returns a `RecvScreenRep`
+//!
+//! Failures come with the matching HTTP status and `{"error": "..."}`.
+//!
+use std::net::SocketAddr;
+
+use anyhow::Result;
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json,
+};
+use log::info;
+use pb::proto::hypercards::{
+    screen_sharing_server::ScreenSharing, whiteboard_server::Whiteboard, ListRoomsRep,
+    ListRoomsReq, RecvScreenRep, RecvScreenReq, SendEventRep, SendEventReq,
+};
+use serde_json::json;
+use tokio_rustls::TlsAcceptor;
+use tonic::{metadata::MetadataMap, Code, Extensions, Request, Status};
+use tower_http::cors::CorsLayer;
+
+use crate::{http, server::Server, throttle::RETRY_AFTER_HEADER};
+
+/// Serves the routes to any origin, over TLS if given an acceptor.
+pub(crate) async fn serve(listen: SocketAddr, srv: Server, tls: Option<TlsAcceptor>) -> Result<()> {
+    info!("[rest] listening on {}://{listen}/v1", http::scheme(tls.as_ref()));
+    http::serve(listen, app(srv), tls).await
+}
+
+fn app(srv: Server) -> axum::Router {
+    axum::Router::new()
+        .route("/v1/events", post(send_event))
+        .route("/v1/rooms", get(list_rooms))
+        .route("/v1/rooms/:room_id/screen", get(recv_screen))
+        .layer(CorsLayer::permissive())
+        .with_state(srv)
+}
+
+/// A gRPC failure, as HTTP.
+struct Error(Status);
+
+impl IntoResponse for Error {
+    fn into_response(self) -> Response {
+        let status = match self.0.code() {
+            Code::InvalidArgument | Code::OutOfRange => StatusCode::BAD_REQUEST,
+            Code::Unauthenticated => StatusCode::UNAUTHORIZED,
+            Code::PermissionDenied => StatusCode::FORBIDDEN,
+            Code::NotFound => StatusCode::NOT_FOUND,
+            Code::AlreadyExists | Code::Aborted => StatusCode::CONFLICT,
+            Code::FailedPrecondition => StatusCode::PRECONDITION_FAILED,
+            Code::ResourceExhausted => StatusCode::TOO_MANY_REQUESTS,
+            Code::Unimplemented => StatusCode::NOT_IMPLEMENTED,
+            Code::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+            Code::DeadlineExceeded => StatusCode::GATEWAY_TIMEOUT,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        let mut rep = (status, Json(json!({"error": self.0.message()}))).into_response();
+        if let Some(retry_after) = self.0.metadata().get(RETRY_AFTER_HEADER) {
+            if let Ok(v) = HeaderValue::from_bytes(retry_after.as_bytes()) {
+                rep.headers_mut().insert(RETRY_AFTER_HEADER, v);
+            }
+        }
+        rep
+    }
+}
+
+impl From<Status> for Error {
+    fn from(status: Status) -> Self {
+        Self(status)
+    }
+}
+
+/// Hands HTTP headers over as gRPC metadata.
+fn req<T>(headers: HeaderMap, msg: T) -> Request<T> {
+    Request::from_parts(MetadataMap::from_headers(headers), Extensions::default(), msg)
+}
+
+async fn send_event(
+    State(srv): State<Server>,
+    headers: HeaderMap,
+    Json(msg): Json<SendEventReq>,
+) -> Result<Json<SendEventRep>, Error> {
+    let rep = srv.send_event(req(headers, msg)).await?;
+    Ok(Json(rep.into_inner()))
+}
+
+async fn list_rooms(
+    State(srv): State<Server>,
+    headers: HeaderMap,
+) -> Result<Json<ListRoomsRep>, Error> {
+    let rep = srv.list_rooms(req(headers, ListRoomsReq {})).await?;
+    Ok(Json(rep.into_inner()))
+}
+
+async fn recv_screen(
+    State(srv): State<Server>,
+    headers: HeaderMap,
+    Path(room_id): Path<String>,
+) -> Result<Json<RecvScreenRep>, Error> {
+    let rep = srv.recv_screen(req(headers, RecvScreenReq { room_id })).await?;
+    Ok(Json(rep.into_inner()))
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{body::Body, http};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    async fn call(srv: &Server, method: &str, uri: &str, body: &str) -> (StatusCode, String) {
+        let req = http::Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("x-user", "joe")
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_owned()))
+            .unwrap();
+        let rep = app(srv.clone()).oneshot(req).await.unwrap();
+        let status = rep.status();
+        let body = axum::body::to_bytes(rep.into_body(), usize::MAX).await.unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn sends_events_and_lists_rooms() {
+        let srv = Server::default();
+        let send = r#"{"room_ids": ["r"], "event": {"drawing":
+            {"xs": [1, 2], "ys": [3, 4], "pressures": [2000, 2000], "widths": [2, 2], "color": "BLACK"}}}"#;
+        assert_eq!(call(&srv, "POST", "/v1/events", send).await, (StatusCode::OK, "{}".into()));
+        assert_eq!(srv.rooms.drawings("r").await.unwrap().unwrap().len(), 1);
+
+        let (status, body) = call(&srv, "GET", "/v1/rooms", "").await;
+        assert_eq!(status, StatusCode::OK);
+        let rep: ListRoomsRep = serde_json::from_str(&body).unwrap();
+        assert_eq!(rep.rooms[0].room_id, "r");
+        assert_eq!(rep.rooms[0].history_len, 1);
+    }
+
+    #[tokio::test]
+    async fn maps_failures_to_http() {
+        let srv = Server::default();
+        let invisible = r#"{"room_ids": ["r"], "event": {"drawing": {"xs": [1], "ys": [1]}}}"#;
+        let (status, body) = call(&srv, "POST", "/v1/events", invisible).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{body}");
+        assert!(body.starts_with(r#"{"error":"#), "{body}");
+
+        let (status, _) = call(&srv, "GET", "/v1/rooms/r/screen", "").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn serves_screens_in_base64() {
+        let srv = Server::default();
+        srv.rooms.join("r", "jane").await.unwrap();
+        srv.rooms.set_screen("r", b"\x89PNG".to_vec()).await.unwrap();
+        let (status, body) = call(&srv, "GET", "/v1/rooms/r/screen", "").await;
+        assert_eq!((status, body.as_str()), (StatusCode::OK, r#"{"canvas_png":"iVBORw=="}"#));
+    }
+}
@@ -0,0 +1,261 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::{debug, error};
+use pb::proto::hypercards::{event, Drawing, Event};
+use tokio::sync::broadcast;
+use tonic::Status;
+
+use crate::{
+    lifecycle::Policy,
+    storage::{Memory, Stats, Storage},
+};
+
+const ROOM_CHANNEL_CAPACITY: usize = 1024;
+
+/// All rooms: their live events here, everything else in storage.
+#[derive(Clone)]
+pub(crate) struct Rooms {
+    channels: Arc<Mutex<HashMap<String, broadcast::Sender<Event>>>>,
+    storage: Arc<dyn Storage>,
+    policy: Arc<Policy>,
+}
+
+impl Default for Rooms {
+    fn default() -> Self {
+        Self::new(Arc::new(Memory::default()), Policy::default())
+    }
+}
+
+/// Nanoseconds since UNIX epoch, as put in `Event.created_at`.
+pub(crate) fn now() -> i64 {
+    let d = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    d.as_nanos().try_into().unwrap_or(i64::MAX)
+}
+
+pub(crate) fn status_event(room_id: &str, user_id: &str, e: event::Event) -> Event {
+    Event {
+        created_at: now(),
+        by_user_id: user_id.to_owned(),
+        in_room_id: room_id.to_owned(),
+        event: Some(e),
+        origin_server_id: String::new(),
+        via_server_ids: vec![],
+    }
+}
+
+fn history_full(room_id: &str, max: u64) -> Status {
+    Status::resource_exhausted(format!("room {room_id:?} is full ({max} events)"))
+}
+
+fn internal(e: anyhow::Error) -> Status {
+    error!("[storage] {e:#}");
+    Status::internal("storage failure")
+}
+
+impl Rooms {
+    pub(crate) fn new(storage: Arc<dyn Storage>, policy: Policy) -> Self {
+        Self { channels: Default::default(), storage, policy: Arc::new(policy) }
+    }
+
+    pub(crate) fn policy(&self) -> &Policy {
+        &self.policy
+    }
+
+    fn tx(&self, room_id: &str) -> broadcast::Sender<Event> {
+        let mut channels = self.channels.lock().unwrap();
+        let tx = channels
+            .entry(room_id.to_owned())
+            .or_insert_with(|| broadcast::channel(ROOM_CHANNEL_CAPACITY).0);
+        tx.clone()
+    }
+
+    /// Subscribes `user_id` to `room_id`'s events, announcing them to others.
+    /// Returns the receiving end along with the amount of users in the room.
+    pub(crate) async fn join(
+        &self,
+        room_id: &str,
+        user_id: &str,
+    ) -> Result<(broadcast::Receiver<Event>, u32), Status> {
+        let rx = self.tx(room_id).subscribe();
+        let count = self.storage.join(room_id, user_id).await.map_err(internal)?;
+        let max = self.policy.settings(room_id).max_members;
+        if max != 0 && count > max {
+            self.storage.leave(room_id, user_id).await.map_err(internal)?;
+            return Err(Status::resource_exhausted(format!(
+                "room {room_id:?} is full ({max} members)"
+            )));
+        }
+        self.announce(status_event(room_id, user_id, event::Event::UserJoinedTheRoom(true)));
+        debug!("[join] {user_id:?} joined {room_id:?}");
+        Ok((rx, count))
+    }
+
+    pub(crate) async fn leave(&self, room_id: &str, user_id: &str) -> Result<(), Status> {
+        self.storage.leave(room_id, user_id).await.map_err(internal)?;
+        self.announce(status_event(room_id, user_id, event::Event::UserLeftTheRoom(true)));
+        debug!("[leave] {user_id:?} left {room_id:?}");
+        Ok(())
+    }
+
+    /// Fails early if the room's history is full, e.g. before publishing to many rooms.
+    /// Others may publish in the meantime: [`Self::publish`] has the final say.
+    pub(crate) async fn check_history(&self, room_id: &str) -> Result<(), Status> {
+        let max = self.policy.settings(room_id).max_history;
+        if max == 0 {
+            return Ok(());
+        }
+        if self.storage.history_len(room_id).await.map_err(internal)? >= max {
+            return Err(history_full(room_id, max));
+        }
+        Ok(())
+    }
+
+    /// Records then broadcasts an already stamped event, failing if the room's history is full.
+    pub(crate) async fn publish(&self, event: Event) -> Result<(), Status> {
+        let room_id = &event.in_room_id;
+        let max = self.policy.settings(room_id).max_history;
+        if !self.storage.push_event(&event, max).await.map_err(internal)? {
+            return Err(history_full(room_id, max));
+        }
+        self.announce(event);
+        Ok(())
+    }
+
+    /// Broadcasts without recording, as with status events.
+    fn announce(&self, event: Event) {
+        // Errors only mean nobody is listening
+        let _ = self.tx(&event.in_room_id).send(event);
+    }
+
+    /// All rooms, sorted by name.
+    pub(crate) async fn stats(&self) -> Result<Vec<Stats>, Status> {
+        self.storage.rooms().await.map_err(internal)
+    }
+
+    /// Forgets a room's history, screenshot and members.
+    pub(crate) async fn remove(&self, room_id: &str) -> Result<(), Status> {
+        self.storage.remove(room_id).await.map_err(internal)?;
+        self.drop_channel(room_id);
+        Ok(())
+    }
+
+    /// Forgets a room unless someone is in it or it was active after `idle_since`,
+    /// both checked as the room is removed. Returns whether it was.
+    pub(crate) async fn remove_idle(&self, room_id: &str, idle_since: i64) -> Result<bool, Status> {
+        let removed = self.storage.remove_idle(room_id, idle_since).await.map_err(internal)?;
+        if removed {
+            self.drop_channel(room_id);
+        }
+        Ok(removed)
+    }
+
+    fn drop_channel(&self, room_id: &str) {
+        let mut channels = self.channels.lock().unwrap();
+        if channels.get(room_id).is_some_and(|tx| tx.receiver_count() == 0) {
+            channels.remove(room_id);
+        }
+    }
+
+    /// Forgets a room's history and screenshot, erasing its members' canvases.
+    pub(crate) async fn clear(&self, room_id: &str) -> Result<(), Status> {
+        self.storage.clear(room_id).await.map_err(internal)?;
+        for d in marauder::shapes::wipe::whole_display() {
+            self.announce(status_event(room_id, "", event::Event::Drawing(d)));
+        }
+        Ok(())
+    }
+
+    /// Moves a room's history and screenshot to `new_room_id`, which must not exist yet.
+    /// The history is cut down to the new room's `max_history`.
+    pub(crate) async fn rename(&self, room_id: &str, new_room_id: &str) -> Result<(), Status> {
+        let max = self.policy.settings(new_room_id).max_history;
+        if !self.storage.rename(room_id, new_room_id, max).await.map_err(internal)? {
+            if self.storage.events(new_room_id).await.map_err(internal)?.is_some() {
+                return Err(Status::already_exists(format!("room {new_room_id:?} already exists")));
+            }
+            return Err(Status::not_found(format!("no such room {room_id:?}")));
+        }
+        self.drop_channel(room_id);
+        Ok(())
+    }
+
+    /// One entry per open stream, or `None` if the room doesn't exist.
+    pub(crate) async fn members(&self, room_id: &str) -> Result<Option<Vec<String>>, Status> {
+        self.storage.members(room_id).await.map_err(internal)
+    }
+
+    /// Drawings of a room in the order they were received, or `None` if the room doesn't exist.
+    pub(crate) async fn drawings(&self, room_id: &str) -> Result<Option<Vec<Drawing>>, Status> {
+        let Some(events) = self.storage.events(room_id).await.map_err(internal)? else {
+            return Ok(None);
+        };
+        let drawings = events
+            .into_iter()
+            .filter_map(|e| match e.event {
+                Some(event::Event::Drawing(d)) => Some(d),
+                _ => None,
+            })
+            .collect();
+        Ok(Some(drawings))
+    }
+
+    pub(crate) async fn set_screen(&self, room_id: &str, png: Vec<u8>) -> Result<(), Status> {
+        self.storage.set_screen(room_id, png).await.map_err(internal)
+    }
+
+    pub(crate) async fn screen(&self, room_id: &str) -> Result<Option<Vec<u8>>, Status> {
+        self.storage.screen(room_id).await.map_err(internal)
+    }
+}
+
+#[tokio::test]
+async fn joining_and_leaving_are_recorded() {
+    let rooms = Rooms::default();
+    let (mut rx, count) = rooms.join("living-room", "joe").await.unwrap();
+    assert_eq!(count, 1);
+    let (_rx, count) = rooms.join("living-room", "jane").await.unwrap();
+    assert_eq!(count, 2);
+    let members = rooms.members("living-room").await.unwrap();
+    assert_eq!(members, Some(vec!["jane".to_owned(), "joe".to_owned()]));
+
+    let e = rx.try_recv().unwrap();
+    assert_eq!(e.by_user_id, "joe");
+    let e = rx.try_recv().unwrap();
+    assert_eq!(e.by_user_id, "jane");
+    assert_eq!(e.event, Some(event::Event::UserJoinedTheRoom(true)));
+
+    rooms.leave("living-room", "jane").await.unwrap();
+    let stats = rooms.stats().await.unwrap();
+    assert_eq!((stats[0].room_id.as_str(), stats[0].streams), ("living-room", 1));
+    let e = rx.try_recv().unwrap();
+    assert_eq!(e.event, Some(event::Event::UserLeftTheRoom(true)));
+
+    assert_eq!(rooms.drawings("living-room").await.unwrap(), Some(vec![]));
+    assert_eq!(rooms.drawings("kitchen").await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn limits_are_enforced() {
+    use pb::proto::hypercards::RoomSettings;
+
+    let settings = RoomSettings { max_members: 1, max_history: 1, ..Default::default() };
+    let rooms = Rooms::new(Arc::new(Memory::default()), Policy::uniform(settings));
+    let (_rx, _) = rooms.join("r", "joe").await.unwrap();
+    let full = rooms.join("r", "jane").await.unwrap_err();
+    assert_eq!(full.code(), tonic::Code::ResourceExhausted);
+    assert_eq!(rooms.members("r").await.unwrap().unwrap(), ["joe"]);
+
+    rooms.check_history("r").await.unwrap();
+    let drawing = event::Event::Drawing(Drawing::default());
+    rooms.publish(status_event("r", "joe", drawing.clone())).await.unwrap();
+    let full = rooms.check_history("r").await.unwrap_err();
+    assert_eq!(full.code(), tonic::Code::ResourceExhausted);
+    // Even when publishing past the check
+    let full = rooms.publish(status_event("r", "joe", drawing)).await.unwrap_err();
+    assert_eq!(full.code(), tonic::Code::ResourceExhausted);
+    assert_eq!(rooms.drawings("r").await.unwrap().unwrap().len(), 1);
+}
@@ -0,0 +1,41 @@
+use log::debug;
+use pb::proto::hypercards::{
+    screen_sharing_server::ScreenSharing, RecvScreenRep, RecvScreenReq, SendScreenRep,
+    SendScreenReq,
+};
+use tonic::{Request, Response, Status};
+
+use crate::server::{ntui, user_id, Server};
+
+#[tonic::async_trait]
+impl ScreenSharing for Server {
+    async fn send_screen(
+        &self,
+        req: Request<SendScreenReq>,
+    ) -> Result<Response<SendScreenRep>, Status> {
+        let user_id = user_id(&req)?;
+        let SendScreenReq { room_id, screen_png } = req.into_inner();
+        ntui(&room_id)?;
+        self.moderation.check_user(&user_id)?;
+        self.moderation.check_room(&room_id)?;
+        debug!("[send_screen] {user_id:?} sent {} bytes to {room_id:?}", screen_png.len());
+        self.metrics.bytes_in.inc_by(screen_png.len() as u64);
+        self.metrics.screenshot_bytes.observe(screen_png.len() as f64);
+        self.rooms.set_screen(&room_id, screen_png).await?;
+        Ok(Response::new(SendScreenRep {}))
+    }
+
+    // Anonymous calls are allowed
+    async fn recv_screen(
+        &self,
+        req: Request<RecvScreenReq>,
+    ) -> Result<Response<RecvScreenRep>, Status> {
+        let RecvScreenReq { room_id } = req.into_inner();
+        ntui(&room_id)?;
+        let Some(canvas_png) = self.rooms.screen(&room_id).await? else {
+            return Err(Status::not_found(format!("no such room {room_id:?}")));
+        };
+        self.metrics.bytes_out.inc_by(canvas_png.len() as u64);
+        Ok(Response::new(RecvScreenRep { canvas_png }))
+    }
+}
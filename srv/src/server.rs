@@ -0,0 +1,77 @@
+use std::sync::Arc;
+
+use tonic::{Request, Status};
+
+use crate::{metrics::Metrics, moderation::Moderation, rooms::Rooms, throttle::Throttle};
+
+/// Holds everything our gRPC services share.
+#[derive(Clone)]
+pub(crate) struct Server {
+    /// Tells events published here from those bridged from elsewhere.
+    pub(crate) id: Arc<str>,
+    pub(crate) rooms: Rooms,
+    pub(crate) throttle: Arc<Throttle>,
+    pub(crate) metrics: Arc<Metrics>,
+    pub(crate) moderation: Arc<Moderation>,
+}
+
+impl Default for Server {
+    fn default() -> Self {
+        Self::new(random_id(), Default::default(), Default::default(), Default::default())
+    }
+}
+
+impl Server {
+    pub(crate) fn new(
+        id: String,
+        rooms: Rooms,
+        throttle: Throttle,
+        moderation: Moderation,
+    ) -> Self {
+        Self {
+            id: id.into(),
+            rooms,
+            throttle: Arc::new(throttle),
+            metrics: Default::default(),
+            moderation: Arc::new(moderation),
+        }
+    }
+}
+
+pub(crate) fn random_id() -> String {
+    uuid::Uuid::new_v4().hyphenated().to_string()
+}
+
+/// Header `RecvEvents` responses tell the server's ID with.
+pub(crate) const SERVER_ID_HEADER: &str = "x-server-id";
+
+const USER_ID_HEADER: &str = "x-user";
+
+/// Extracts the caller's ID, as set by clients in the "x-user" header.
+pub(crate) fn user_id<T>(req: &Request<T>) -> Result<String, Status> {
+    let Some(user_id) = req.metadata().get(USER_ID_HEADER) else {
+        return Err(Status::unauthenticated(format!("missing {USER_ID_HEADER:?} header")));
+    };
+    let user_id = user_id
+        .to_str()
+        .map_err(|e| Status::unauthenticated(format!("bad {USER_ID_HEADER:?} header: {e}")))?;
+    ntui(user_id)?;
+    Ok(user_id.to_owned())
+}
+
+/// Disallows empty IDs and the characters the Go server forbids (. / * > and whitespace)
+pub(crate) fn ntui(s: &str) -> Result<(), Status> {
+    if s.is_empty() || s.contains(['.', '/', '*', '>']) || s.contains(char::is_whitespace) {
+        return Err(Status::invalid_argument(format!("bad user string {s:?}")));
+    }
+    Ok(())
+}
+
+#[test]
+fn ntui_rejects_routing_chars() {
+    assert!(ntui("living-room").is_ok());
+    assert!(ntui("c91dd90e-77b8-477c-94f7-a25ff0e5b584").is_ok());
+    for bad in ["", "a.b", "a/b", "*", "a>", "a b", "a\tb"] {
+        assert!(ntui(bad).is_err(), "{bad:?}");
+    }
+}
@@ -0,0 +1,347 @@
+use std::{fs, path::Path, sync::Arc};
+
+use anyhow::Result;
+use pb::proto::hypercards::Event;
+use prost::Message;
+use redb::{Database, ReadableDatabase, ReadableTable, TableDefinition};
+
+use super::{count, moved, Stats, Storage};
+use crate::rooms::now;
+
+/// Room ID -> (last activity, history length)
+const ROOMS: TableDefinition<&str, (i64, u64)> = TableDefinition::new("rooms");
+const EVENTS: TableDefinition<(&str, u64), &[u8]> = TableDefinition::new("events");
+const SCREENS: TableDefinition<&str, &[u8]> = TableDefinition::new("screens");
+const MEMBERS: TableDefinition<(&str, &str), u64> = TableDefinition::new("members");
+
+/// An embedded database, in `<dir>/srv.redb`.
+pub(crate) struct Disk {
+    db: Arc<Database>,
+}
+
+impl Disk {
+    pub(crate) fn open(dir: &str) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        let db = Database::create(Path::new(dir).join("srv.redb"))?;
+        let tx = db.begin_write()?;
+        {
+            tx.open_table(ROOMS)?;
+            tx.open_table(EVENTS)?;
+            tx.open_table(SCREENS)?;
+            // Streams don't survive restarts
+            tx.open_table(MEMBERS)?.retain(|_, _| false)?;
+        }
+        tx.commit()?;
+        Ok(Self { db: Arc::new(db) })
+    }
+
+    /// Runs blocking database work off the async runtime.
+    async fn with<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Database) -> Result<T> + Send + 'static,
+    {
+        let db = self.db.clone();
+        tokio::task::spawn_blocking(move || f(&db)).await?
+    }
+}
+
+fn exists(db: &Database, room_id: &str) -> Result<bool> {
+    Ok(db.begin_read()?.open_table(ROOMS)?.get(room_id)?.is_some())
+}
+
+/// Length of a room's history, 0 if the room doesn't exist.
+fn history_len(table: &impl ReadableTable<&'static str, (i64, u64)>, room_id: &str) -> Result<u64> {
+    Ok(table.get(room_id)?.map_or(0, |v| v.value().1))
+}
+
+/// Open streams of a room, by user.
+fn streams(
+    table: &impl ReadableTable<(&'static str, &'static str), u64>,
+    room_id: &str,
+) -> Result<Vec<(String, u64)>> {
+    let mut streams = vec![];
+    for kv in table.range((room_id, "")..)? {
+        let (k, v) = kv?;
+        let (room, user) = k.value();
+        if room != room_id {
+            break;
+        }
+        streams.push((user.to_owned(), v.value()));
+    }
+    Ok(streams)
+}
+
+#[tonic::async_trait]
+impl Storage for Disk {
+    async fn push_event(&self, event: &Event, max_history: u64) -> Result<bool> {
+        let (room_id, data) = (event.in_room_id.clone(), event.encode_to_vec());
+        self.with(move |db| {
+            // Write transactions are serialized: nobody pushes in between counting & pushing
+            let tx = db.begin_write()?;
+            {
+                let mut rooms = tx.open_table(ROOMS)?;
+                let len = history_len(&rooms, &room_id)?;
+                if max_history != 0 && len >= max_history {
+                    return Ok(false);
+                }
+                // Histories only ever grow, or go as a whole: their length is the next sequence
+                tx.open_table(EVENTS)?.insert((room_id.as_str(), len), data.as_slice())?;
+                rooms.insert(room_id.as_str(), (now(), len + 1))?;
+            }
+            tx.commit()?;
+            Ok(true)
+        })
+        .await
+    }
+
+    async fn history_len(&self, room_id: &str) -> Result<u64> {
+        let room_id = room_id.to_owned();
+        self.with(move |db| history_len(&db.begin_read()?.open_table(ROOMS)?, &room_id)).await
+    }
+
+    async fn events(&self, room_id: &str) -> Result<Option<Vec<Event>>> {
+        let room_id = room_id.to_owned();
+        self.with(move |db| {
+            if !exists(db, &room_id)? {
+                return Ok(None);
+            }
+            let tx = db.begin_read()?;
+            let table = tx.open_table(EVENTS)?;
+            let mut events = vec![];
+            for kv in table.range((room_id.as_str(), 0)..=(room_id.as_str(), u64::MAX))? {
+                events.push(Event::decode(kv?.1.value())?);
+            }
+            Ok(Some(events))
+        })
+        .await
+    }
+
+    async fn set_screen(&self, room_id: &str, png: Vec<u8>) -> Result<()> {
+        let room_id = room_id.to_owned();
+        self.with(move |db| {
+            let tx = db.begin_write()?;
+            {
+                let mut rooms = tx.open_table(ROOMS)?;
+                let len = history_len(&rooms, &room_id)?;
+                rooms.insert(room_id.as_str(), (now(), len))?;
+                tx.open_table(SCREENS)?.insert(room_id.as_str(), png.as_slice())?;
+            }
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn screen(&self, room_id: &str) -> Result<Option<Vec<u8>>> {
+        let room_id = room_id.to_owned();
+        self.with(move |db| {
+            if !exists(db, &room_id)? {
+                return Ok(None);
+            }
+            let tx = db.begin_read()?;
+            let png = tx.open_table(SCREENS)?.get(room_id.as_str())?;
+            Ok(Some(png.map(|png| png.value().to_vec()).unwrap_or_default()))
+        })
+        .await
+    }
+
+    async fn join(&self, room_id: &str, user_id: &str) -> Result<u32> {
+        let (room_id, user_id) = (room_id.to_owned(), user_id.to_owned());
+        self.with(move |db| {
+            let tx = db.begin_write()?;
+            let count = {
+                let mut rooms = tx.open_table(ROOMS)?;
+                let len = history_len(&rooms, &room_id)?;
+                rooms.insert(room_id.as_str(), (now(), len))?;
+                let mut members = tx.open_table(MEMBERS)?;
+                let key = (room_id.as_str(), user_id.as_str());
+                let n = members.get(key)?.map(|n| n.value()).unwrap_or_default();
+                members.insert(key, n + 1)?;
+                count(streams(&members, &room_id)?.into_iter().map(|(_, n)| n))
+            };
+            tx.commit()?;
+            Ok(count)
+        })
+        .await
+    }
+
+    async fn leave(&self, room_id: &str, user_id: &str) -> Result<()> {
+        let (room_id, user_id) = (room_id.to_owned(), user_id.to_owned());
+        self.with(move |db| {
+            let tx = db.begin_write()?;
+            {
+                let mut rooms = tx.open_table(ROOMS)?;
+                let len = rooms.get(room_id.as_str())?.map(|v| v.value().1);
+                if let Some(len) = len {
+                    rooms.insert(room_id.as_str(), (now(), len))?;
+                }
+                let mut members = tx.open_table(MEMBERS)?;
+                let key = (room_id.as_str(), user_id.as_str());
+                let n = members.get(key)?.map(|n| n.value());
+                match n {
+                    None => {}
+                    Some(0 | 1) => drop(members.remove(key)?),
+                    Some(n) => drop(members.insert(key, n - 1)?),
+                }
+            }
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn members(&self, room_id: &str) -> Result<Option<Vec<String>>> {
+        let room_id = room_id.to_owned();
+        self.with(move |db| {
+            if !exists(db, &room_id)? {
+                return Ok(None);
+            }
+            let tx = db.begin_read()?;
+            let streams = streams(&tx.open_table(MEMBERS)?, &room_id)?;
+            let members = streams
+                .into_iter()
+                .flat_map(|(user_id, n)| std::iter::repeat_n(user_id, n as usize))
+                .collect();
+            Ok(Some(members))
+        })
+        .await
+    }
+
+    async fn rooms(&self) -> Result<Vec<Stats>> {
+        self.with(move |db| {
+            let tx = db.begin_read()?;
+            let members = tx.open_table(MEMBERS)?;
+            let mut rooms = vec![];
+            for kv in tx.open_table(ROOMS)?.iter()? {
+                let (k, v) = kv?;
+                let room_id = k.value().to_owned();
+                let streams = count(streams(&members, &room_id)?.into_iter().map(|(_, n)| n));
+                let (last_active_at, history_len) = v.value();
+                rooms.push(Stats { room_id, streams, history_len, last_active_at });
+            }
+            Ok(rooms)
+        })
+        .await
+    }
+
+    async fn clear(&self, room_id: &str) -> Result<()> {
+        let room_id = room_id.to_owned();
+        self.with(move |db| {
+            let tx = db.begin_write()?;
+            {
+                let room_id = room_id.as_str();
+                tx.open_table(ROOMS)?.insert(room_id, (now(), 0))?;
+                tx.open_table(SCREENS)?.remove(room_id)?;
+                tx.open_table(EVENTS)?
+                    .retain_in((room_id, 0)..=(room_id, u64::MAX), |_, _| false)?;
+            }
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn rename(&self, room_id: &str, new_room_id: &str, max_history: u64) -> Result<bool> {
+        let (room_id, new_room_id) = (room_id.to_owned(), new_room_id.to_owned());
+        self.with(move |db| {
+            let tx = db.begin_write()?;
+            {
+                let (room_id, new_room_id) = (room_id.as_str(), new_room_id.as_str());
+                let mut rooms = tx.open_table(ROOMS)?;
+                if rooms.get(new_room_id)?.is_some() || rooms.get(room_id)?.is_none() {
+                    return Ok(false);
+                }
+
+                let mut events = tx.open_table(EVENTS)?;
+                let mut history = vec![];
+                for kv in events.range((room_id, 0)..=(room_id, u64::MAX))? {
+                    history.push(Event::decode(kv?.1.value())?);
+                }
+                events.retain_in((room_id, 0)..=(room_id, u64::MAX), |_, _| false)?;
+                let history = moved(history, new_room_id, max_history);
+                for (seq, event) in history.iter().enumerate() {
+                    events.insert((new_room_id, seq as u64), event.encode_to_vec().as_slice())?;
+                }
+                rooms.remove(room_id)?;
+                rooms.insert(new_room_id, (now(), history.len() as u64))?;
+
+                let mut screens = tx.open_table(SCREENS)?;
+                let png = screens.remove(room_id)?.map(|png| png.value().to_vec());
+                if let Some(png) = png {
+                    screens.insert(new_room_id, png.as_slice())?;
+                }
+                let mut members = tx.open_table(MEMBERS)?;
+                for (user_id, _) in streams(&members, room_id)? {
+                    members.remove((room_id, user_id.as_str()))?;
+                }
+            }
+            tx.commit()?;
+            Ok(true)
+        })
+        .await
+    }
+
+    async fn remove(&self, room_id: &str) -> Result<()> {
+        let room_id = room_id.to_owned();
+        self.with(move |db| {
+            let tx = db.begin_write()?;
+            {
+                let room_id = room_id.as_str();
+                tx.open_table(ROOMS)?.remove(room_id)?;
+                tx.open_table(SCREENS)?.remove(room_id)?;
+                tx.open_table(EVENTS)?
+                    .retain_in((room_id, 0)..=(room_id, u64::MAX), |_, _| false)?;
+                let mut members = tx.open_table(MEMBERS)?;
+                for (user_id, _) in streams(&members, room_id)? {
+                    members.remove((room_id, user_id.as_str()))?;
+                }
+            }
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn remove_idle(&self, room_id: &str, idle_since: i64) -> Result<bool> {
+        let room_id = room_id.to_owned();
+        self.with(move |db| {
+            let tx = db.begin_write()?;
+            {
+                let room_id = room_id.as_str();
+                let mut rooms = tx.open_table(ROOMS)?;
+                let last_active_at = rooms.get(room_id)?.map(|v| v.value().0);
+                if last_active_at.is_none_or(|at| at > idle_since) {
+                    return Ok(false);
+                }
+                if !streams(&tx.open_table(MEMBERS)?, room_id)?.is_empty() {
+                    return Ok(false);
+                }
+                rooms.remove(room_id)?;
+                tx.open_table(SCREENS)?.remove(room_id)?;
+                tx.open_table(EVENTS)?
+                    .retain_in((room_id, 0)..=(room_id, u64::MAX), |_, _| false)?;
+            }
+            tx.commit()?;
+            Ok(true)
+        })
+        .await
+    }
+}
+
+#[tokio::test]
+async fn conforms() {
+    let dir = std::env::temp_dir().join(format!("srv-disk-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let disk = Disk::open(dir.to_str().unwrap()).unwrap();
+    super::conformance(&disk).await;
+    let event = Event { in_room_id: "r".to_owned(), ..Default::default() };
+    assert!(disk.push_event(&event, 0).await.unwrap());
+    drop(disk);
+
+    // History outlives restarts, streams don't
+    let disk = Disk::open(dir.to_str().unwrap()).unwrap();
+    assert_eq!(disk.events("r").await.unwrap().unwrap(), [event]);
+    assert_eq!(disk.members("r").await.unwrap().unwrap(), Vec::<String>::new());
+    fs::remove_dir_all(&dir).unwrap();
+}
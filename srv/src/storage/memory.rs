@@ -0,0 +1,158 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Mutex,
+};
+
+use anyhow::Result;
+use pb::proto::hypercards::Event;
+
+use super::{count, moved, Stats, Storage};
+use crate::rooms::now;
+
+#[derive(Default)]
+pub(crate) struct Memory {
+    rooms: Mutex<BTreeMap<String, Room>>,
+}
+
+#[derive(Default)]
+struct Room {
+    history: Vec<Event>,
+    screen_png: Vec<u8>,
+    members: HashMap<String, u64>, // user ID -> open streams
+    last_active_at: i64,
+}
+
+impl Room {
+    fn count(&self) -> u32 {
+        count(self.members.values().copied())
+    }
+}
+
+/// The room, touched.
+fn active<'a>(rooms: &'a mut BTreeMap<String, Room>, room_id: &str) -> &'a mut Room {
+    let room = rooms.entry(room_id.to_owned()).or_default();
+    room.last_active_at = now();
+    room
+}
+
+#[tonic::async_trait]
+impl Storage for Memory {
+    async fn push_event(&self, event: &Event, max_history: u64) -> Result<bool> {
+        let mut rooms = self.rooms.lock().unwrap();
+        let len = rooms.get(&event.in_room_id).map_or(0, |room| room.history.len() as u64);
+        if max_history != 0 && len >= max_history {
+            return Ok(false);
+        }
+        active(&mut rooms, &event.in_room_id).history.push(event.clone());
+        Ok(true)
+    }
+
+    async fn history_len(&self, room_id: &str) -> Result<u64> {
+        let rooms = self.rooms.lock().unwrap();
+        Ok(rooms.get(room_id).map_or(0, |room| room.history.len() as u64))
+    }
+
+    async fn events(&self, room_id: &str) -> Result<Option<Vec<Event>>> {
+        let rooms = self.rooms.lock().unwrap();
+        Ok(rooms.get(room_id).map(|room| room.history.clone()))
+    }
+
+    async fn set_screen(&self, room_id: &str, png: Vec<u8>) -> Result<()> {
+        let mut rooms = self.rooms.lock().unwrap();
+        active(&mut rooms, room_id).screen_png = png;
+        Ok(())
+    }
+
+    async fn screen(&self, room_id: &str) -> Result<Option<Vec<u8>>> {
+        let rooms = self.rooms.lock().unwrap();
+        Ok(rooms.get(room_id).map(|room| room.screen_png.clone()))
+    }
+
+    async fn join(&self, room_id: &str, user_id: &str) -> Result<u32> {
+        let mut rooms = self.rooms.lock().unwrap();
+        let room = active(&mut rooms, room_id);
+        *room.members.entry(user_id.to_owned()).or_default() += 1;
+        Ok(room.count())
+    }
+
+    async fn leave(&self, room_id: &str, user_id: &str) -> Result<()> {
+        let mut rooms = self.rooms.lock().unwrap();
+        let Some(room) = rooms.get_mut(room_id) else { return Ok(()) };
+        room.last_active_at = now();
+        if let Some(streams) = room.members.get_mut(user_id) {
+            *streams -= 1;
+            if *streams == 0 {
+                room.members.remove(user_id);
+            }
+        }
+        Ok(())
+    }
+
+    async fn members(&self, room_id: &str) -> Result<Option<Vec<String>>> {
+        let rooms = self.rooms.lock().unwrap();
+        let Some(room) = rooms.get(room_id) else { return Ok(None) };
+        let mut members: Vec<_> = room
+            .members
+            .iter()
+            .flat_map(|(id, streams)| std::iter::repeat_n(id.clone(), *streams as usize))
+            .collect();
+        members.sort();
+        Ok(Some(members))
+    }
+
+    async fn rooms(&self) -> Result<Vec<Stats>> {
+        let rooms = self.rooms.lock().unwrap();
+        let stats = rooms
+            .iter()
+            .map(|(id, room)| Stats {
+                room_id: id.clone(),
+                streams: room.count(),
+                history_len: room.history.len() as u64,
+                last_active_at: room.last_active_at,
+            })
+            .collect();
+        Ok(stats)
+    }
+
+    async fn clear(&self, room_id: &str) -> Result<()> {
+        let mut rooms = self.rooms.lock().unwrap();
+        let room = active(&mut rooms, room_id);
+        room.history.clear();
+        room.screen_png.clear();
+        Ok(())
+    }
+
+    async fn rename(&self, room_id: &str, new_room_id: &str, max_history: u64) -> Result<bool> {
+        let mut rooms = self.rooms.lock().unwrap();
+        if rooms.contains_key(new_room_id) {
+            return Ok(false);
+        }
+        let Some(Room { history, screen_png, .. }) = rooms.remove(room_id) else {
+            return Ok(false);
+        };
+        let room = active(&mut rooms, new_room_id);
+        room.history = moved(history, new_room_id, max_history);
+        room.screen_png = screen_png;
+        Ok(true)
+    }
+
+    async fn remove(&self, room_id: &str) -> Result<()> {
+        self.rooms.lock().unwrap().remove(room_id);
+        Ok(())
+    }
+
+    async fn remove_idle(&self, room_id: &str, idle_since: i64) -> Result<bool> {
+        let mut rooms = self.rooms.lock().unwrap();
+        let Some(room) = rooms.get(room_id) else { return Ok(false) };
+        if !room.members.is_empty() || room.last_active_at > idle_since {
+            return Ok(false);
+        }
+        rooms.remove(room_id);
+        Ok(true)
+    }
+}
+
+#[tokio::test]
+async fn conforms() {
+    super::conformance(&Memory::default()).await;
+}
@@ -0,0 +1,187 @@
+//! Where rooms' history, screenshots and membership live
+//!
+//! Picked with a URL-ish string:
+//! * `memory` (the default): lost on restart
+//! * `file:///some/dir`: an embedded database, for single node self-hosting
+//! * `redis://[:password@]host:port/db`: shared with the Go servers' Redis (see `redis/redis.conf`)
+//!
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
+use pb::proto::hypercards::Event;
+
+mod disk;
+mod memory;
+mod redis;
+
+pub(crate) use memory::Memory;
+
+/// A room exists as soon as someone joins it or publishes to it, until it is removed.
+/// Every write but `remove` marks the room as active.
+#[tonic::async_trait]
+pub(crate) trait Storage: Send + Sync {
+    /// Appends to the history of `event.in_room_id`, unless it already holds
+    /// `max_history` events (0 for no limit). Returns whether it did.
+    async fn push_event(&self, event: &Event, max_history: u64) -> Result<bool>;
+
+    /// Length of a room's history, 0 if the room doesn't exist.
+    async fn history_len(&self, room_id: &str) -> Result<u64>;
+
+    /// A room's history, or `None` if the room doesn't exist.
+    async fn events(&self, room_id: &str) -> Result<Option<Vec<Event>>>;
+
+    async fn set_screen(&self, room_id: &str, png: Vec<u8>) -> Result<()>;
+
+    /// A room's last screenshot (empty if none), or `None` if the room doesn't exist.
+    async fn screen(&self, room_id: &str) -> Result<Option<Vec<u8>>>;
+
+    /// Accounts for one more stream of `user_id`'s, returning the amount of streams in the room.
+    async fn join(&self, room_id: &str, user_id: &str) -> Result<u32>;
+
+    async fn leave(&self, room_id: &str, user_id: &str) -> Result<()>;
+
+    /// One entry per open stream, sorted, or `None` if the room doesn't exist.
+    async fn members(&self, room_id: &str) -> Result<Option<Vec<String>>>;
+
+    /// All rooms, sorted by name.
+    async fn rooms(&self) -> Result<Vec<Stats>>;
+
+    /// Forgets a room's history and screenshot, but not its members.
+    async fn clear(&self, room_id: &str) -> Result<()>;
+
+    /// Moves a room's screenshot and history, down to its first `max_history` events
+    /// (0 for no limit), to `new_room_id` and forgets everything else about it, all at once.
+    /// Returns whether it did: not if the room doesn't exist, or `new_room_id` does.
+    async fn rename(&self, room_id: &str, new_room_id: &str, max_history: u64) -> Result<bool>;
+
+    /// Forgets everything about a room.
+    async fn remove(&self, room_id: &str) -> Result<()>;
+
+    /// Forgets everything about a room nobody is in and that was last active
+    /// at `idle_since` or before. Returns whether it did.
+    async fn remove_idle(&self, room_id: &str, idle_since: i64) -> Result<bool>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Stats {
+    pub(crate) room_id: String,
+    /// Open streams
+    pub(crate) streams: u32,
+    pub(crate) history_len: u64,
+    /// See `rooms::now`
+    pub(crate) last_active_at: i64,
+}
+
+pub(crate) async fn open(url: &str) -> Result<Arc<dyn Storage>> {
+    let storage: Arc<dyn Storage> = match url {
+        "memory" => Arc::new(Memory::default()),
+        _ if url.starts_with("file://") => Arc::new(disk::Disk::open(&url["file://".len()..])?),
+        _ if url.starts_with("redis://") => Arc::new(redis::Redis::open(url).await?),
+        _ => bail!("No idea how to store things in {url:?}"),
+    };
+    Ok(storage)
+}
+
+fn count(streams: impl IntoIterator<Item = u64>) -> u32 {
+    streams.into_iter().sum::<u64>().try_into().unwrap_or(u32::MAX)
+}
+
+/// A room's history as moved to `new_room_id`, down to its first `max_history` events.
+fn moved(mut history: Vec<Event>, new_room_id: &str, max_history: u64) -> Vec<Event> {
+    if max_history != 0 {
+        history.truncate(max_history.try_into().unwrap_or(usize::MAX));
+    }
+    for event in &mut history {
+        new_room_id.clone_into(&mut event.in_room_id);
+    }
+    history
+}
+
+/// Exercises any storage the same way.
+#[cfg(test)]
+pub(crate) async fn conformance(s: &dyn Storage) {
+    use pb::proto::hypercards::event;
+
+    let e = |room_id: &str, user_id: &str| Event {
+        created_at: 42,
+        by_user_id: user_id.to_owned(),
+        in_room_id: room_id.to_owned(),
+        event: Some(event::Event::UserJoinedTheRoom(true)),
+        origin_server_id: "srv".to_owned(),
+        via_server_ids: vec!["lan".to_owned()],
+    };
+
+    assert_eq!(s.events("r").await.unwrap(), None);
+    assert_eq!(s.screen("r").await.unwrap(), None);
+    assert_eq!(s.members("r").await.unwrap(), None);
+    assert_eq!(s.rooms().await.unwrap(), vec![]);
+
+    assert_eq!(s.join("r", "joe").await.unwrap(), 1);
+    assert_eq!(s.join("r", "joe").await.unwrap(), 2);
+    assert_eq!(s.join("r", "jane").await.unwrap(), 3);
+    assert_eq!(s.members("r").await.unwrap().unwrap(), ["jane", "joe", "joe"]);
+    s.leave("r", "joe").await.unwrap();
+    s.leave("r", "jane").await.unwrap();
+    s.leave("r", "nobody").await.unwrap();
+    assert_eq!(s.members("r").await.unwrap().unwrap(), ["joe"]);
+    assert_eq!(s.screen("r").await.unwrap(), Some(vec![]));
+
+    assert!(s.push_event(&e("r", "joe"), 0).await.unwrap());
+    assert!(s.push_event(&e("q", "jane"), 1).await.unwrap());
+    assert!(!s.push_event(&e("q", "joe"), 1).await.unwrap());
+    assert!(s.push_event(&e("r", "jane"), 2).await.unwrap());
+    assert_eq!(s.events("r").await.unwrap().unwrap(), [e("r", "joe"), e("r", "jane")]);
+    assert_eq!(s.events("q").await.unwrap().unwrap(), [e("q", "jane")]);
+    assert_eq!(s.history_len("r").await.unwrap(), 2);
+    assert_eq!(s.history_len("nowhere").await.unwrap(), 0);
+    assert_eq!(s.members("q").await.unwrap().unwrap(), Vec::<String>::new());
+
+    s.set_screen("s", vec![1, 2, 3]).await.unwrap();
+    assert_eq!(s.screen("s").await.unwrap(), Some(vec![1, 2, 3]));
+    assert_eq!(s.events("s").await.unwrap(), Some(vec![]));
+
+    let rooms = s.rooms().await.unwrap();
+    let summary: Vec<_> =
+        rooms.iter().map(|r| (r.room_id.as_str(), r.streams, r.history_len)).collect();
+    assert_eq!(summary, [("q", 0, 1), ("r", 1, 2), ("s", 0, 0)]);
+    assert!(rooms.iter().all(|r| r.last_active_at > 0));
+
+    s.clear("r").await.unwrap();
+    assert_eq!(s.events("r").await.unwrap(), Some(vec![]));
+    assert_eq!(s.screen("r").await.unwrap(), Some(vec![]));
+    assert_eq!(s.members("r").await.unwrap().unwrap(), ["joe"]);
+    s.clear("s").await.unwrap();
+    assert_eq!(s.screen("s").await.unwrap(), Some(vec![]));
+    s.push_event(&e("r", "joe"), 0).await.unwrap();
+    s.push_event(&e("r", "jane"), 0).await.unwrap();
+
+    // Someone is in there, or it's been active since
+    assert!(!s.remove_idle("r", i64::MAX).await.unwrap());
+    assert!(!s.remove_idle("q", 0).await.unwrap());
+    assert!(s.remove_idle("q", i64::MAX).await.unwrap());
+    assert!(!s.remove_idle("nowhere", i64::MAX).await.unwrap());
+    assert_eq!(s.events("q").await.unwrap(), None);
+
+    s.set_screen("r", vec![4]).await.unwrap();
+    assert!(!s.rename("r", "s", 0).await.unwrap());
+    assert!(!s.rename("nowhere", "p", 0).await.unwrap());
+    assert!(s.rename("r", "p", 1).await.unwrap());
+    assert_eq!(s.events("r").await.unwrap(), None);
+    assert_eq!(s.members("r").await.unwrap(), None);
+    assert_eq!(s.events("p").await.unwrap().unwrap(), [e("p", "joe")]);
+    assert_eq!(s.history_len("p").await.unwrap(), 1);
+    assert_eq!(s.screen("p").await.unwrap(), Some(vec![4]));
+    assert_eq!(s.members("p").await.unwrap().unwrap(), Vec::<String>::new());
+    assert!(s.push_event(&e("p", "jane"), 0).await.unwrap());
+    assert!(s.rename("p", "r", 0).await.unwrap());
+    assert_eq!(s.events("r").await.unwrap().unwrap(), [e("r", "joe"), e("r", "jane")]);
+    assert_eq!(s.join("r", "joe").await.unwrap(), 1);
+
+    s.remove("r").await.unwrap();
+    s.remove("nowhere").await.unwrap();
+    assert_eq!(s.events("r").await.unwrap(), None);
+    assert_eq!(s.members("r").await.unwrap(), None);
+    assert_eq!(s.rooms().await.unwrap().len(), 1);
+    assert_eq!(s.join("r", "joe").await.unwrap(), 1);
+    assert_eq!(s.events("r").await.unwrap(), Some(vec![]));
+}
@@ -0,0 +1,345 @@
+use anyhow::Result;
+use pb::proto::hypercards::Event;
+use prost::Message;
+use redis::{aio::MultiplexedConnection, cmd, pipe, Client};
+
+use super::{count, moved, Stats, Storage};
+use crate::rooms::now;
+
+/// Same key & expiry as the Go servers use, so their `http-server` shows our screens.
+fn screen_key(room_id: &str) -> String {
+    format!("ScreenSharing-{room_id}")
+}
+const SCREEN_EXPIRE_SECS: u64 = 24 * 60 * 60;
+
+/// Sorted set of room IDs, scored by last activity
+const ROOMS_KEY: &str = "Rooms";
+fn events_key(room_id: &str) -> String {
+    format!("Events-{room_id}")
+}
+fn members_key(room_id: &str) -> String {
+    format!("Members-{room_id}")
+}
+
+/// Nanoseconds don't fit in the 53 bits of a score's mantissa: milliseconds do.
+const NANOS_PER_SCORE: i64 = 1_000_000;
+fn score() -> i64 {
+    now() / NANOS_PER_SCORE
+}
+
+/// Appends to a room's history unless it is full, all at once.
+/// KEYS: events, rooms. ARGV: max history (0 for no limit), score, room ID, event.
+const PUSH_EVENT: &str = r"
+local max = tonumber(ARGV[1])
+if max > 0 and redis.call('LLEN', KEYS[1]) >= max then
+  return 0
+end
+redis.call('ZADD', KEYS[2], ARGV[2], ARGV[3])
+redis.call('RPUSH', KEYS[1], ARGV[4])
+return 1
+";
+
+/// Forgets one of a member's streams, if any, all at once.
+/// KEYS: members, rooms. ARGV: user ID, score, room ID.
+const LEAVE: &str = r"
+if redis.call('HEXISTS', KEYS[1], ARGV[1]) == 0 then
+  return 0
+end
+if redis.call('HINCRBY', KEYS[1], ARGV[1], -1) <= 0 then
+  redis.call('HDEL', KEYS[1], ARGV[1])
+end
+redis.call('ZADD', KEYS[2], 'XX', ARGV[2], ARGV[3])
+return 1
+";
+
+/// Forgets a room nobody is in and last active at the given score or before, all at once.
+/// KEYS: rooms, events, members, screen. ARGV: room ID, score.
+const REMOVE_IDLE: &str = r"
+local score = redis.call('ZSCORE', KEYS[1], ARGV[1])
+if not score or tonumber(score) > tonumber(ARGV[2]) or redis.call('HLEN', KEYS[3]) > 0 then
+  return 0
+end
+redis.call('ZREM', KEYS[1], ARGV[1])
+redis.call('DEL', KEYS[2], KEYS[3], KEYS[4])
+return 1
+";
+
+/// Moves a room's screenshot and (already moved) history, forgetting everything else about it,
+/// all at once. Returns 0 if the room doesn't exist or the new one does, -1 if the history
+/// changed since it was read.
+/// KEYS: rooms, events, members, screen, new events, new screen.
+/// ARGV: room ID, new room ID, score, history length & last event as read, new history...
+const RENAME: &str = r"
+if not redis.call('ZSCORE', KEYS[1], ARGV[1]) or redis.call('ZSCORE', KEYS[1], ARGV[2]) then
+  return 0
+end
+local last = redis.call('LINDEX', KEYS[2], -1) or ''
+if redis.call('LLEN', KEYS[2]) ~= tonumber(ARGV[4]) or last ~= ARGV[5] then
+  return -1
+end
+redis.call('ZREM', KEYS[1], ARGV[1])
+redis.call('ZADD', KEYS[1], ARGV[3], ARGV[2])
+redis.call('DEL', KEYS[2], KEYS[3], KEYS[5], KEYS[6])
+for i = 6, #ARGV do
+  redis.call('RPUSH', KEYS[5], ARGV[i])
+end
+if redis.call('EXISTS', KEYS[4]) == 1 then
+  redis.call('RENAME', KEYS[4], KEYS[6])
+end
+return 1
+";
+
+pub(crate) struct Redis {
+    conn: MultiplexedConnection,
+}
+
+impl Redis {
+    pub(crate) async fn open(url: &str) -> Result<Self> {
+        let conn = Client::open(url)?.get_multiplexed_async_connection().await?;
+        Ok(Self { conn })
+    }
+
+    async fn exists(&self, room_id: &str) -> Result<bool> {
+        let score: Option<f64> =
+            cmd("ZSCORE").arg(ROOMS_KEY).arg(room_id).query_async(&mut self.conn.clone()).await?;
+        Ok(score.is_some())
+    }
+
+    async fn streams(&self, room_id: &str) -> Result<Vec<(String, u64)>> {
+        let streams: Vec<(String, u64)> =
+            cmd("HGETALL").arg(members_key(room_id)).query_async(&mut self.conn.clone()).await?;
+        Ok(streams)
+    }
+}
+
+#[tonic::async_trait]
+impl Storage for Redis {
+    async fn push_event(&self, event: &Event, max_history: u64) -> Result<bool> {
+        let room_id = &event.in_room_id;
+        let pushed: bool = cmd("EVAL")
+            .arg(PUSH_EVENT)
+            .arg(2)
+            .arg(events_key(room_id))
+            .arg(ROOMS_KEY)
+            .arg(max_history)
+            .arg(score())
+            .arg(room_id)
+            .arg(event.encode_to_vec())
+            .query_async(&mut self.conn.clone())
+            .await?;
+        Ok(pushed)
+    }
+
+    async fn history_len(&self, room_id: &str) -> Result<u64> {
+        let n: u64 =
+            cmd("LLEN").arg(events_key(room_id)).query_async(&mut self.conn.clone()).await?;
+        Ok(n)
+    }
+
+    async fn events(&self, room_id: &str) -> Result<Option<Vec<Event>>> {
+        if !self.exists(room_id).await? {
+            return Ok(None);
+        }
+        let data: Vec<Vec<u8>> = cmd("LRANGE")
+            .arg(events_key(room_id))
+            .arg(0)
+            .arg(-1)
+            .query_async(&mut self.conn.clone())
+            .await?;
+        let events = data.iter().map(|e| Event::decode(e.as_slice())).collect::<Result<_, _>>()?;
+        Ok(Some(events))
+    }
+
+    async fn set_screen(&self, room_id: &str, png: Vec<u8>) -> Result<()> {
+        pipe()
+            .atomic()
+            .cmd("ZADD")
+            .arg(ROOMS_KEY)
+            .arg(score())
+            .arg(room_id)
+            .ignore()
+            .cmd("SET")
+            .arg(screen_key(room_id))
+            .arg(png)
+            .arg("EX")
+            .arg(SCREEN_EXPIRE_SECS)
+            .ignore()
+            .query_async::<()>(&mut self.conn.clone())
+            .await?;
+        Ok(())
+    }
+
+    async fn screen(&self, room_id: &str) -> Result<Option<Vec<u8>>> {
+        if !self.exists(room_id).await? {
+            return Ok(None);
+        }
+        let png: Option<Vec<u8>> =
+            cmd("GET").arg(screen_key(room_id)).query_async(&mut self.conn.clone()).await?;
+        Ok(Some(png.unwrap_or_default()))
+    }
+
+    async fn join(&self, room_id: &str, user_id: &str) -> Result<u32> {
+        pipe()
+            .atomic()
+            .cmd("ZADD")
+            .arg(ROOMS_KEY)
+            .arg(score())
+            .arg(room_id)
+            .ignore()
+            .cmd("HINCRBY")
+            .arg(members_key(room_id))
+            .arg(user_id)
+            .arg(1)
+            .ignore()
+            .query_async::<()>(&mut self.conn.clone())
+            .await?;
+        Ok(count(self.streams(room_id).await?.into_iter().map(|(_, n)| n)))
+    }
+
+    async fn leave(&self, room_id: &str, user_id: &str) -> Result<()> {
+        cmd("EVAL")
+            .arg(LEAVE)
+            .arg(2)
+            .arg(members_key(room_id))
+            .arg(ROOMS_KEY)
+            .arg(user_id)
+            .arg(score())
+            .arg(room_id)
+            .query_async::<()>(&mut self.conn.clone())
+            .await?;
+        Ok(())
+    }
+
+    async fn members(&self, room_id: &str) -> Result<Option<Vec<String>>> {
+        if !self.exists(room_id).await? {
+            return Ok(None);
+        }
+        let mut members: Vec<_> = self
+            .streams(room_id)
+            .await?
+            .into_iter()
+            .flat_map(|(user_id, n)| std::iter::repeat_n(user_id, n as usize))
+            .collect();
+        members.sort();
+        Ok(Some(members))
+    }
+
+    async fn rooms(&self) -> Result<Vec<Stats>> {
+        let mut conn = self.conn.clone();
+        let mut scored: Vec<(String, f64)> = cmd("ZRANGE")
+            .arg(ROOMS_KEY)
+            .arg(0)
+            .arg(-1)
+            .arg("WITHSCORES")
+            .query_async(&mut conn)
+            .await?;
+        scored.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let mut rooms = Vec::with_capacity(scored.len());
+        for (room_id, last_active_at) in scored {
+            let streams = count(self.streams(&room_id).await?.into_iter().map(|(_, n)| n));
+            let history_len: u64 =
+                cmd("LLEN").arg(events_key(&room_id)).query_async(&mut conn).await?;
+            let last_active_at = (last_active_at as i64).saturating_mul(NANOS_PER_SCORE);
+            rooms.push(Stats { room_id, streams, history_len, last_active_at });
+        }
+        Ok(rooms)
+    }
+
+    async fn clear(&self, room_id: &str) -> Result<()> {
+        pipe()
+            .atomic()
+            .cmd("ZADD")
+            .arg(ROOMS_KEY)
+            .arg(score())
+            .arg(room_id)
+            .ignore()
+            .cmd("DEL")
+            .arg(events_key(room_id))
+            .arg(screen_key(room_id))
+            .ignore()
+            .query_async::<()>(&mut self.conn.clone())
+            .await?;
+        Ok(())
+    }
+
+    async fn rename(&self, room_id: &str, new_room_id: &str, max_history: u64) -> Result<bool> {
+        loop {
+            let history: Vec<Vec<u8>> = cmd("LRANGE")
+                .arg(events_key(room_id))
+                .arg(0)
+                .arg(-1)
+                .query_async(&mut self.conn.clone())
+                .await?;
+            let events = history
+                .iter()
+                .map(|e| Event::decode(e.as_slice()))
+                .collect::<Result<Vec<_>, _>>()?;
+            let new_history: Vec<_> =
+                moved(events, new_room_id, max_history).iter().map(Event::encode_to_vec).collect();
+            let renamed: i8 = cmd("EVAL")
+                .arg(RENAME)
+                .arg(6)
+                .arg(ROOMS_KEY)
+                .arg(events_key(room_id))
+                .arg(members_key(room_id))
+                .arg(screen_key(room_id))
+                .arg(events_key(new_room_id))
+                .arg(screen_key(new_room_id))
+                .arg(room_id)
+                .arg(new_room_id)
+                .arg(score())
+                .arg(history.len())
+                .arg(history.last().map_or(&[][..], Vec::as_slice))
+                .arg(new_history)
+                .query_async(&mut self.conn.clone())
+                .await?;
+            // Otherwise someone published in the meantime: read it all again
+            if renamed >= 0 {
+                return Ok(renamed == 1);
+            }
+        }
+    }
+
+    async fn remove(&self, room_id: &str) -> Result<()> {
+        pipe()
+            .atomic()
+            .cmd("ZREM")
+            .arg(ROOMS_KEY)
+            .arg(room_id)
+            .ignore()
+            .cmd("DEL")
+            .arg(events_key(room_id))
+            .arg(members_key(room_id))
+            .arg(screen_key(room_id))
+            .ignore()
+            .query_async::<()>(&mut self.conn.clone())
+            .await?;
+        Ok(())
+    }
+
+    async fn remove_idle(&self, room_id: &str, idle_since: i64) -> Result<bool> {
+        let removed: bool = cmd("EVAL")
+            .arg(REMOVE_IDLE)
+            .arg(4)
+            .arg(ROOMS_KEY)
+            .arg(events_key(room_id))
+            .arg(members_key(room_id))
+            .arg(screen_key(room_id))
+            .arg(room_id)
+            .arg(idle_since / NANOS_PER_SCORE)
+            .query_async(&mut self.conn.clone())
+            .await?;
+        Ok(removed)
+    }
+}
+
+/// Needs a throwaway database, e.g. `SRV_TEST_REDIS=redis://127.0.0.1:6379/15`: it gets flushed!
+/// Run with `cargo test -p srv -- --ignored`, see README.
+#[tokio::test]
+#[ignore = "needs a throwaway Redis database in SRV_TEST_REDIS"]
+async fn conforms() {
+    let url = std::env::var("SRV_TEST_REDIS").expect("SRV_TEST_REDIS");
+    let redis = Redis::open(&url).await.unwrap();
+    cmd("FLUSHDB").query_async::<()>(&mut redis.conn.clone()).await.unwrap();
+    super::conformance(&redis).await;
+}
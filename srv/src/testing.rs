@@ -0,0 +1,38 @@
+//! What the services' tests send them
+//!
+use pb::proto::hypercards::{drawing::Color, event, Drawing, Event, RecvEventsReq, SendEventReq};
+use tonic::Request;
+
+/// `msg`, as sent by `user_id`.
+pub(crate) fn user<T>(user_id: &str, msg: T) -> Request<T> {
+    let mut req = Request::new(msg);
+    req.metadata_mut().insert("x-user", user_id.parse().unwrap());
+    req
+}
+
+/// A short black line.
+pub(crate) fn drawing() -> Drawing {
+    Drawing {
+        xs: vec![1., 2., 3.],
+        ys: vec![1., 2., 3.],
+        pressures: vec![2000; 3],
+        widths: vec![2; 3],
+        color: Color::Black.into(),
+    }
+}
+
+/// Sends [`drawing`] to these rooms.
+pub(crate) fn send(room_ids: &[&str]) -> SendEventReq {
+    send_with(event::Event::Drawing(drawing()), room_ids)
+}
+
+pub(crate) fn send_with(event: event::Event, room_ids: &[&str]) -> SendEventReq {
+    SendEventReq {
+        event: Some(Event { event: Some(event), ..Default::default() }),
+        room_ids: room_ids.iter().map(|&r| r.to_owned()).collect(),
+    }
+}
+
+pub(crate) fn recv(room_id: &str) -> RecvEventsReq {
+    RecvEventsReq { room_id: room_id.to_owned(), ..Default::default() }
+}
@@ -0,0 +1,193 @@
+//! How many events users may send, and rooms may receive, per second
+//!
+//! Each user and each room gets a token bucket holding a second's worth of events:
+//! bursts are fine, sustained floods get `RESOURCE_EXHAUSTED` along with a
+//! `retry-after-ms` header telling when to try again.
+//!
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use tonic::{metadata::MetadataValue, Status};
+
+pub(crate) const RETRY_AFTER_HEADER: &str = "retry-after-ms";
+
+/// How often full buckets are forgotten: they are as good as new ones.
+const PRUNE_EVERY: Duration = Duration::from_secs(60);
+
+#[derive(clap::Args, Debug, Clone)]
+#[group(id = "throttle")]
+pub(crate) struct Args {
+    /// Most events a user may send per second, 0 for no limit
+    #[arg(long, env = "SRV_USER_EVENTS_PER_SEC", default_value_t = 50)]
+    pub(crate) user_events_per_sec: u32,
+
+    /// Most events a room may receive per second, 0 for no limit
+    #[arg(long, env = "SRV_ROOM_EVENTS_PER_SEC", default_value_t = 200)]
+    pub(crate) room_events_per_sec: u32,
+}
+
+/// Per user and per room limits. The default limits nothing.
+#[derive(Debug, Default)]
+pub(crate) struct Throttle {
+    users: Buckets,
+    rooms: Buckets,
+}
+
+impl Throttle {
+    pub(crate) fn new(args: Args) -> Self {
+        Self {
+            users: Buckets::new(args.user_events_per_sec),
+            rooms: Buckets::new(args.room_events_per_sec),
+        }
+    }
+
+    /// Takes one event from `user_id`'s allowance and from each room's, or none at all.
+    /// Call it once the event is known to be accepted otherwise, and [`Self::refund`]
+    /// what then fails to be published, so rejects cost nothing.
+    pub(crate) fn check(&self, user_id: &str, room_ids: &[String]) -> Result<(), Status> {
+        let now = Instant::now();
+        let mut users = self.users.buckets.lock().unwrap();
+        let mut rooms = self.rooms.buckets.lock().unwrap();
+
+        let mut wait = self.users.wait(&mut users, user_id, now).map(|w| (w, "you are"));
+        for room_id in room_ids {
+            wait = wait.max(self.rooms.wait(&mut rooms, room_id, now).map(|w| (w, "this room is")));
+        }
+        if let Some((wait, who)) = wait {
+            let mut status =
+                Status::resource_exhausted(format!("{who} sending too many events, slow down"));
+            let ms = MetadataValue::from(u64::try_from(wait.as_millis()).unwrap_or(u64::MAX));
+            status.metadata_mut().insert(RETRY_AFTER_HEADER, ms);
+            return Err(status);
+        }
+
+        self.users.take(&mut users, user_id);
+        for room_id in room_ids {
+            self.rooms.take(&mut rooms, room_id);
+        }
+        Ok(())
+    }
+
+    /// Gives back what [`Self::check`] took from `user_id`, if given, and from these rooms.
+    pub(crate) fn refund(&self, user_id: Option<&str>, room_ids: &[String]) {
+        let mut users = self.users.buckets.lock().unwrap();
+        let mut rooms = self.rooms.buckets.lock().unwrap();
+        if let Some(user_id) = user_id {
+            self.users.give(&mut users, user_id);
+        }
+        for room_id in room_ids {
+            self.rooms.give(&mut rooms, room_id);
+        }
+    }
+
+    /// Forgets the buckets that refilled completely.
+    fn prune(&self, now: Instant) {
+        self.users.prune(now);
+        self.rooms.prune(now);
+    }
+}
+
+/// Forgets full buckets, every so often.
+pub(crate) async fn prune(throttle: Arc<Throttle>) {
+    let mut every = tokio::time::interval(PRUNE_EVERY);
+    loop {
+        every.tick().await;
+        throttle.prune(Instant::now());
+    }
+}
+
+/// Token buckets, by key.
+#[derive(Debug, Default)]
+struct Buckets {
+    per_sec: f64,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    at: Instant,
+}
+
+impl Buckets {
+    fn new(per_sec: u32) -> Self {
+        Self { per_sec: per_sec.into(), buckets: Default::default() }
+    }
+
+    /// Refills `key`'s bucket as of `now`, returning how long until it holds a token.
+    fn wait(
+        &self,
+        buckets: &mut HashMap<String, Bucket>,
+        key: &str,
+        now: Instant,
+    ) -> Option<Duration> {
+        if self.per_sec == 0. {
+            return None;
+        }
+        let bucket =
+            buckets.entry(key.to_owned()).or_insert(Bucket { tokens: self.per_sec, at: now });
+        *bucket = Bucket { tokens: self.refilled(*bucket, now), at: now };
+        (bucket.tokens < 1.).then(|| Duration::from_secs_f64((1. - bucket.tokens) / self.per_sec))
+    }
+
+    fn take(&self, buckets: &mut HashMap<String, Bucket>, key: &str) {
+        if let Some(bucket) = buckets.get_mut(key) {
+            bucket.tokens -= 1.;
+        }
+    }
+
+    fn give(&self, buckets: &mut HashMap<String, Bucket>, key: &str) {
+        if let Some(bucket) = buckets.get_mut(key) {
+            bucket.tokens = (bucket.tokens + 1.).min(self.per_sec);
+        }
+    }
+
+    fn prune(&self, now: Instant) {
+        self.buckets.lock().unwrap().retain(|_, b| self.refilled(*b, now) < self.per_sec);
+    }
+
+    fn refilled(&self, b: Bucket, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(b.at).as_secs_f64();
+        (b.tokens + elapsed * self.per_sec).min(self.per_sec)
+    }
+}
+
+#[test]
+fn throttles_users_and_rooms() {
+    let throttle = Throttle::new(Args { user_events_per_sec: 2, room_events_per_sec: 3 });
+    let r = ["r".to_owned()];
+    throttle.check("joe", &r).unwrap();
+    throttle.check("joe", &r).unwrap();
+    let status = throttle.check("joe", &r).unwrap_err();
+    assert_eq!(status.code(), tonic::Code::ResourceExhausted);
+    let ms: u64 =
+        status.metadata().get(RETRY_AFTER_HEADER).unwrap().to_str().unwrap().parse().unwrap();
+    assert!(ms > 0 && ms <= 500, "{ms}");
+
+    // Joe's third attempt took nothing from the room
+    throttle.check("jane", &r).unwrap();
+    assert!(throttle.check("jane", &r).is_err());
+    throttle.check("jane", &["q".to_owned()]).unwrap();
+
+    // Refunds make up for what was taken
+    throttle.refund(Some("joe"), &r);
+    throttle.check("joe", &["q".to_owned()]).unwrap();
+    assert!(throttle.check("joe", &["q".to_owned()]).is_err());
+    throttle.refund(Some("nobody"), &["nowhere".to_owned()]);
+
+    // Only partly refilled buckets are worth remembering
+    throttle.check("jim", &["p".to_owned()]).unwrap();
+    throttle.prune(Instant::now() + Duration::from_secs(1));
+    assert_eq!(throttle.users.buckets.lock().unwrap().len(), 0);
+    throttle.check("joe", &r).unwrap();
+    throttle.prune(Instant::now());
+    assert_eq!(throttle.users.buckets.lock().unwrap().len(), 1);
+
+    let unlimited = Throttle::default();
+    for _ in 0..1000 {
+        unlimited.check("joe", &r).unwrap();
+    }
+}
@@ -0,0 +1,162 @@
+//! Animate how a room's drawings came to be
+//!
+//! Frames are painted with the tablets' own rasterizer: the first one is blank
+//! and the last one shows every drawing.
+//!
+use std::{borrow::Cow, slice::Chunks};
+
+use anyhow::Result;
+use marauder::raster::Canvas;
+use pb::proto::hypercards::Drawing;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Settings {
+    pub(crate) strokes_per_frame: usize,
+    pub(crate) frame_delay_ms: u16,
+    pub(crate) scale: f32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self { strokes_per_frame: 1, frame_delay_ms: 100, scale: 0.5 }
+    }
+}
+
+struct Frames<'a> {
+    canvas: Canvas,
+    chunks: Chunks<'a, Drawing>,
+    scale: f32,
+    started: bool,
+}
+
+impl<'a> Frames<'a> {
+    fn new(ds: &'a [Drawing], settings: &Settings) -> Self {
+        let chunks = ds.chunks(settings.strokes_per_frame.max(1));
+        Self { canvas: Canvas::default(), chunks, scale: settings.scale, started: false }
+    }
+
+    fn len(&self) -> usize {
+        1 + self.chunks.len()
+    }
+
+    fn dimensions(&self) -> (u32, u32) {
+        self.canvas.scaled(self.scale).dimensions()
+    }
+}
+
+impl Iterator for Frames<'_> {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.started {
+            for d in self.chunks.next()? {
+                self.canvas.paint(d);
+            }
+        }
+        self.started = true;
+        Some(self.canvas.scaled(self.scale).into_raw())
+    }
+}
+
+pub(crate) fn gif(ds: &[Drawing], settings: &Settings) -> Result<Vec<u8>> {
+    let frames = Frames::new(ds, settings);
+    let (w, h) = frames.dimensions();
+    let (w, h) = (u16::try_from(w)?, u16::try_from(h)?);
+
+    // Pixels' luma doubles as their index in this palette
+    let grays: Vec<u8> = (0..=u8::MAX).flat_map(|l| [l, l, l]).collect();
+    let mut data = vec![];
+    {
+        let mut encoder = gif::Encoder::new(&mut data, w, h, &grays)?;
+        encoder.set_repeat(gif::Repeat::Infinite)?;
+        for buffer in frames {
+            let frame = gif::Frame {
+                width: w,
+                height: h,
+                delay: settings.frame_delay_ms.div_ceil(10), // in centiseconds
+                buffer: Cow::Owned(buffer),
+                ..Default::default()
+            };
+            encoder.write_frame(&frame)?;
+        }
+    }
+    Ok(data)
+}
+
+pub(crate) fn apng(ds: &[Drawing], settings: &Settings) -> Result<Vec<u8>> {
+    let frames = Frames::new(ds, settings);
+    let (w, h) = frames.dimensions();
+
+    let mut data = vec![];
+    {
+        let mut encoder = png::Encoder::new(&mut data, w, h);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_animated(u32::try_from(frames.len())?, 0)?;
+        encoder.set_frame_delay(settings.frame_delay_ms, 1000)?;
+        let mut writer = encoder.write_header()?;
+        for buffer in frames {
+            writer.write_image_data(&buffer)?;
+        }
+        writer.finish()?;
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod test {
+    use pb::proto::hypercards::drawing::Color;
+
+    use super::*;
+
+    fn ds(n: usize) -> Vec<Drawing> {
+        (0..n)
+            .map(|i| Drawing {
+                xs: vec![100., 200., 300.],
+                ys: vec![100. + 50. * i as f32; 3],
+                pressures: vec![2048; 3],
+                widths: vec![8; 3],
+                color: Color::Black.into(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn one_frame_per_batch_of_strokes() {
+        let settings = Settings { strokes_per_frame: 2, ..Default::default() };
+        let frames = Frames::new(&[], &settings);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames.dimensions(), (702, 936));
+
+        let ds = ds(5);
+        let frames = Frames::new(&ds, &settings);
+        assert_eq!(frames.len(), 4);
+        let inked: Vec<usize> =
+            frames.map(|f| f.into_iter().filter(|&l| l < u8::MAX).count()).collect();
+        assert_eq!(inked.len(), 4);
+        assert_eq!(inked[0], 0);
+        assert!(inked.windows(2).all(|w| w[0] < w[1]), "{inked:?}");
+    }
+
+    #[test]
+    fn encodes_animations() {
+        let settings = Settings { scale: 0.25, ..Default::default() };
+
+        let data = gif(&ds(3), &settings).unwrap();
+        assert!(data.starts_with(b"GIF89a"));
+        let mut decoder = gif::DecodeOptions::new().read_info(data.as_slice()).unwrap();
+        assert_eq!((decoder.width(), decoder.height()), (351, 468));
+        let mut frames = 0;
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            assert_eq!(frame.delay, 10);
+            frames += 1;
+        }
+        assert_eq!(frames, 4);
+
+        let data = apng(&ds(3), &settings).unwrap();
+        let reader = png::Decoder::new(std::io::Cursor::new(data)).read_info().unwrap();
+        let info = reader.info();
+        assert_eq!((info.width, info.height), (351, 468));
+        assert_eq!(info.animation_control.unwrap().num_frames, 4);
+    }
+}
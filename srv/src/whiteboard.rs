@@ -0,0 +1,467 @@
+use std::{collections::HashSet, pin::Pin, sync::Arc, time::Instant};
+
+use log::{debug, info, warn};
+use pb::{
+    ingest::{MAX_PRESSURE, MAX_WIDTH},
+    proto::hypercards::{
+        drawing::Color, event, whiteboard_server::Whiteboard, Capabilities, Event,
+        ListRoomMembersRep, ListRoomMembersReq, ListRoomsRep, ListRoomsReq, RecvEventsReq, Room,
+        RoomMember, SendEventRep, SendEventReq,
+    },
+};
+use prost::Message;
+use tokio::sync::broadcast::error::RecvError;
+use tokio_stream::Stream;
+use tonic::{Request, Response, Status};
+
+use crate::{
+    metrics::Metrics,
+    rooms::{now, status_event, Rooms},
+    server::{ntui, user_id, Server, SERVER_ID_HEADER},
+};
+
+/// Sends a "user left" event when a `RecvEvents` stream gets dropped.
+struct Membership {
+    rooms: Rooms,
+    metrics: Arc<Metrics>,
+    room_id: String,
+    user_id: String,
+}
+
+impl Drop for Membership {
+    fn drop(&mut self) {
+        self.metrics.streams.dec();
+        let Self { rooms, room_id, user_id, .. } = self;
+        let (rooms, room_id, user_id) = (rooms.clone(), room_id.clone(), user_id.clone());
+        tokio::spawn(async move {
+            if let Err(e) = rooms.leave(&room_id, &user_id).await {
+                warn!("[recv_events] {user_id:?} could not leave {room_id:?}: {e}");
+            }
+        });
+    }
+}
+
+#[tonic::async_trait]
+impl Whiteboard for Server {
+    type RecvEventsStream = Pin<Box<dyn Stream<Item = Result<Event, Status>> + Send>>;
+
+    async fn recv_events(
+        &self,
+        req: Request<RecvEventsReq>,
+    ) -> Result<Response<Self::RecvEventsStream>, Status> {
+        let user_id = user_id(&req)?;
+        let RecvEventsReq { room_id, capabilities } = req.into_inner();
+        ntui(&room_id)?;
+        self.moderation.check_user(&user_id)?;
+        let peer = Capabilities::of_peer(capabilities);
+        info!("[recv_events] {user_id:?} listening on {room_id:?} (v{})", peer.protocol_version);
+
+        let mut kicks = self.moderation.kicks();
+        let (mut rx, count) = self.rooms.join(&room_id, &user_id).await?;
+        self.metrics.streams.inc();
+        let metrics = self.metrics.clone();
+        let membership = Membership { rooms: self.rooms.clone(), metrics, room_id, user_id };
+
+        let stream = async_stream::stream! {
+            let Membership { room_id, user_id, metrics, .. } = &membership;
+            let count = status_event(room_id, user_id, event::Event::UsersInTheRoom(count));
+            if peer.accepts(&count) {
+                yield Ok(count);
+            }
+
+            loop {
+                let received = tokio::select! {
+                    biased; // Kicks first, however busy the room
+                    kick = kicks.recv() => match kick {
+                        Ok(kick) if kick.applies(room_id, user_id) => Err(kick),
+                        Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                        Err(RecvError::Closed) => break,
+                    },
+                    event = rx.recv() => Ok(event),
+                };
+                match received {
+                    Err(kick) => {
+                        info!("[recv_events] {user_id:?} kicked from {room_id:?}: {}", kick.reason);
+                        yield Err(kick.status());
+                        break;
+                    }
+                    Ok(Ok(event)) if event.by_user_id == *user_id => debug!("[recv_events] not FWDing to self"),
+                    Ok(Ok(event)) if !peer.accepts(&event) => debug!("[recv_events] not FWDing what {user_id:?} doesn't support"),
+                    Ok(Ok(event)) => {
+                        metrics.events_out.inc();
+                        metrics.bytes_out.inc_by(event.encoded_len() as u64);
+                        yield Ok(event)
+                    }
+                    Ok(Err(RecvError::Lagged(n))) => warn!("[recv_events] {user_id:?} missed {n} events"),
+                    Ok(Err(RecvError::Closed)) => break,
+                }
+            }
+        };
+        let mut rep = Response::new(Box::pin(stream) as Self::RecvEventsStream);
+        if let Ok(id) = self.id.parse() {
+            rep.metadata_mut().insert(SERVER_ID_HEADER, id);
+        }
+        Capabilities::ours().to_header(rep.metadata_mut());
+        Ok(rep)
+    }
+
+    async fn send_event(
+        &self,
+        req: Request<SendEventReq>,
+    ) -> Result<Response<SendEventRep>, Status> {
+        let start = Instant::now();
+        let user_id = user_id(&req)?;
+        let req = req.into_inner();
+        self.metrics.bytes_in.inc_by(req.encoded_len() as u64);
+        validate_send_event(&req)?;
+        self.moderation.check_user(&user_id)?;
+
+        for room_id in &req.room_ids {
+            self.moderation.check_room(room_id)?;
+            self.rooms.check_history(room_id).await?;
+        }
+
+        let SendEventReq { event, room_ids } = req;
+        let Event { event, origin_server_id, via_server_ids, .. } = event.expect("validated");
+        if *origin_server_id == *self.id {
+            debug!("[send_event] dropping {user_id:?}'s event: it was published here first");
+            return Ok(Response::new(SendEventRep {}));
+        }
+        if via_server_ids.iter().any(|id| **id == *self.id) {
+            debug!("[send_event] dropping {user_id:?}'s event: it went through here already");
+            return Ok(Response::new(SendEventRep {}));
+        }
+        self.throttle.check(&user_id, &room_ids)?;
+        let origin_server_id = match origin_server_id.as_str() {
+            "" => self.id.to_string(),
+            _ => origin_server_id,
+        };
+
+        let created_at = now();
+        for (i, room_id) in room_ids.iter().enumerate() {
+            debug!("[send_event] {user_id:?} publishing to {room_id:?}");
+            let event = Event {
+                created_at,
+                by_user_id: user_id.clone(),
+                in_room_id: room_id.clone(),
+                event: event.clone(),
+                origin_server_id: origin_server_id.clone(),
+                via_server_ids: via_server_ids.clone(),
+            };
+            if let Err(status) = self.rooms.publish(event).await {
+                // Others may have filled the room up since its history was checked
+                self.throttle.refund((i == 0).then_some(user_id.as_str()), &room_ids[i..]);
+                return Err(status);
+            }
+            self.metrics.events_in.inc();
+        }
+        self.metrics.send_event_seconds.observe(start.elapsed().as_secs_f64());
+        Ok(Response::new(SendEventRep {}))
+    }
+
+    async fn list_rooms(
+        &self,
+        req: Request<ListRoomsReq>,
+    ) -> Result<Response<ListRoomsRep>, Status> {
+        let user_id = user_id(&req)?;
+        let stats = self.rooms.stats().await?;
+        let events = stats
+            .iter()
+            .map(|s| status_event(&s.room_id, &user_id, event::Event::UsersInTheRoom(s.streams)))
+            .collect();
+        let rooms = stats
+            .into_iter()
+            .map(|s| Room {
+                settings: Some(self.rooms.policy().settings(&s.room_id)),
+                room_id: s.room_id,
+                users_in_the_room: s.streams,
+                history_len: s.history_len,
+                last_active_at: s.last_active_at,
+            })
+            .collect();
+        Ok(Response::new(ListRoomsRep { events, rooms }))
+    }
+
+    async fn list_room_members(
+        &self,
+        req: Request<ListRoomMembersReq>,
+    ) -> Result<Response<ListRoomMembersRep>, Status> {
+        let _ = user_id(&req)?;
+        let ListRoomMembersReq { room_id } = req.into_inner();
+        ntui(&room_id)?;
+        let members = self.rooms.members(&room_id).await?.unwrap_or_default();
+        let members = members.into_iter().map(|_| RoomMember {}).collect();
+        Ok(Response::new(ListRoomMembersRep { members }))
+    }
+}
+
+/// Drawings larger than this are better sent in pieces.
+const MAX_POINTS: usize = 10_000;
+/// Farther than any display, in either direction.
+const MAX_COORD: f32 = 16_384.;
+const MAX_ROOMS_PER_EVENT: usize = 16;
+/// Bridges an event may cross.
+const MAX_HOPS: usize = 16;
+
+fn validate_send_event(req: &SendEventReq) -> Result<(), Status> {
+    let bad = |why: &str| Err(Status::invalid_argument(why.to_owned()));
+
+    let Some(event) = &req.event else { return bad("missing event") };
+    if event.created_at != 0 || !event.by_user_id.is_empty() || !event.in_room_id.is_empty() {
+        return bad("created_at, by_user_id and in_room_id are set by the server");
+    }
+    if !event.origin_server_id.is_empty() {
+        ntui(&event.origin_server_id)?;
+    }
+    if event.via_server_ids.len() > MAX_HOPS {
+        return bad(&format!("events may go through at most {MAX_HOPS} servers"));
+    }
+    for id in &event.via_server_ids {
+        ntui(id)?;
+    }
+    match &event.event {
+        None => return bad("empty event"),
+        Some(event::Event::Drawing(drawing)) => {
+            if drawing.color() == Color::Invisible {
+                return bad("invisible drawing");
+            }
+            let n = drawing.xs.len();
+            if n == 0
+                || n != drawing.ys.len()
+                || n != drawing.pressures.len()
+                || n != drawing.widths.len()
+            {
+                return bad("drawing coordinates must be non-empty and of equal lengths");
+            }
+            if n > MAX_POINTS {
+                return bad(&format!("drawings have at most {MAX_POINTS} points"));
+            }
+            let coord = |c: &f32| c.is_finite() && c.abs() <= MAX_COORD;
+            if !drawing.xs.iter().chain(&drawing.ys).all(coord) {
+                return bad(&format!("drawing coordinates must be within ±{MAX_COORD}"));
+            }
+            if !drawing.pressures.iter().all(|p| (0..=MAX_PRESSURE).contains(p)) {
+                return bad(&format!("drawing pressures must be within 0..={MAX_PRESSURE}"));
+            }
+            if drawing.widths.iter().any(|w| *w > MAX_WIDTH) {
+                return bad(&format!("drawing widths must be at most {MAX_WIDTH}"));
+            }
+        }
+        // Disallow status events
+        Some(
+            event::Event::UserLeftTheRoom(_)
+            | event::Event::UserJoinedTheRoom(_)
+            | event::Event::UsersInTheRoom(_),
+        ) => return bad("status events are sent by the server"),
+    }
+
+    if req.room_ids.len() > MAX_ROOMS_PER_EVENT {
+        return bad(&format!("events go to at most {MAX_ROOMS_PER_EVENT} rooms"));
+    }
+    if req.room_ids.len() != req.room_ids.iter().collect::<HashSet<_>>().len() {
+        return bad("duplicate room IDs");
+    }
+    for room_id in &req.room_ids {
+        ntui(room_id)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use pb::proto::hypercards::Drawing;
+    use tokio_stream::StreamExt;
+
+    use super::*;
+    use crate::testing::{drawing, recv, send, send_with, user};
+
+    #[test]
+    fn rejects_malformed_events() {
+        assert!(validate_send_event(&send(&["living-room"])).is_ok());
+
+        let d = drawing();
+        let short = Drawing { widths: vec![2; 2], ..d.clone() };
+        let invisible = Drawing { color: Color::Invisible.into(), ..d.clone() };
+        let far = Drawing { xs: vec![1., 2e9, 3.], ..d.clone() };
+        let nan = Drawing { ys: vec![1., f32::NAN, 3.], ..d.clone() };
+        let negative = Drawing { pressures: vec![2000, -1, 2000], ..d.clone() };
+        let huge = Drawing { widths: vec![2, 1_000_000, 2], ..d.clone() };
+        // What clients would paint differently from how it was drawn
+        let wide = Drawing { widths: vec![2, MAX_WIDTH + 1, 2], ..d.clone() };
+        let pressed = Drawing { pressures: vec![2000, MAX_PRESSURE + 1, 2000], ..d.clone() };
+        let widest = Drawing { widths: vec![2, MAX_WIDTH, 2], ..d.clone() };
+        validate_send_event(&send_with(event::Event::Drawing(widest), &["r"])).unwrap();
+        let n = MAX_POINTS + 1;
+        let long = Drawing {
+            xs: vec![1.; n],
+            ys: vec![1.; n],
+            pressures: vec![1; n],
+            widths: vec![1; n],
+            ..d
+        };
+        for e in [
+            event::Event::Drawing(short),
+            event::Event::Drawing(invisible),
+            event::Event::Drawing(far),
+            event::Event::Drawing(nan),
+            event::Event::Drawing(negative),
+            event::Event::Drawing(huge),
+            event::Event::Drawing(wide),
+            event::Event::Drawing(pressed),
+            event::Event::Drawing(long),
+            event::Event::UserJoinedTheRoom(true),
+        ] {
+            let s = validate_send_event(&send_with(e, &["living-room"])).unwrap_err();
+            assert_eq!(s.code(), tonic::Code::InvalidArgument);
+        }
+
+        assert!(validate_send_event(&send(&["a", "a"])).is_err());
+        assert!(validate_send_event(&send(&["a.b"])).is_err());
+        let many: Vec<_> = (0..=MAX_ROOMS_PER_EVENT).map(|i| i.to_string()).collect();
+        let many: Vec<_> = many.iter().map(String::as_str).collect();
+        assert!(validate_send_event(&send(&many)).is_err());
+    }
+
+    #[tokio::test]
+    async fn throttles_senders() {
+        use crate::{
+            rooms::Rooms,
+            server::random_id,
+            throttle::{self, Throttle},
+        };
+
+        let args = throttle::Args { user_events_per_sec: 1, room_events_per_sec: 0 };
+        let srv =
+            Server::new(random_id(), Rooms::default(), Throttle::new(args), Default::default());
+        // Rejected sends cost nothing
+        srv.moderation.lock("locked");
+        let s = srv.send_event(user("joe", send(&["locked"]))).await.unwrap_err();
+        assert_eq!(s.code(), tonic::Code::FailedPrecondition);
+        srv.send_event(user("joe", send(&["r"]))).await.unwrap();
+        let s = srv.send_event(user("joe", send(&["r"]))).await.unwrap_err();
+        assert_eq!(s.code(), tonic::Code::ResourceExhausted);
+        srv.send_event(user("jane", send(&["r"]))).await.unwrap();
+    }
+
+    /// Rooms whose history always seems to have room left when checked, as when others
+    /// publish in between checking and publishing.
+    #[derive(Default)]
+    struct Racy(crate::storage::Memory);
+
+    #[tonic::async_trait]
+    impl crate::storage::Storage for Racy {
+        async fn push_event(&self, event: &Event, max_history: u64) -> anyhow::Result<bool> {
+            self.0.push_event(event, max_history).await
+        }
+        async fn history_len(&self, _: &str) -> anyhow::Result<u64> {
+            Ok(0)
+        }
+        async fn events(&self, room_id: &str) -> anyhow::Result<Option<Vec<Event>>> {
+            self.0.events(room_id).await
+        }
+        async fn set_screen(&self, room_id: &str, png: Vec<u8>) -> anyhow::Result<()> {
+            self.0.set_screen(room_id, png).await
+        }
+        async fn screen(&self, room_id: &str) -> anyhow::Result<Option<Vec<u8>>> {
+            self.0.screen(room_id).await
+        }
+        async fn join(&self, room_id: &str, user_id: &str) -> anyhow::Result<u32> {
+            self.0.join(room_id, user_id).await
+        }
+        async fn leave(&self, room_id: &str, user_id: &str) -> anyhow::Result<()> {
+            self.0.leave(room_id, user_id).await
+        }
+        async fn members(&self, room_id: &str) -> anyhow::Result<Option<Vec<String>>> {
+            self.0.members(room_id).await
+        }
+        async fn rooms(&self) -> anyhow::Result<Vec<crate::storage::Stats>> {
+            self.0.rooms().await
+        }
+        async fn clear(&self, room_id: &str) -> anyhow::Result<()> {
+            self.0.clear(room_id).await
+        }
+        async fn rename(&self, room_id: &str, new: &str, max: u64) -> anyhow::Result<bool> {
+            self.0.rename(room_id, new, max).await
+        }
+        async fn remove(&self, room_id: &str) -> anyhow::Result<()> {
+            self.0.remove(room_id).await
+        }
+        async fn remove_idle(&self, room_id: &str, idle_since: i64) -> anyhow::Result<bool> {
+            self.0.remove_idle(room_id, idle_since).await
+        }
+    }
+
+    #[tokio::test]
+    async fn refunds_senders_beaten_to_full_rooms() {
+        use pb::proto::hypercards::RoomSettings;
+
+        use crate::{
+            lifecycle::Policy,
+            rooms::Rooms,
+            server::random_id,
+            throttle::{self, Throttle},
+        };
+
+        let policy = Policy::uniform(RoomSettings { max_history: 1, ..Default::default() });
+        let rooms = Rooms::new(Arc::new(Racy::default()), policy);
+        let args = throttle::Args { user_events_per_sec: 1, room_events_per_sec: 2 };
+        let srv = Server::new(random_id(), rooms, Throttle::new(args), Default::default());
+        srv.send_event(user("jane", send(&["r"]))).await.unwrap();
+
+        // Joe's event got past the checks, not into the room: it cost nothing
+        let s = srv.send_event(user("joe", send(&["r"]))).await.unwrap_err();
+        assert!(s.message().contains("is full"), "{}", s.message());
+        srv.send_event(user("joe", send(&["q"]))).await.unwrap();
+
+        // Jim's got into one room: it costs him, not the other room
+        let s = srv.send_event(user("jim", send(&["p", "r"]))).await.unwrap_err();
+        assert!(s.message().contains("is full"), "{}", s.message());
+        assert_eq!(srv.rooms.drawings("p").await.unwrap().unwrap().len(), 1);
+        let s = srv.send_event(user("jim", send(&["o"]))).await.unwrap_err();
+        assert!(s.message().starts_with("you are"), "{}", s.message());
+        let s = srv.send_event(user("ann", send(&["r"]))).await.unwrap_err();
+        assert!(s.message().contains("is full"), "{}", s.message());
+    }
+
+    #[tokio::test]
+    async fn forwards_events_to_others_only() {
+        let srv = Server::default();
+        let rep = srv.recv_events(user("joe", recv("r"))).await;
+        let mut joe = rep.unwrap().into_inner();
+        let count = joe.next().await.unwrap().unwrap();
+        assert_eq!(count.event, Some(event::Event::UsersInTheRoom(1)));
+
+        assert!(srv.recv_events(Request::new(recv("r"))).await.is_err());
+
+        srv.send_event(user("joe", send(&["r"]))).await.unwrap();
+        srv.send_event(user("jane", send(&["r"]))).await.unwrap();
+        let got = joe.next().await.unwrap().unwrap();
+        assert_eq!(got.by_user_id, "jane");
+        assert_eq!(got.in_room_id, "r");
+        assert_eq!(got.event, Some(event::Event::Drawing(drawing())));
+        assert_eq!(srv.rooms.drawings("r").await.unwrap().unwrap().len(), 2);
+
+        drop(joe);
+        tokio::task::yield_now().await; // Leaving happens in the background
+        let rep = srv.list_rooms(user("joe", ListRoomsReq {})).await.unwrap().into_inner();
+        assert_eq!(rep.events[0].event, Some(event::Event::UsersInTheRoom(0)));
+        assert_eq!((rep.rooms[0].room_id.as_str(), rep.rooms[0].history_len), ("r", 2));
+    }
+
+    #[tokio::test]
+    async fn forwards_only_what_clients_support() {
+        let srv = Server::default();
+        let capabilities =
+            Capabilities { event_kinds: vec!["drawing".into()], ..Default::default() };
+        let req = RecvEventsReq { capabilities: Some(capabilities), ..recv("r") };
+        let rep = srv.recv_events(user("joe", req)).await.unwrap();
+        assert_eq!(Capabilities::from_header(rep.metadata()), Some(Capabilities::ours()));
+        let mut joe = rep.into_inner();
+
+        let _jane = srv.recv_events(user("jane", recv("r"))).await.unwrap();
+        srv.send_event(user("jane", send(&["r"]))).await.unwrap();
+        // Neither the count nor Jane joining
+        let got = joe.next().await.unwrap().unwrap();
+        assert_eq!(got.event, Some(event::Event::Drawing(drawing())));
+    }
+}